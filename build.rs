@@ -0,0 +1,43 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+#[path = "src/shaders/compile.rs"]
+mod compile;
+
+const SHADERS_DIR: &str = "src/shaders";
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    println!("cargo:rerun-if-changed={}", SHADERS_DIR);
+
+    let mut paths: Vec<_> = fs::read_dir(SHADERS_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| compile::is_shader(path))
+        .collect();
+    paths.sort();
+
+    let mut failed = false;
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        match compile::compile(&path) {
+            Ok(code) => {
+                let name = path.file_name().unwrap().to_string_lossy();
+                let bytes: Vec<u8> = code.iter().flat_map(|w| w.to_le_bytes()).collect();
+                fs::write(out_dir.join(format!("{}.spv", name)), bytes).unwrap();
+            }
+            Err(e) => {
+                eprintln!("{}\n", e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
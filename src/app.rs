@@ -1,26 +1,139 @@
+use std::path::Path;
 use std::time::Duration;
 
-use winit::event::{Event, WindowEvent};
+use cgmath::Point3;
+use winit::event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 
 use crate::game::Input;
-use crate::gfx::{events, Vulkan, Window};
+use crate::gfx::renderers::{Block, Clouds, Particles, Selected, Sky};
+use crate::gfx::{events, Camera, Fog, FrameLimiter, Overlay, Result, Stage, Vulkan, Window};
+use crate::settings::{Settings, SETTINGS_FILE};
+#[cfg(debug_assertions)]
+use crate::shaders;
+use crate::world::{self, Breaking, ParticleSystem, World};
+
+/// Simulated time before a headless screenshot, so particles are already spread out.
+const HEADLESS_STEPS: u32 = 60;
+const HEADLESS_STEP: Duration = Duration::from_millis(50);
+
+/// How far away blocks can be targeted, in blocks.
+const REACH: f32 = 5.0;
 
 pub struct App {
+    // Renderers must drop before 'vulkan'
+    block: Block,
+    sky: Sky,
+    clouds: Clouds,
+    particles: Particles,
+    #[cfg(debug_assertions)]
+    shaders: shaders::Watcher,
+
     vulkan: Vulkan,
+    settings: Settings,
+    limiter: FrameLimiter,
+    overlay: Overlay,
+    world: World,
+    particle_system: ParticleSystem,
+    breaking: Breaking,
+    /// Whether the button breaking blocks is held.
+    attacking: bool,
+    camera: Camera,
     window: Option<Window>,
     event_loop: Option<EventLoop<()>>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(settings: &Settings) -> Result<Self> {
         let event_loop = EventLoop::new();
+        let vulkan = Vulkan::new(&event_loop, settings)?;
+        let world = World::demo();
+
+        let mut block = Block::new(&vulkan)?;
+        block.set_lod_distance(settings.lod_distance);
+        block.set_render_distance(Some(settings.render_distance));
+        block.set_leaves(&vulkan, &world, settings.leaves);
+        block.set_shadows(&vulkan, settings.shadows)?;
+        block.load_world(&vulkan, &world);
+        let sky = Sky::new(&vulkan)?;
+        let mut clouds = Clouds::new(&vulkan)?;
+        clouds.set_mode(&vulkan, settings.clouds);
+        let particles = Particles::new(&vulkan)?;
+        let mut particle_system = ParticleSystem::new();
+        particle_system.add_torches(&world);
+        particle_system.set_rain(settings.rain);
+
+        Ok(Self {
+            block,
+            sky,
+            clouds,
+            particles,
+            #[cfg(debug_assertions)]
+            shaders: shaders::Watcher::new(),
 
-        Self {
-            vulkan: Vulkan::new(&event_loop),
+            vulkan,
+            settings: settings.clone(),
+            limiter: FrameLimiter::new(settings.max_fps),
+            overlay: Overlay::new(),
+            world,
+            particle_system,
+            breaking: Breaking::new(),
+            attacking: false,
+            camera: demo_camera(),
             window: Some(Window::new()),
             event_loop: Some(event_loop),
+        })
+    }
+
+    /// Renders a single frame without a window and writes it to `path` as a PNG.
+    pub fn screenshot_headless(
+        settings: &Settings,
+        path: impl AsRef<Path>,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let mut vulkan = Vulkan::headless(width, height, settings)?;
+        let world = World::demo();
+        let camera = demo_camera();
+
+        let mut block = Block::new(&vulkan)?;
+        block.set_lod_distance(settings.lod_distance);
+        block.set_leaves(&vulkan, &world, settings.leaves);
+        block.set_shadows(&vulkan, settings.shadows)?;
+        block.load_world(&vulkan, &world);
+        block.update_lod(&vulkan, &world, camera.position);
+        block.set_sky_light(world.time().sky_light());
+        block.set_render_distance(Some(settings.render_distance));
+        let fog = fog(&world, &camera, settings);
+        block.set_fog(fog);
+        let mut sky = Sky::new(&vulkan)?;
+        let mut clouds = Clouds::new(&vulkan)?;
+        clouds.set_mode(&vulkan, settings.clouds);
+        let mut particles = Particles::new(&vulkan)?;
+        let mut particle_system = ParticleSystem::new();
+        particle_system.add_torches(&world);
+        particle_system.set_rain(settings.rain);
+        // Let smoke rise and rain fall for a moment rather than showing them just spawned
+        for _ in 0..HEADLESS_STEPS {
+            particle_system.update(&world, camera.position, HEADLESS_STEP);
         }
+
+        let aspect = width as f32 / height as f32;
+        let view_projection = camera.view_projection(aspect);
+        let eye = camera.position;
+        let time = world.time();
+        vulkan.draw_stages(|stage, command_buffer, frame| match stage {
+            Stage::Prepare => block.draw_shadows(command_buffer, frame, &camera, aspect, time),
+            Stage::Main => {
+                sky.draw(command_buffer, frame, &camera, aspect, time, fog);
+                clouds.draw(command_buffer, frame, view_projection, eye, time, fog);
+                block.draw(command_buffer, frame, view_projection, eye);
+                let system = &particle_system;
+                particles.draw(command_buffer, frame, &camera, aspect, system, time, fog);
+            }
+        })?;
+        vulkan.save_png(path);
+        Ok(())
     }
 
     pub fn run(mut self) -> ! {
@@ -39,8 +152,36 @@ impl App {
                 Event::WindowEvent {
                     event: WindowEvent::Resized(_),
                     ..
+                } => self.vulkan.mark_stale(),
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::F2),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => self.vulkan.screenshot(),
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::F5),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
                 } => {
-                    // self.vulkan.mark_stale(); TODO resize
+                    if let Err(e) = self.apply_settings(self.settings.reload(SETTINGS_FILE)) {
+                        log::error!("{}", e);
+                    }
                 }
                 // Window Input events
                 Event::WindowEvent { event, .. } => {
@@ -60,7 +201,10 @@ impl App {
                         self.update(time, inputs);
                     });
 
-                    self.render();
+                    if let Err(e) = self.render() {
+                        log::error!("{}", e);
+                        *control_flow = ControlFlow::Exit;
+                    }
                 }
                 // ...rest
                 _ => (),
@@ -68,11 +212,121 @@ impl App {
         });
     }
 
-    fn update(&self, time: Duration, inputs: &[Input]) {
-        println!("time: {:?}, update: {:?}", time, inputs);
+    /// Applies settings that can change while running, e.g. after editing the settings file.
+    /// Shadows keep their previous quality if the new one fails to apply.
+    fn apply_settings(&mut self, settings: Settings) -> Result<()> {
+        self.vulkan.set_present_mode(settings.vsync);
+        self.vulkan.set_msaa(settings.msaa);
+        self.vulkan.set_render_scale(settings.render_scale);
+        self.limiter.set_max_fps(settings.max_fps);
+        self.block.set_lod_distance(settings.lod_distance);
+        self.block
+            .set_render_distance(Some(settings.render_distance));
+        self.block
+            .set_leaves(&self.vulkan, &self.world, settings.leaves);
+        self.clouds.set_mode(&self.vulkan, settings.clouds);
+        self.particle_system.set_rain(settings.rain);
+        let shadows = self.block.set_shadows(&self.vulkan, settings.shadows);
+        self.settings = settings;
+        shadows
     }
 
-    fn render(&mut self) {
-        // self.vulkan.prepare_render();
+    fn update(&mut self, time: Duration, inputs: &[Input]) {
+        log::trace!("time: {:?}, update: {:?}", time, inputs);
+        self.world.tick(time);
+
+        for input in inputs {
+            if let Input::MouseButton {
+                button: MouseButton::Left,
+                state,
+            } = input
+            {
+                self.attacking = *state == ElementState::Pressed;
+            }
+        }
+        let eye = self.camera.position;
+        let direction = self.camera.direction();
+        let mut target = world::raycast(&self.world, eye, direction, REACH);
+        if let Some(hit) = self.breaking.update(target, self.attacking, time) {
+            self.world.set_block(hit.pos, world::Block::Air);
+            self.particle_system.break_block(hit.pos, hit.block);
+            self.block.update_block(&self.vulkan, &self.world, hit.pos);
+            // Whatever was behind it is targeted now
+            target = world::raycast(&self.world, eye, direction, REACH);
+        }
+        let progress = self.breaking.progress();
+        self.block.set_selected(target.map(|hit| {
+            Selected {
+                pos: hit.pos,
+                block: hit.block,
+                progress: progress
+                    .filter(|&(pos, _)| pos == hit.pos)
+                    .map_or(0.0, |(_, progress)| progress),
+            }
+        }));
+
+        self.particle_system.update(&self.world, eye, time);
     }
+
+    fn render(&mut self) -> Result<()> {
+        #[cfg(debug_assertions)]
+        for (name, code) in self.shaders.poll() {
+            if self.block.reload_shader(&self.vulkan, &name, &code)
+                || self.sky.reload_shader(&self.vulkan, &name, &code)
+                || self.clouds.reload_shader(&self.vulkan, &name, &code)
+                || self.particles.reload_shader(&self.vulkan, &name, &code)
+            {
+                log::info!("reloaded shader {}", name);
+            }
+        }
+
+        let extent = self.vulkan.extent();
+        let aspect = extent.width as f32 / extent.height as f32;
+        let view_projection = self.camera.view_projection(aspect);
+
+        self.limiter.wait();
+        self.block.update_surface(&self.vulkan);
+        self.sky.update_surface(&self.vulkan);
+        self.clouds.update_surface(&self.vulkan);
+        self.particles.update_surface(&self.vulkan);
+        self.block.set_sky_light(self.world.time().sky_light());
+        let fog = fog(&self.world, &self.camera, &self.settings);
+        self.block.set_fog(fog);
+        self.block
+            .update_lod(&self.vulkan, &self.world, self.camera.position);
+
+        let block = &mut self.block;
+        let sky = &mut self.sky;
+        let clouds = &mut self.clouds;
+        let particles = &mut self.particles;
+        let particle_system = &self.particle_system;
+        let camera = &self.camera;
+        let time = self.world.time();
+        let eye = camera.position;
+        self.vulkan
+            .draw_stages(|stage, command_buffer, frame| match stage {
+                Stage::Prepare => block.draw_shadows(command_buffer, frame, camera, aspect, time),
+                Stage::Main => {
+                    sky.draw(command_buffer, frame, camera, aspect, time, fog);
+                    clouds.draw(command_buffer, frame, view_projection, eye, time, fog);
+                    block.draw(command_buffer, frame, view_projection, eye);
+                    let system = particle_system;
+                    particles.draw(command_buffer, frame, camera, aspect, system, time, fog);
+                }
+            })?;
+
+        self.overlay.frame(&self.vulkan, &[&self.block.stats()]);
+        Ok(())
+    }
+}
+
+/// Fog of the fluid the camera is in, or distance fog out in the open.
+fn fog(world: &World, camera: &Camera, settings: &Settings) -> Fog {
+    let eye = camera.position.map(|c| c.floor() as i32);
+    Fog::submerged(world.block(eye))
+        .unwrap_or_else(|| Fog::distance(Sky::horizon(world.time()), settings.render_distance))
+}
+
+fn demo_camera() -> Camera {
+    Camera::look_at(Point3::new(-24.0, 20.0, 24.0), Point3::new(0.0, 6.0, 0.0))
 }
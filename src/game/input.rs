@@ -4,6 +4,8 @@
 
 use winit::event::{ElementState, MouseButton, VirtualKeyCode};
 
+// Only logged so far, apart from mouse buttons
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum Input {
     Char(char),
@@ -19,6 +21,8 @@ pub enum Input {
     MouseDelta(i32, i32),
 }
 
+// Movement state for when the game reads input beyond logging
+#[allow(dead_code)]
 pub struct Controls {
     // keys
     pub forward: bool, // W
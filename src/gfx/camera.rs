@@ -0,0 +1,64 @@
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Rad, Vector3};
+
+/// Converts OpenGL clip space to Vulkan's, flipping Y and mapping depth from -1..1 to 0..1.
+#[rustfmt::skip]
+pub const VULKAN_CLIP: Matrix4<f32> = Matrix4::new(
+    1.0,  0.0, 0.0, 0.0,
+    0.0, -1.0, 0.0, 0.0,
+    0.0,  0.0, 0.5, 0.0,
+    0.0,  0.0, 0.5, 1.0,
+);
+
+/// A perspective camera, yaw 0 looks towards -Z and positive pitch looks up.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    pub fov: Rad<f32>,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn new(position: Point3<f32>) -> Self {
+        Self {
+            position,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            fov: Deg(70.0).into(),
+            near: 0.1,
+            far: 500.0,
+        }
+    }
+
+    /// A camera at `position` facing `target`.
+    pub fn look_at(position: Point3<f32>, target: Point3<f32>) -> Self {
+        let dir = (target - position).normalize();
+
+        Self {
+            yaw: Rad(dir.x.atan2(-dir.z)),
+            pitch: Rad(dir.y.asin()),
+            ..Self::new(position)
+        }
+    }
+
+    pub fn direction(&self) -> Vector3<f32> {
+        let (yaw_sin, yaw_cos) = self.yaw.0.sin_cos();
+        let (pitch_sin, pitch_cos) = self.pitch.0.sin_cos();
+
+        Vector3::new(yaw_sin * pitch_cos, pitch_sin, -yaw_cos * pitch_cos)
+    }
+
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.position, self.direction(), Vector3::unit_y())
+    }
+
+    pub fn projection(&self, aspect: f32) -> Matrix4<f32> {
+        VULKAN_CLIP * cgmath::perspective(self.fov, aspect, self.near, self.far)
+    }
+
+    pub fn view_projection(&self, aspect: f32) -> Matrix4<f32> {
+        self.projection(aspect) * self.view()
+    }
+}
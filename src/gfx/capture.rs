@@ -0,0 +1,168 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ash::vk;
+
+pub const SCREENSHOTS_DIR: &str = "screenshots";
+
+/// Writes tightly packed RGBA8 texels to a PNG file.
+pub fn write_png(path: impl AsRef<Path>, width: u32, height: u32, rgba: &[u8]) {
+    let path = path.as_ref();
+    let file = File::create(path)
+        .unwrap_or_else(|e| panic!("failed to create '{}': {}", path.display(), e));
+
+    encode_png(file, width, height, rgba)
+        .unwrap_or_else(|e| panic!("failed to write '{}': {}", path.display(), e));
+}
+
+fn encode_png(file: File, width: u32, height: u32, rgba: &[u8]) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+}
+
+/// Whether `to_rgba8` can convert texels of `format`.
+pub fn is_supported(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::A8B8G8R8_UNORM_PACK32
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::B8G8R8A8_SRGB
+    )
+}
+
+/// Converts texels copied from an image of `format` to opaque RGBA8 in place.
+///
+/// For 8 bit formats the bytes already hold what the display shows: `_SRGB` formats get encoded
+/// by the hardware on write and `_UNORM` surfaces are presented as is, so only the channel order
+/// needs fixing.
+pub fn to_rgba8(format: vk::Format, texels: &mut [u8]) {
+    assert!(is_supported(format), "can't convert {:?} texels", format);
+
+    let bgra = matches!(
+        format,
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB
+    );
+    for texel in texels.chunks_mut(4) {
+        if bgra {
+            texel.swap(0, 2);
+        }
+        // Swapchains are composited opaque, alpha is whatever the shaders left behind
+        texel[3] = 255;
+    }
+}
+
+/// Converts and writes texels to a new timestamped PNG in `SCREENSHOTS_DIR` on another thread.
+/// Failures are logged, a screenshot isn't worth crashing for.
+pub fn save_screenshot(
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    mut texels: Vec<u8>,
+) -> JoinHandle<()> {
+    // Reserve the file up front so quick successive screenshots get distinct names
+    let reserved = create_screenshot_file(Path::new(SCREENSHOTS_DIR), SystemTime::now());
+
+    thread::spawn(move || match reserved {
+        Ok((file, path)) => {
+            to_rgba8(format, &mut texels);
+            match encode_png(file, width, height, &texels) {
+                Ok(()) => log::info!("saved screenshot {}", path.display()),
+                Err(e) => log::error!("failed to write screenshot '{}': {}", path.display(), e),
+            }
+        }
+        Err(e) => log::error!("failed to save screenshot: {}", e),
+    })
+}
+
+fn create_screenshot_file(dir: &Path, time: SystemTime) -> io::Result<(File, PathBuf)> {
+    fs::create_dir_all(dir)?;
+
+    let name = timestamp(time);
+    for attempt in 0.. {
+        let path = match attempt {
+            0 => dir.join(format!("{}.png", name)),
+            n => dir.join(format!("{}_{}.png", name, n)),
+        };
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// UTC time formatted as `2021-03-14_15.09.26`.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}.{:02}.{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Gregorian date of a day since the Unix epoch, after Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn swizzles_bgra_and_drops_alpha() {
+        let mut texels = vec![1, 2, 3, 0, 4, 5, 6, 128];
+        to_rgba8(vk::Format::B8G8R8A8_SRGB, &mut texels);
+        assert_eq!(texels, [3, 2, 1, 255, 6, 5, 4, 255]);
+
+        let mut texels = vec![1, 2, 3, 0];
+        to_rgba8(vk::Format::R8G8B8A8_UNORM, &mut texels);
+        assert_eq!(texels, [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01_00.00.00");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_615_734_566)),
+            "2021-03-14_15.09.26"
+        );
+        // Leap day
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29_00.00.00"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+use ash::vk;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Reasons graphics can't start or continue, worded for players.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The Vulkan library couldn't be loaded.
+    Loading(String),
+    Window(String),
+    MissingLayer(String),
+    MissingExtension(String),
+    /// No GPU fulfills our requirements, explained per device.
+    NoSuitableDevice(String),
+    SurfaceLost,
+    DeviceLost,
+    OutOfMemory {
+        device: bool,
+    },
+    InvalidShader(String),
+    InvalidModel(String),
+    /// Any other failed Vulkan call, while doing `context`.
+    Vulkan {
+        context: &'static str,
+        result: vk::Result,
+    },
+}
+
+impl Error {
+    /// Maps the result of a failed Vulkan call made while doing `context`.
+    pub fn vulkan(context: &'static str) -> impl Fn(vk::Result) -> Self {
+        move |result| match result {
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY => Error::OutOfMemory { device: false },
+            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => Error::OutOfMemory { device: true },
+            vk::Result::ERROR_SURFACE_LOST_KHR => Error::SurfaceLost,
+            vk::Result::ERROR_DEVICE_LOST => Error::DeviceLost,
+            result => Error::Vulkan { context, result },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Loading(e) => write!(
+                f,
+                "couldn't load Vulkan ({}), make sure a graphics driver with Vulkan support is \
+                 installed",
+                e
+            ),
+            Error::Window(e) => write!(f, "couldn't create a window: {}", e),
+            Error::MissingLayer(name) => write!(
+                f,
+                "Vulkan layer {} is not available, install the Vulkan SDK",
+                name
+            ),
+            Error::MissingExtension(name) => write!(
+                f,
+                "Vulkan extension {} is not supported, try updating your graphics driver",
+                name
+            ),
+            Error::NoSuitableDevice(reasons) => write!(f, "{}", reasons),
+            Error::SurfaceLost => write!(f, "lost the window surface"),
+            Error::DeviceLost => write!(
+                f,
+                "the GPU stopped responding, try updating your graphics driver"
+            ),
+            Error::OutOfMemory { device: true } => write!(f, "out of GPU memory"),
+            Error::OutOfMemory { device: false } => write!(f, "out of memory"),
+            Error::InvalidShader(e) => write!(f, "invalid shader: {}", e),
+            Error::InvalidModel(e) => write!(f, "invalid block model: {}", e),
+            Error::Vulkan { context, result } => {
+                write!(f, "Vulkan error while {}: {}", context, result)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,76 @@
+//! # Fog
+//!
+//! Fog fades blocks into a flat color with distance from the camera. Out in the open it reaches
+//! full density at the render distance, hiding where chunks end behind the horizon's color.
+//! With the camera in a fluid it turns dense and takes the fluid's tint, the sky included.
+
+use crate::world::{Block, CHUNK_SIZE};
+
+/// Fraction of the distance fog's end where it starts.
+const DISTANCE_FOG_START: f32 = 0.75;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fog {
+    /// Linear color things fade to.
+    pub color: [f32; 3],
+    /// Distance from the camera where fog starts and where it's fully opaque, in blocks.
+    pub start: f32,
+    pub end: f32,
+    /// Whether the sky disappears in fog too, as when looking out of water.
+    pub hides_sky: bool,
+}
+
+impl Fog {
+    /// No fog at all, it ends before it starts.
+    pub const NONE: Fog = Fog {
+        color: [0.0; 3],
+        start: 0.0,
+        end: 0.0,
+        hides_sky: false,
+    };
+
+    /// Fog in the open, ending at `render_distance` chunks.
+    pub fn distance(color: [f32; 3], render_distance: u32) -> Self {
+        let end = (render_distance * CHUNK_SIZE as u32) as f32;
+
+        Self {
+            color,
+            start: end * DISTANCE_FOG_START,
+            end,
+            hides_sky: false,
+        }
+    }
+
+    /// Fog of a camera inside `block`, `None` unless it's a fluid.
+    pub fn submerged(block: Block) -> Option<Self> {
+        let (color, end) = match block {
+            Block::Water => ([0.02, 0.08, 0.25], 12.0),
+            Block::Lava => ([0.6, 0.1, 0.0], 2.0),
+            _ => return None,
+        };
+
+        Some(Self {
+            color,
+            start: 0.0,
+            end,
+            hides_sky: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fluids_are_denser_than_air() {
+        let distance = Fog::distance([1.0; 3], 4);
+        assert_eq!((distance.start, distance.end), (48.0, 64.0));
+
+        assert_eq!(Fog::submerged(Block::Air), None);
+        let water = Fog::submerged(Block::Water).unwrap();
+        let lava = Fog::submerged(Block::Lava).unwrap();
+        assert!(lava.end < water.end && water.end < distance.start);
+        assert!(water.hides_sky);
+    }
+}
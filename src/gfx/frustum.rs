@@ -0,0 +1,107 @@
+//! # Frustum
+//!
+//! View frustum culling. Planes are extracted from a view-projection matrix in Vulkan clip space,
+//! where visible points satisfy `-w <= x <= w`, `-w <= y <= w` and `0 <= z <= w`.
+
+use cgmath::{Matrix, Matrix4, Point3, Vector4};
+
+/// An axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+}
+
+/// The six planes bounding what a camera sees. Each plane is `(a, b, c, d)` with points inside
+/// satisfying `a*x + b*y + c*z + d >= 0`.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(view_projection: Matrix4<f32>) -> Self {
+        let m = view_projection;
+        let (x, y, z, w) = (m.row(0), m.row(1), m.row(2), m.row(3));
+
+        Self {
+            planes: [
+                w + x, // Left
+                w - x, // Right
+                w + y, // Top, Vulkan's Y points down
+                w - y, // Bottom
+                z,     // Near
+                w - z, // Far
+            ],
+        }
+    }
+
+    /// Whether any part of `aabb` may be visible. Boxes near frustum corners can pass without
+    /// being visible, which is fine for culling.
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let pick = |positive: bool, min: f32, max: f32| if positive { max } else { min };
+            let corner = Point3::new(
+                pick(plane.x >= 0.0, aabb.min.x, aabb.max.x),
+                pick(plane.y >= 0.0, aabb.min.y, aabb.max.y),
+                pick(plane.z >= 0.0, aabb.min.z, aabb.max.z),
+            );
+            plane.x * corner.x + plane.y * corner.y + plane.z * corner.z + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::Camera;
+    use cgmath::SquareMatrix;
+
+    fn unit_box(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::new(Point3::new(x, y, z), Point3::new(x + 1.0, y + 1.0, z + 1.0))
+    }
+
+    #[test]
+    fn identity_is_the_clip_volume() {
+        let frustum = Frustum::from_matrix(Matrix4::identity());
+
+        // Clip space spans -1..1 in X and Y and 0..1 in Z
+        assert!(frustum.intersects(&Aabb::new(
+            Point3::new(-0.5, -0.5, 0.25),
+            Point3::new(0.5, 0.5, 0.75),
+        )));
+        assert!(frustum.intersects(&unit_box(0.9, 0.9, 0.9)));
+        assert!(!frustum.intersects(&unit_box(1.5, 0.0, 0.0)));
+        assert!(!frustum.intersects(&unit_box(0.0, -3.0, 0.0)));
+        assert!(!frustum.intersects(&unit_box(0.0, 0.0, -1.5)));
+        assert!(!frustum.intersects(&unit_box(0.0, 0.0, 1.5)));
+    }
+
+    #[test]
+    fn culls_around_camera() {
+        // At the origin looking towards -Z with a 70° vertical field of view
+        let camera = Camera::new(Point3::new(0.0, 0.0, 0.0));
+        let frustum = Frustum::from_matrix(camera.view_projection(1.0));
+
+        assert!(frustum.intersects(&unit_box(0.0, 0.0, -10.0)));
+        // Straddling the camera
+        assert!(frustum.intersects(&unit_box(-0.5, -0.5, -0.5)));
+
+        // Behind
+        assert!(!frustum.intersects(&unit_box(0.0, 0.0, 5.0)));
+        // Too far to the sides, tan(35°) * 10 is about 7
+        assert!(!frustum.intersects(&unit_box(9.0, 0.0, -10.0)));
+        assert!(!frustum.intersects(&unit_box(-10.0, 0.0, -10.0)));
+        assert!(!frustum.intersects(&unit_box(0.0, 9.0, -10.0)));
+        assert!(!frustum.intersects(&unit_box(0.0, -10.0, -10.0)));
+        // Beyond the far plane
+        assert!(!frustum.intersects(&unit_box(0.0, 0.0, -camera.far - 2.0)));
+    }
+}
@@ -0,0 +1,251 @@
+//! # Golden images
+//!
+//! Renders fixed scenes offscreen and compares them against reference PNGs in `tests/golden/`
+//! with a perceptual tolerance. On failure the rendered image and a diff highlighting differing
+//! pixels in red are written to `target/golden/`. After intended visual changes, run the tests
+//! with `GOLDEN_BLESS=1` to rewrite the references and review them like any other change.
+//!
+//! Scenes need a Vulkan implementation, a CPU one like lavapipe is enough, so they're ignored by
+//! default and run with `cargo test -- --ignored`, as CI does on lavapipe. Without a device they
+//! fail rather than pass without rendering. A scene missing its reference fails too, writing
+//! what it rendered to `target/golden/` to review and copy to `tests/golden/`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cgmath::Point3;
+
+use super::capture;
+use super::renderers::Block;
+use super::textures::decode_png;
+use super::{Camera, Stage, Vulkan};
+use crate::settings::Settings;
+use crate::world::{self, World};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+
+/// Perceived color difference below which pixels count as equal, 0 to 1.
+const PIXEL_THRESHOLD: f32 = 0.1;
+/// Fraction of pixels allowed to differ, absorbs rasterization differences between drivers.
+const MAX_DIFFERING: f32 = 0.005;
+/// Largest possible YIQ delta, between black and white.
+const MAX_DELTA: f32 = 35215.0;
+
+struct Comparison {
+    differing: usize,
+    diff: Vec<u8>,
+}
+
+/// Compares two RGBA8 images of the same size pixel by pixel.
+fn compare(expected: &[u8], actual: &[u8]) -> Comparison {
+    assert_eq!(expected.len(), actual.len(), "image sizes differ");
+
+    let mut differing = 0;
+    let mut diff = Vec::with_capacity(actual.len());
+
+    for (e, a) in expected.chunks(4).zip(actual.chunks(4)) {
+        if yiq_delta(e, a) > MAX_DELTA * PIXEL_THRESHOLD * PIXEL_THRESHOLD {
+            differing += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // Faded grayscale of the expected image for context
+            let [y, _, _] = yiq(e);
+            let gray = (255.0 - (255.0 - y) * 0.1) as u8;
+            diff.extend_from_slice(&[gray, gray, gray, 255]);
+        }
+    }
+
+    Comparison { differing, diff }
+}
+
+/// Squared difference in YIQ space, weighted by how sensitive the eye is to each channel.
+fn yiq_delta(a: &[u8], b: &[u8]) -> f32 {
+    let [ya, ia, qa] = yiq(a);
+    let [yb, ib, qb] = yiq(b);
+
+    0.5053 * (ya - yb).powi(2) + 0.299 * (ia - ib).powi(2) + 0.1957 * (qa - qb).powi(2)
+}
+
+/// Converts an RGBA8 pixel blended over white to YIQ.
+fn yiq(rgba: &[u8]) -> [f32; 3] {
+    let alpha = f32::from(rgba[3]) / 255.0;
+    let blend = |c: u8| 255.0 + (f32::from(c) - 255.0) * alpha;
+    let (r, g, b) = (blend(rgba[0]), blend(rgba[1]), blend(rgba[2]));
+
+    [
+        r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_2,
+        r * 0.595_978 - g * 0.274_176_1 - b * 0.321_801_9,
+        r * 0.211_470_2 - g * 0.522_617_1 + b * 0.311_146_9,
+    ]
+}
+
+fn references_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+/// Renders the scene offscreen.
+fn render(world: &World, camera: &Camera) -> Vec<u8> {
+    let mut settings = Settings::default();
+    settings.validation.panic_on_error = true;
+
+    let mut vulkan = Vulkan::headless(WIDTH, HEIGHT, &settings)
+        .unwrap_or_else(|e| panic!("golden images need a Vulkan device, e.g. lavapipe: {}", e));
+    let mut block = Block::new(&vulkan).unwrap();
+    block.load_world(&vulkan, world);
+
+    let view_projection = camera.view_projection(WIDTH as f32 / HEIGHT as f32);
+    let eye = camera.position;
+    vulkan
+        .draw_stages(|stage, command_buffer, frame| {
+            if stage == Stage::Main {
+                block.draw(command_buffer, frame, view_projection, eye);
+            }
+        })
+        .unwrap();
+
+    vulkan.read_pixels()
+}
+
+/// Writes an image about the scene `name` to `target/golden/`, returning its path.
+fn write_output(name: &str, kind: &str, rgba: &[u8]) -> PathBuf {
+    fs::create_dir_all(output_dir()).unwrap();
+    let path = output_dir().join(format!("{}.{}.png", name, kind));
+    capture::write_png(&path, WIDTH, HEIGHT, rgba);
+    path
+}
+
+/// Renders the scene and compares it against the reference image `name`.
+fn check(name: &str, world: &World, camera: &Camera) {
+    let actual = render(world, camera);
+    let reference = references_dir().join(format!("{}.png", name));
+
+    if env::var_os("GOLDEN_BLESS").is_some() {
+        fs::create_dir_all(references_dir()).unwrap();
+        capture::write_png(&reference, WIDTH, HEIGHT, &actual);
+        return;
+    }
+
+    if !reference.exists() {
+        panic!(
+            "missing reference '{}', review '{}' or run with GOLDEN_BLESS=1 to create it",
+            reference.display(),
+            write_output(name, "actual", &actual).display(),
+        );
+    }
+
+    let (width, height, expected) = decode_png(&reference);
+    assert_eq!(
+        (width, height),
+        (WIDTH, HEIGHT),
+        "reference '{}' has the wrong size",
+        reference.display(),
+    );
+
+    let comparison = compare(&expected, &actual);
+    let allowed = ((WIDTH * HEIGHT) as f32 * MAX_DIFFERING) as usize;
+    if comparison.differing > allowed {
+        let actual_path = write_output(name, "actual", &actual);
+        let diff_path = write_output(name, "diff", &comparison.diff);
+        panic!(
+            "'{}' differs from its reference in {} pixels ({} allowed), see '{}' and '{}'",
+            name,
+            comparison.differing,
+            allowed,
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn single_block() {
+    let mut world = World::new();
+    world.set_block(Point3::new(0, 0, 0), world::Block::Grass);
+
+    let camera = Camera::look_at(Point3::new(2.2, 1.8, 2.6), Point3::new(0.5, 0.5, 0.5));
+    check("single_block", &world, &camera);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn chunk_with_ao() {
+    let mut world = World::new();
+    for x in 0..16 {
+        for z in 0..16 {
+            world.set_block(Point3::new(x, 0, z), world::Block::Stone);
+
+            // Steps, walls and a pit give inside and outside corners
+            let height = match (x, z) {
+                (_, 12..=15) => 1 + x / 4,
+                (12..=15, _) => 3,
+                (4..=7, 4..=7) => 0,
+                _ => 1,
+            };
+            for y in 1..=height {
+                world.set_block(Point3::new(x, y, z), world::Block::Dirt);
+            }
+            if (4..=7).contains(&x) && (4..=7).contains(&z) {
+                world.set_block(Point3::new(x, 0, z), world::Block::Air);
+            }
+        }
+    }
+    world.set_block(Point3::new(9, 2, 9), world::Block::Stone);
+
+    let camera = Camera::look_at(Point3::new(-4.0, 14.0, -4.0), Point3::new(8.0, 1.0, 8.0));
+    check("chunk_with_ao", &world, &camera);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn water_surface() {
+    let mut world = World::new();
+    for x in -8..8 {
+        for z in -8..8 {
+            let rim = x == -8 || x == 7 || z == -8 || z == 7;
+            world.set_block(Point3::new(x, 0, z), world::Block::Sand);
+            for y in 1..=2 {
+                let block = if rim {
+                    world::Block::Sand
+                } else {
+                    world::Block::Water
+                };
+                world.set_block(Point3::new(x, y, z), block);
+            }
+        }
+    }
+
+    let camera = Camera::look_at(Point3::new(-10.0, 9.0, 10.0), Point3::new(0.0, 2.0, 0.0));
+    check("water_surface", &world, &camera);
+}
+
+#[test]
+fn identical_images_match() {
+    let image: Vec<u8> = (0..64).map(|i| (i * 4) as u8).collect();
+    assert_eq!(compare(&image, &image).differing, 0);
+}
+
+#[test]
+fn imperceptible_differences_match() {
+    let expected = vec![100; 64];
+    let actual: Vec<u8> = expected.iter().map(|c| c + 2).collect();
+    assert_eq!(compare(&expected, &actual).differing, 0);
+}
+
+#[test]
+fn changed_pixels_are_marked() {
+    let expected = vec![255; 4 * 4];
+    let mut actual = expected.clone();
+    actual[4..8].copy_from_slice(&[0, 0, 0, 255]);
+
+    let comparison = compare(&expected, &actual);
+    assert_eq!(comparison.differing, 1);
+    assert_eq!(&comparison.diff[4..8], &[255, 0, 0, 255]);
+    assert_eq!(&comparison.diff[0..4], &[255, 255, 255, 255]);
+}
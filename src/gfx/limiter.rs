@@ -0,0 +1,74 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Caps the frame rate by sleeping until the next frame is due, for the `max_fps` setting.
+pub struct FrameLimiter {
+    frame_time: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(max_fps: Option<u32>) -> Self {
+        Self {
+            frame_time: frame_time(max_fps),
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.frame_time = frame_time(max_fps);
+    }
+
+    /// Sleeps until the next frame may start, returns right away without a cap.
+    pub fn wait(&mut self) {
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time,
+            None => return,
+        };
+
+        let now = Instant::now();
+        if let Some(remaining) = self.next_frame.checked_duration_since(now) {
+            thread::sleep(remaining);
+        }
+        self.next_frame = next_frame(self.next_frame, Instant::now(), frame_time);
+    }
+}
+
+fn frame_time(max_fps: Option<u32>) -> Option<Duration> {
+    max_fps
+        .filter(|&fps| fps > 0)
+        .map(|fps| Duration::from_secs(1) / fps)
+}
+
+/// When the frame after one due at `due` may start. Frames keep a steady pace, but a frame
+/// running late doesn't let the following ones catch up in a burst.
+fn next_frame(due: Instant, now: Instant, frame_time: Duration) -> Instant {
+    let next = due + frame_time;
+    if next < now {
+        now
+    } else {
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paces_frames_without_bursts() {
+        let start = Instant::now();
+        let frame_time = Duration::from_millis(10);
+
+        // On time
+        let due = start + frame_time;
+        assert_eq!(next_frame(due, due, frame_time), due + frame_time);
+
+        // A long frame restarts the pace instead of catching up
+        let late = due + frame_time * 5;
+        assert_eq!(next_frame(due, late, frame_time), late);
+
+        assert_eq!(super::frame_time(Some(0)), None);
+        assert_eq!(super::frame_time(Some(100)), Some(frame_time));
+    }
+}
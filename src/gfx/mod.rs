@@ -1,7 +1,28 @@
+mod camera;
+mod capture;
+mod error;
 pub mod events;
-mod renderers;
+mod fog;
+mod frustum;
+#[cfg(test)]
+mod golden;
+mod limiter;
+mod models;
+mod overlay;
+pub mod renderers;
+mod textures;
 mod vulkan;
 mod window;
 
-pub use vulkan::Vulkan;
+pub use camera::{Camera, VULKAN_CLIP};
+pub use error::{Error, Result};
+pub use fog::Fog;
+pub use frustum::{Aabb, Frustum};
+pub use limiter::FrameLimiter;
+pub use overlay::Overlay;
+pub use vulkan::{
+    GpuSelector, Msaa, PresentMode, Stage, Validation, Vulkan, MIN_RENDER_SCALE, VALIDATION_ENV,
+};
 pub use window::Window;
+
+pub const TITLE: &str = "Minecraft";
@@ -0,0 +1,564 @@
+//! # Models
+//!
+//! Block models made of cuboid elements, loaded from `assets/models/` in a format modeled on
+//! Minecraft's. A model may name a `parent` to inherit its elements and textures from, and faces
+//! refer to textures by name or through `#variables` that any model along the parent chain can
+//! set. Model and texture names are file stems, there are no namespaces.
+//!
+//! Every model is baked into quads for the four rotations around the Y axis block states can ask
+//! for. Models whose texture variables are never set, like `cube_all`, only serve as parents.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use cgmath::{Deg, Matrix3, Point3};
+use serde::Deserialize;
+
+use super::textures::TextureArray;
+use crate::world::{Block, Face, Model as BlockModel};
+
+pub const MODELS_DIR: &str = "assets/models";
+
+/// Rotations around the Y axis models get baked in, in degrees.
+const ROTATIONS: [u32; 4] = [0, 90, 180, 270];
+
+/// Parent chains longer than this are assumed to loop.
+const MAX_DEPTH: usize = 16;
+
+/// A quad in block coordinates, `0.0..1.0` across the block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BakedQuad {
+    pub positions: [[f32; 3]; 4],
+    pub uvs: [[f32; 2]; 4],
+    pub layer: u32,
+    /// Direction the quad faces, for shading.
+    pub face: Face,
+    /// The quad is hidden when the neighbour on this side is opaque.
+    pub cullface: Option<Face>,
+    pub shade: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BakedModel {
+    pub quads: Vec<BakedQuad>,
+}
+
+/// Baked models by name and rotation.
+pub struct BlockModels {
+    baked: HashMap<(String, u32), BakedModel>,
+}
+
+impl BlockModels {
+    /// Loads and bakes every `.json` in `dir`, using its file stem as the model name, and checks
+    /// that every block's model is among them.
+    pub fn load(dir: impl AsRef<Path>, textures: &TextureArray) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        let read_error = |path: &Path| {
+            let path = path.display().to_string();
+            move |e| format!("failed to read '{}': {}", path, e)
+        };
+
+        let mut sources = HashMap::new();
+        for entry in fs::read_dir(dir).map_err(read_error(dir))? {
+            let path = entry.map_err(read_error(dir))?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                let source = fs::read_to_string(&path).map_err(read_error(&path))?;
+                sources.insert(name, source);
+            }
+        }
+
+        let models = Self::parse(&sources, textures)
+            .and_then(|models| models.check_blocks().map(|()| models))
+            .map_err(|e| format!("{}: {}", dir.display(), e))?;
+        Ok(models)
+    }
+
+    /// Bakes models from their JSON sources by name.
+    pub fn parse(
+        sources: &HashMap<String, String>,
+        textures: &TextureArray,
+    ) -> Result<Self, String> {
+        let mut files = HashMap::new();
+        for (name, source) in sources {
+            let file: ModelFile =
+                serde_json::from_str(source).map_err(|e| format!("{}: {}", name, e))?;
+            files.insert(name.as_str(), file);
+        }
+
+        let mut baked = HashMap::new();
+        for &name in files.keys() {
+            let model = resolve(&files, name).map_err(|e| format!("{}: {}", name, e))?;
+            for &rotation in &ROTATIONS {
+                match bake(&model, rotation, textures).map_err(|e| format!("{}: {}", name, e))? {
+                    Some(quads) => baked.insert((name.to_string(), rotation), quads),
+                    None => {
+                        log::debug!("model {} has unset textures, only usable as a parent", name);
+                        break;
+                    }
+                };
+            }
+        }
+        Ok(Self { baked })
+    }
+
+    /// Fails if a block uses a model that's missing or only usable as a parent, so meshing can
+    /// rely on finding every block's model.
+    fn check_blocks(&self) -> Result<(), String> {
+        for block in Block::all() {
+            if let BlockModel::Elements { name, rotation } = block.model() {
+                if self.get(name, rotation).is_none() {
+                    return Err(format!("{:?} needs the missing model {}", block, name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The model `name` turned clockwise around the Y axis by `rotation` degrees.
+    pub fn get(&self, name: &str, rotation: u32) -> Option<&BakedModel> {
+        self.baked.get(&(name.to_string(), rotation % 360))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelFile {
+    parent: Option<String>,
+    #[serde(default)]
+    textures: HashMap<String, String>,
+    elements: Option<Vec<Element>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Element {
+    from: [f32; 3],
+    to: [f32; 3],
+    rotation: Option<ElementRotation>,
+    #[serde(default = "default_shade")]
+    shade: bool,
+    faces: HashMap<Direction, ElementFace>,
+}
+
+fn default_shade() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ElementRotation {
+    origin: [f32; 3],
+    axis: Axis,
+    /// Degrees, counter-clockwise looking down the axis.
+    angle: f32,
+    /// Scales the element so its faces keep spanning the block, e.g. for crosses.
+    #[serde(default)]
+    rescale: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ElementFace {
+    /// Texels of the texture, `[u0, v0, u1, v1]` in `0..16`. Defaults to the element's extent.
+    uv: Option<[f32; 4]>,
+    texture: String,
+    cullface: Option<Direction>,
+    /// Turns the texture clockwise by a multiple of 90 degrees.
+    #[serde(default)]
+    rotation: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    Down,
+    Up,
+    North,
+    South,
+    West,
+    East,
+}
+
+impl From<Direction> for Face {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Down => Face::Bottom,
+            Direction::Up => Face::Top,
+            Direction::North => Face::North,
+            Direction::South => Face::South,
+            Direction::West => Face::West,
+            Direction::East => Face::East,
+        }
+    }
+}
+
+/// A model with its parents applied.
+#[derive(Debug)]
+struct Model {
+    elements: Vec<Element>,
+    textures: HashMap<String, String>,
+}
+
+/// Applies the parent chain of `name`. Elements come from the closest model defining any,
+/// textures set closer to `name` win.
+fn resolve(files: &HashMap<&str, ModelFile>, name: &str) -> Result<Model, String> {
+    let mut model = Model {
+        elements: Vec::new(),
+        textures: HashMap::new(),
+    };
+    let mut elements = None;
+
+    let mut next = Some(name);
+    let mut depth = 0;
+    while let Some(name) = next {
+        depth += 1;
+        if depth > MAX_DEPTH {
+            return Err("parents form a loop".to_string());
+        }
+
+        let file = files
+            .get(name)
+            .ok_or_else(|| format!("missing parent '{}'", name))?;
+        for (variable, texture) in &file.textures {
+            model
+                .textures
+                .entry(variable.clone())
+                .or_insert_with(|| texture.clone());
+        }
+        if elements.is_none() {
+            elements = file.elements.clone();
+        }
+        next = file.parent.as_deref();
+    }
+
+    model.elements = elements.unwrap_or_default();
+    Ok(model)
+}
+
+/// Follows `#variable` references to a texture name, `None` if a variable isn't set.
+fn resolve_texture<'a>(model: &'a Model, texture: &'a str) -> Option<&'a str> {
+    let mut texture = texture;
+    for _ in 0..MAX_DEPTH {
+        match texture.strip_prefix('#') {
+            Some(variable) => texture = model.textures.get(variable)?,
+            None => return Some(texture),
+        }
+    }
+    None
+}
+
+/// Quads of `model` turned by `rotation` degrees, `None` if a texture variable isn't set.
+fn bake(
+    model: &Model,
+    rotation: u32,
+    textures: &TextureArray,
+) -> Result<Option<BakedModel>, String> {
+    if model.elements.is_empty() {
+        return Ok(None);
+    }
+
+    let mut baked = BakedModel::default();
+    for element in &model.elements {
+        for (&direction, face) in &element.faces {
+            let name = match resolve_texture(model, &face.texture) {
+                Some(name) => name,
+                None => return Ok(None),
+            };
+            let layer = textures
+                .texture(name)
+                .ok_or_else(|| format!("missing texture '{}'", name))?
+                .layer;
+
+            let mut quad = bake_face(element, direction.into(), face);
+            for position in &mut quad.positions {
+                *position = rotate_y(*position, rotation);
+            }
+            quad.face = rotate_face(quad.face, rotation);
+            quad.cullface = quad.cullface.map(|face| rotate_face(face, rotation));
+            quad.layer = layer;
+            baked.quads.push(quad);
+        }
+    }
+
+    // Stable order regardless of how faces were hashed
+    baked.quads.sort_by(|a, b| {
+        let key = |q: &BakedQuad| (Face::ALL.iter().position(|&f| f == q.face), q.positions);
+        key(a).partial_cmp(&key(b)).unwrap()
+    });
+    Ok(Some(baked))
+}
+
+/// The quad of one face of an element, in block coordinates without the model's rotation.
+fn bake_face(element: &Element, face: Face, element_face: &ElementFace) -> BakedQuad {
+    let (from, to) = (element.from, element.to);
+    let [u0, v0, u1, v1] = element_face
+        .uv
+        .unwrap_or_else(|| default_uv(face, from, to));
+
+    // Corners go right and down across the texture, as seen from outside
+    let (normal, right, down) = face_axes(face);
+    let coordinate = |axis: usize, s: f32, t: f32| {
+        let lerp = |positive: bool, f: f32| {
+            if positive {
+                from[axis] + (to[axis] - from[axis]) * f
+            } else {
+                to[axis] + (from[axis] - to[axis]) * f
+            }
+        };
+        if axis == normal.0 {
+            if normal.1 {
+                to[axis]
+            } else {
+                from[axis]
+            }
+        } else if axis == right.0 {
+            lerp(right.1, s)
+        } else {
+            lerp(down.1, t)
+        }
+    };
+
+    let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+    let texels = [(u0, v0), (u1, v0), (u1, v1), (u0, v1)];
+    let turns = (element_face.rotation / 90) as usize;
+
+    let mut quad = BakedQuad {
+        positions: [[0.0; 3]; 4],
+        uvs: [[0.0; 2]; 4],
+        layer: 0,
+        face,
+        cullface: element_face.cullface.map(Face::from),
+        shade: element.shade,
+    };
+    for (i, &(s, t)) in corners.iter().enumerate() {
+        let position = [
+            coordinate(0, s, t),
+            coordinate(1, s, t),
+            coordinate(2, s, t),
+        ];
+        let position = match &element.rotation {
+            Some(rotation) => rotate_element(position, rotation),
+            None => position,
+        };
+        quad.positions[i] = position.map(|c| c / 16.0);
+
+        let (u, v) = texels[(i + 4 - turns % 4) % 4];
+        quad.uvs[i] = [u / 16.0, v / 16.0];
+    }
+    quad
+}
+
+/// Axis and direction (true if positive) of a face's normal, and of the directions right and
+/// down across its texture as seen from outside. Matches the mesher's cube faces.
+fn face_axes(face: Face) -> ((usize, bool), (usize, bool), (usize, bool)) {
+    match face {
+        Face::Top => ((1, true), (0, true), (2, true)),
+        Face::Bottom => ((1, false), (0, true), (2, false)),
+        Face::North => ((2, false), (0, false), (1, false)),
+        Face::South => ((2, true), (0, true), (1, false)),
+        Face::East => ((0, true), (2, false), (1, false)),
+        Face::West => ((0, false), (2, true), (1, false)),
+    }
+}
+
+/// Texels covered by the element's extent on a face, like vanilla.
+fn default_uv(face: Face, from: [f32; 3], to: [f32; 3]) -> [f32; 4] {
+    match face {
+        Face::Top => [from[0], from[2], to[0], to[2]],
+        Face::Bottom => [from[0], 16.0 - to[2], to[0], 16.0 - from[2]],
+        Face::North => [16.0 - to[0], 16.0 - to[1], 16.0 - from[0], 16.0 - from[1]],
+        Face::South => [from[0], 16.0 - to[1], to[0], 16.0 - from[1]],
+        Face::East => [16.0 - to[2], 16.0 - to[1], 16.0 - from[2], 16.0 - from[1]],
+        Face::West => [from[2], 16.0 - to[1], to[2], 16.0 - from[1]],
+    }
+}
+
+fn rotate_element(position: [f32; 3], rotation: &ElementRotation) -> [f32; 3] {
+    let angle = Deg(rotation.angle);
+    let (matrix, axis) = match rotation.axis {
+        Axis::X => (Matrix3::from_angle_x(angle), 0),
+        Axis::Y => (Matrix3::from_angle_y(angle), 1),
+        Axis::Z => (Matrix3::from_angle_z(angle), 2),
+    };
+
+    let origin = Point3::from(rotation.origin);
+    let mut offset = matrix * (Point3::from(position) - origin);
+    if rotation.rescale {
+        let scale = 1.0 / rotation.angle.to_radians().cos();
+        for i in (0..3).filter(|&i| i != axis) {
+            offset[i] *= scale;
+        }
+    }
+    (origin + offset).into()
+}
+
+/// Turns a position in block coordinates clockwise around the block's center, seen from above.
+fn rotate_y(position: [f32; 3], degrees: u32) -> [f32; 3] {
+    let [mut x, y, mut z] = position;
+    for _ in 0..degrees / 90 {
+        // East turns to south
+        let turned = (1.0 - z, x);
+        x = turned.0;
+        z = turned.1;
+    }
+    [x, y, z]
+}
+
+fn rotate_face(face: Face, degrees: u32) -> Face {
+    let mut face = face;
+    for _ in 0..degrees / 90 {
+        face = match face {
+            Face::East => Face::South,
+            Face::South => Face::West,
+            Face::West => Face::North,
+            Face::North => Face::East,
+            vertical => vertical,
+        };
+    }
+    face
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::textures::TEXTURES_DIR;
+
+    fn sources(models: &[(&str, &str)]) -> HashMap<String, String> {
+        models
+            .iter()
+            .map(|&(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    }
+
+    const SLAB: &str = r##"{
+        "textures": { "side": "#all" },
+        "elements": [{
+            "from": [0, 0, 0],
+            "to": [16, 8, 16],
+            "faces": {
+                "down": { "texture": "#all", "cullface": "down" },
+                "up": { "texture": "#all" },
+                "east": { "texture": "#side", "cullface": "east" }
+            }
+        }]
+    }"##;
+
+    #[test]
+    fn inherits_elements_and_textures() {
+        let textures = TextureArray::load(TEXTURES_DIR);
+        let sources = sources(&[
+            ("slab", SLAB),
+            (
+                "sand_slab",
+                r#"{ "parent": "slab", "textures": { "all": "sand" } }"#,
+            ),
+        ]);
+        let models = BlockModels::parse(&sources, &textures).unwrap();
+
+        // The parent's textures are never set
+        assert!(models.get("slab", 0).is_none());
+
+        let slab = models.get("sand_slab", 0).unwrap();
+        let sand = textures.texture("sand").unwrap().layer;
+        assert_eq!(slab.quads.len(), 3);
+        assert!(slab.quads.iter().all(|q| q.layer == sand));
+
+        let top = &slab.quads[0];
+        assert_eq!(top.face, Face::Top);
+        assert_eq!(top.cullface, None);
+        assert!(top.positions.iter().all(|p| p[1] == 0.5));
+        assert_eq!(top.uvs, [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+
+        // Half height sides show the lower half of the texture
+        let east = slab.quads.iter().find(|q| q.face == Face::East).unwrap();
+        assert_eq!(east.uvs[0], [0.0, 0.5]);
+    }
+
+    #[test]
+    fn rotates_faces_and_cullfaces() {
+        let textures = TextureArray::load(TEXTURES_DIR);
+        let sources = sources(&[
+            ("slab", SLAB),
+            (
+                "sand_slab",
+                r#"{ "parent": "slab", "textures": { "all": "sand" } }"#,
+            ),
+        ]);
+        let models = BlockModels::parse(&sources, &textures).unwrap();
+
+        let side = |rotation| {
+            let model = models.get("sand_slab", rotation).unwrap();
+            let quad = model
+                .quads
+                .iter()
+                .find(|q| q.face != Face::Top && q.face != Face::Bottom);
+            let quad = quad.unwrap();
+            (quad.face, quad.cullface, quad.positions[0])
+        };
+        assert_eq!(side(0), (Face::East, Some(Face::East), [1.0, 0.5, 1.0]));
+        assert_eq!(side(90), (Face::South, Some(Face::South), [0.0, 0.5, 1.0]));
+        assert_eq!(side(180).0, Face::West);
+        assert_eq!(side(270).0, Face::North);
+        assert_eq!(models.get("sand_slab", 450), models.get("sand_slab", 90));
+    }
+
+    #[test]
+    fn rejects_broken_models() {
+        let textures = TextureArray::load(TEXTURES_DIR);
+        let parse = |models: &[(&str, &str)]| BlockModels::parse(&sources(models), &textures);
+
+        assert!(parse(&[("a", r#"{ "parent": "b" }"#)]).is_err());
+        assert!(parse(&[("a", r#"{ "parent": "a" }"#)]).is_err());
+        assert!(parse(&[("a", r#"{ "elements": 3 }"#)]).is_err());
+        let missing = r#"{ "textures": { "all": "nope" }, "parent": "slab" }"#;
+        assert!(parse(&[("slab", SLAB), ("a", missing)]).is_err());
+
+        // Parses, but blocks need models that aren't there or are parents only
+        let models = parse(&[("stone_slab", SLAB)]).unwrap();
+        assert!(models.check_blocks().is_err());
+    }
+
+    #[test]
+    fn rescales_rotated_elements() {
+        let element = |rescale| ElementRotation {
+            origin: [8.0, 8.0, 8.0],
+            axis: Axis::Y,
+            angle: 45.0,
+            rescale,
+        };
+
+        let corner = rotate_element([0.0, 0.0, 8.0], &element(false));
+        let length = |p: [f32; 3]| ((p[0] - 8.0).powi(2) + (p[2] - 8.0).powi(2)).sqrt();
+        assert!((length(corner) - 8.0).abs() < 1e-4);
+        let rescaled = rotate_element([0.0, 0.0, 8.0], &element(true));
+        assert!((length(rescaled) - 8.0 * 2f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn loads_block_models() {
+        let textures = TextureArray::load(TEXTURES_DIR);
+        let models = BlockModels::load(MODELS_DIR, &textures).unwrap();
+
+        for &name in &["stone_slab", "stone_stairs", "oak_fence_post", "torch"] {
+            for &rotation in &ROTATIONS {
+                let model = models.get(name, rotation);
+                assert!(
+                    model.is_some_and(|m| !m.quads.is_empty()),
+                    "{} {}",
+                    name,
+                    rotation
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use super::{Vulkan, TITLE};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Frame statistics, shown in the window title until there's an in-game debug overlay.
+pub struct Overlay {
+    frames: u32,
+    since: Instant,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self {
+            frames: 0,
+            since: Instant::now(),
+        }
+    }
+
+    /// Counts a drawn frame, updating the title about once a second with renderer `stats`.
+    pub fn frame(&mut self, vulkan: &Vulkan, stats: &[&dyn Display]) {
+        self.frames += 1;
+
+        let elapsed = self.since.elapsed();
+        if elapsed < UPDATE_INTERVAL {
+            return;
+        }
+
+        let fps = f64::from(self.frames) / elapsed.as_secs_f64();
+        let mut title = format!("{} | {:.0} fps", TITLE, fps);
+        if let Some(present_mode) = vulkan.present_mode() {
+            title += &format!(" | {}", present_mode);
+        }
+        for stat in stats {
+            title += &format!(" | {}", stat);
+        }
+        if let Some(window) = vulkan.window() {
+            window.set_title(&title);
+        }
+
+        self.frames = 0;
+        self.since = Instant::now();
+    }
+}
@@ -0,0 +1,637 @@
+//! # Mesher
+//!
+//! Turns chunks into textured quads with per-vertex ambient occlusion. Faces hidden by opaque
+//! neighbours, or by a neighbour of the same block like water next to water, are skipped.
+//!
+//! Blocks other than cubes are meshed from their model: plants are crosses of two quads, and
+//! blocks like stairs use the quads baked from their JSON model, each hidden by an opaque
+//! neighbour on the side its `cullface` names.
+//!
+//! Cutout blocks like leaves and plants get their own indices, drawn with transparent texels
+//! discarded. Fancy leaves keep the faces between neighbouring leaves so trees look full, fast
+//! leaves cull them.
+//!
+//! Translucent blocks like water and glass get their own indices, drawn blended after the
+//! opaque ones. Their quads keep their centers so they can be sorted back to front.
+//!
+//! Distant chunks get meshed at a level of detail, where each cell of 2×2×2, 4×4×4 or 8×8×8
+//! blocks becomes one large block. Neighbouring chunks at another level don't line up, so faces
+//! on borders to them are kept as seams that close the gaps.
+
+use std::fmt;
+use std::str::FromStr;
+
+use cgmath::{EuclideanSpace, MetricSpace, Point3};
+
+use crate::gfx::models::{BakedModel, BlockModels};
+use crate::gfx::textures::TextureArray;
+use crate::world::{Block, ChunkPos, Face, Model, World, CHUNK_SIZE};
+
+/// Brightness by number of occluding blocks around a vertex.
+const AO_CURVE: [f32; 4] = [1.0, 0.8, 0.6, 0.45];
+
+/// Coarsest level of detail, cells of 8×8×8 blocks.
+pub const MAX_LOD: u32 = 3;
+
+/// Faces of a chunk, in `Face::ALL` order, whose border faces are kept regardless of the
+/// neighbouring chunk because it's meshed at a different level of detail.
+pub type Seams = [bool; 6];
+
+/// Leaves quality, set by the `leaves` setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Leaves {
+    /// Faces between leaves are culled.
+    Fast,
+    /// Every leaf face is drawn, so trees look full through the holes.
+    #[default]
+    Fancy,
+}
+
+impl FromStr for Leaves {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Leaves::Fast),
+            "fancy" => Ok(Leaves::Fancy),
+            _ => Err(format!(
+                "unknown leaves quality '{}', expected fast or fancy",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Leaves {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Leaves::Fast => "fast",
+            Leaves::Fancy => "fancy",
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    /// Texture coordinates and the texture array layer.
+    pub uv: [f32; 3],
+    pub shade: f32,
+}
+
+#[derive(Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    /// Indices of opaque quads.
+    pub indices: Vec<u32>,
+    /// Indices of alpha tested quads.
+    pub cutout: Vec<u32>,
+    pub translucent: Translucent,
+}
+
+impl Mesh {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty() && self.cutout.is_empty() && self.translucent.indices.is_empty()
+    }
+}
+
+/// Indices of translucent quads, six per quad, and the center of each quad.
+#[derive(Debug, Clone, Default)]
+pub struct Translucent {
+    pub indices: Vec<u32>,
+    pub centers: Vec<Point3<f32>>,
+}
+
+impl Translucent {
+    /// Indices with the quads furthest from `eye` first.
+    pub fn sorted(&self, eye: Point3<f32>) -> Vec<u32> {
+        let mut order: Vec<_> = (0..self.centers.len()).collect();
+        order.sort_by(|&a, &b| {
+            let a = self.centers[a].distance2(eye);
+            let b = self.centers[b].distance2(eye);
+            b.partial_cmp(&a).unwrap()
+        });
+        order
+            .iter()
+            .flat_map(|&quad| &self.indices[quad * 6..quad * 6 + 6])
+            .copied()
+            .collect()
+    }
+}
+
+/// Meshes the chunk at `pos` with cells of `2^level` blocks per side, looking into neighbouring
+/// chunks for culling and occlusion.
+pub fn mesh_lod(
+    world: &World,
+    pos: ChunkPos,
+    level: u32,
+    seams: Seams,
+    leaves: Leaves,
+    textures: &TextureArray,
+    models: &BlockModels,
+) -> Mesh {
+    let mut mesh = Mesh::default();
+    if world.chunk(pos).is_none() {
+        return mesh;
+    }
+
+    let grid = Grid::new(world, pos, level);
+    for y in 0..grid.cells {
+        for z in 0..grid.cells {
+            for x in 0..grid.cells {
+                let p = Point3::new(x, y, z);
+                let block = grid.block(p);
+                if block == Block::Air {
+                    continue;
+                }
+                match block.model() {
+                    Model::Cube => {}
+                    Model::Cross => {
+                        if let Some(texture) =
+                            block.texture(Face::North).and_then(|t| textures.texture(t))
+                        {
+                            push_cross(&mut mesh, &grid, p, texture.layer as f32);
+                        }
+                        continue;
+                    }
+                    Model::Elements { name, rotation } => {
+                        // Checked for every block when loading
+                        let model = models.get(name, rotation).unwrap();
+                        push_model(&mut mesh, &grid, p, model, seams);
+                        continue;
+                    }
+                }
+
+                for (&face, &seam) in Face::ALL.iter().zip(&seams) {
+                    let (neighbour, on_seam) = grid.neighbour(p, face, seam);
+                    let fancy = block == Block::Leaves && leaves == Leaves::Fancy;
+                    let hidden = neighbour.is_opaque() || (neighbour == block && !fancy);
+                    if hidden && !on_seam {
+                        continue;
+                    }
+
+                    let texture = match block.texture(face).and_then(|t| textures.texture(t)) {
+                        Some(texture) => texture,
+                        None => continue,
+                    };
+                    push_face(&mut mesh, &grid, p, face, texture.layer as f32);
+                }
+            }
+        }
+    }
+    mesh
+}
+
+/// Cells of a chunk and the ones around it, in cell coordinates relative to the chunk.
+struct Grid {
+    /// Cells along a side of the chunk.
+    cells: i32,
+    /// Blocks along a side of a cell.
+    scale: i32,
+    /// Origin of the chunk in world blocks.
+    origin: Point3<i32>,
+    blocks: Vec<Block>,
+}
+
+impl Grid {
+    fn new(world: &World, pos: ChunkPos, level: u32) -> Self {
+        let scale = 1 << level;
+        let cells = CHUNK_SIZE / scale;
+        let side = cells + 2;
+
+        let mut grid = Self {
+            cells,
+            scale,
+            origin: pos * CHUNK_SIZE,
+            blocks: Vec::with_capacity((side * side * side) as usize),
+        };
+        for y in -1..=cells {
+            for z in -1..=cells {
+                for x in -1..=cells {
+                    let block = grid.downsample(world, Point3::new(x, y, z));
+                    grid.blocks.push(block);
+                }
+            }
+        }
+        grid
+    }
+
+    /// A cell is solid if at least half its blocks are, and shows the topmost of them. Plants
+    /// are too small to count.
+    fn downsample(&self, world: &World, cell: Point3<i32>) -> Block {
+        let min = self.origin + (cell * self.scale).to_vec();
+        if self.scale == 1 {
+            return world.block(min);
+        }
+
+        let mut top = Block::Air;
+        let mut filled = 0;
+        for y in (0..self.scale).rev() {
+            for z in 0..self.scale {
+                for x in 0..self.scale {
+                    let block = world.block(Point3::new(min.x + x, min.y + y, min.z + z));
+                    if block != Block::Air && block.model() == Model::Cube {
+                        filled += 1;
+                        if top == Block::Air {
+                            top = block;
+                        }
+                    }
+                }
+            }
+        }
+
+        if filled * 2 >= self.scale.pow(3) {
+            top
+        } else {
+            Block::Air
+        }
+    }
+
+    fn contains(&self, p: Point3<i32>) -> bool {
+        [p.x, p.y, p.z].iter().all(|c| (0..self.cells).contains(c))
+    }
+
+    /// Cell next to `p` across `face`, and whether it's in a neighbouring chunk across a seam.
+    fn neighbour(&self, p: Point3<i32>, face: Face, seam: bool) -> (Block, bool) {
+        let n = face.normal();
+        let next = Point3::new(p.x + n[0], p.y + n[1], p.z + n[2]);
+        (self.block(next), seam && !self.contains(next))
+    }
+
+    /// Cell at `p`, within one cell of the chunk.
+    fn block(&self, p: Point3<i32>) -> Block {
+        let side = self.cells + 2;
+        self.blocks[(((p.y + 1) * side + p.z + 1) * side + p.x + 1) as usize]
+    }
+}
+
+fn push_face(mesh: &mut Mesh, grid: &Grid, p: Point3<i32>, face: Face, layer: f32) {
+    let n = face.normal();
+    let (t1, t2) = tangents(face);
+    let opaque = |d: [i32; 3]| {
+        grid.block(Point3::new(p.x + d[0], p.y + d[1], p.z + d[2]))
+            .is_opaque()
+    };
+
+    // Counter-clockwise seen from outside since t1 × t2 = n
+    let corners = [(-1, -1), (1, -1), (1, 1), (-1, 1)];
+    let mut ao = [0; 4];
+    let base = mesh.vertices.len() as u32;
+    let size = grid.scale as f32;
+
+    for (i, &(s1, s2)) in corners.iter().enumerate() {
+        let side1 = add(n, scale(t1, s1));
+        let side2 = add(n, scale(t2, s2));
+        let corner = add(side1, scale(t2, s2));
+        ao[i] = occlusion(opaque(side1), opaque(side2), opaque(corner));
+
+        // Offset of the corner from the cell center, in half cells
+        let offset = add(n, add(scale(t1, s1), scale(t2, s2)));
+        let (u, v) = uv(face, offset);
+
+        let position = |cell: i32, origin: i32, offset: i32| {
+            origin as f32 + (cell as f32 + 0.5 + offset as f32 * 0.5) * size
+        };
+        mesh.vertices.push(Vertex {
+            position: [
+                position(p.x, grid.origin.x, offset[0]),
+                position(p.y, grid.origin.y, offset[1]),
+                position(p.z, grid.origin.z, offset[2]),
+            ],
+            // Textures repeat once per block
+            uv: [u * size, v * size, layer],
+            shade: face_shade(face) * AO_CURVE[ao[i]],
+        });
+    }
+
+    // Split along the brighter diagonal so occlusion interpolates symmetrically
+    let quad = if ao[0] + ao[2] <= ao[1] + ao[3] {
+        [0, 1, 2, 0, 2, 3]
+    } else {
+        [1, 2, 3, 1, 3, 0]
+    };
+    push_indices(mesh, grid.block(p), base, quad);
+}
+
+/// Adds the quad of the four vertices from `base` on to the indices of `block`'s pass.
+fn push_indices(mesh: &mut Mesh, block: Block, base: u32, quad: [u32; 6]) {
+    let indices = quad.iter().map(|i| base + i);
+
+    if block.is_cutout() {
+        mesh.cutout.extend(indices);
+    } else if block.is_translucent() {
+        let center = |axis: usize| {
+            let corners = &mesh.vertices[base as usize..base as usize + 4];
+            corners.iter().map(|v| v.position[axis]).sum::<f32>() / 4.0
+        };
+        let center = Point3::new(center(0), center(1), center(2));
+        mesh.translucent.indices.extend(indices);
+        mesh.translucent.centers.push(center);
+    } else {
+        mesh.indices.extend(indices);
+    }
+}
+
+/// Two quads along the diagonals of the cell, seen from both sides.
+fn push_cross(mesh: &mut Mesh, grid: &Grid, p: Point3<i32>, layer: f32) {
+    let min = grid.origin + (p * grid.scale).to_vec();
+    let size = grid.scale as f32;
+    let diagonals = [[(0.0, 0.0), (1.0, 1.0)], [(1.0, 0.0), (0.0, 1.0)]];
+
+    for [(x0, z0), (x1, z1)] in diagonals {
+        let base = mesh.vertices.len() as u32;
+        let corners = [(x0, z0, 0.0), (x1, z1, 0.0), (x1, z1, 1.0), (x0, z0, 1.0)];
+        for (i, &(x, z, y)) in corners.iter().enumerate() {
+            let u = if i == 0 || i == 3 { 0.0 } else { 1.0 };
+            mesh.vertices.push(Vertex {
+                position: [
+                    min.x as f32 + x * size,
+                    min.y as f32 + y * size,
+                    min.z as f32 + z * size,
+                ],
+                uv: [u, 1.0 - y, layer],
+                shade: AO_CURVE[0],
+            });
+        }
+        push_indices(mesh, grid.block(p), base, [0, 1, 2, 0, 2, 3]);
+    }
+}
+
+/// Quads of a baked model, without the ones an opaque neighbour hides.
+fn push_model(mesh: &mut Mesh, grid: &Grid, p: Point3<i32>, model: &BakedModel, seams: Seams) {
+    let min = grid.origin + (p * grid.scale).to_vec();
+    let size = grid.scale as f32;
+
+    for quad in &model.quads {
+        if let Some(cullface) = quad.cullface {
+            let seam = seams[Face::ALL.iter().position(|&f| f == cullface).unwrap()];
+            let (neighbour, on_seam) = grid.neighbour(p, cullface, seam);
+            if neighbour.is_opaque() && !on_seam {
+                continue;
+            }
+        }
+
+        let base = mesh.vertices.len() as u32;
+        let shade = if quad.shade {
+            face_shade(quad.face)
+        } else {
+            1.0
+        };
+        for (position, uv) in quad.positions.iter().zip(&quad.uvs) {
+            mesh.vertices.push(Vertex {
+                position: [
+                    min.x as f32 + position[0] * size,
+                    min.y as f32 + position[1] * size,
+                    min.z as f32 + position[2] * size,
+                ],
+                uv: [uv[0], uv[1], quad.layer as f32],
+                shade,
+            });
+        }
+        push_indices(mesh, grid.block(p), base, [0, 1, 2, 0, 2, 3]);
+    }
+}
+
+/// Number of occluders, a vertex between two opaque sides is fully occluded.
+fn occlusion(side1: bool, side2: bool, corner: bool) -> usize {
+    if side1 && side2 {
+        3
+    } else {
+        side1 as usize + side2 as usize + corner as usize
+    }
+}
+
+fn tangents(face: Face) -> ([i32; 3], [i32; 3]) {
+    match face {
+        Face::Top => ([0, 0, 1], [1, 0, 0]),
+        Face::Bottom => ([1, 0, 0], [0, 0, 1]),
+        Face::North => ([0, 1, 0], [1, 0, 0]),
+        Face::South => ([1, 0, 0], [0, 1, 0]),
+        Face::East => ([0, 1, 0], [0, 0, 1]),
+        Face::West => ([0, 0, 1], [0, 1, 0]),
+    }
+}
+
+/// Texture coordinates of a corner, side textures stay upright.
+fn uv(face: Face, offset: [i32; 3]) -> (f32, f32) {
+    let unit = |c: i32| (c + 1) as f32 * 0.5;
+
+    match face {
+        Face::Top => (unit(offset[0]), unit(offset[2])),
+        Face::Bottom => (unit(offset[0]), unit(-offset[2])),
+        _ => {
+            // Right as seen from outside is up × normal
+            let n = face.normal();
+            let right = [n[2], 0, -n[0]];
+            let u = offset[0] * right[0] + offset[2] * right[2];
+            (unit(u), unit(-offset[1]))
+        }
+    }
+}
+
+/// Directional shading so faces stay distinguishable without lighting.
+fn face_shade(face: Face) -> f32 {
+    match face {
+        Face::Top => 1.0,
+        Face::Bottom => 0.5,
+        Face::North | Face::South => 0.8,
+        Face::East | Face::West => 0.6,
+    }
+}
+
+fn add(a: [i32; 3], b: [i32; 3]) -> [i32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(a: [i32; 3], s: i32) -> [i32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::models::MODELS_DIR;
+    use crate::gfx::textures::TEXTURES_DIR;
+
+    /// Meshes the chunk at the origin at full detail.
+    fn mesh(world: &World, leaves: Leaves) -> Mesh {
+        mesh_at(world, 0, [false; 6], leaves)
+    }
+
+    fn mesh_at(world: &World, level: u32, seams: Seams, leaves: Leaves) -> Mesh {
+        let textures = TextureArray::load(TEXTURES_DIR);
+        let models = BlockModels::load(MODELS_DIR, &textures).unwrap();
+        mesh_lod(
+            world,
+            Point3::new(0, 0, 0),
+            level,
+            seams,
+            leaves,
+            &textures,
+            &models,
+        )
+    }
+
+    #[test]
+    fn culls_faces_between_blocks() {
+        let mut world = World::new();
+        world.set_block(Point3::new(0, 0, 0), Block::Stone);
+        assert_eq!(mesh(&world, Leaves::Fancy).vertices.len(), 6 * 4);
+
+        // Across the chunk border
+        world.set_block(Point3::new(-1, 0, 0), Block::Stone);
+        assert_eq!(mesh(&world, Leaves::Fancy).vertices.len(), 5 * 4);
+    }
+
+    #[test]
+    fn darkens_corners_next_to_walls() {
+        let mut world = World::new();
+        world.set_block(Point3::new(0, 0, 0), Block::Stone);
+        world.set_block(Point3::new(1, 1, 0), Block::Stone);
+
+        let mesh = mesh(&world, Leaves::Fancy);
+        let top = mesh
+            .vertices
+            .chunks(4)
+            .find(|quad| quad.iter().all(|v| v.position[1] == 1.0))
+            .unwrap();
+
+        // Corners along the wall have one occluding side
+        for v in top {
+            let expected = if v.position[0] == 1.0 {
+                AO_CURVE[1]
+            } else {
+                AO_CURVE[0]
+            };
+            assert_eq!(v.shade, expected, "at {:?}", v.position);
+        }
+    }
+
+    #[test]
+    fn sorts_translucent_quads_back_to_front() {
+        let mut world = World::new();
+        world.set_block(Point3::new(0, 0, 0), Block::Stone);
+        world.set_block(Point3::new(2, 0, 0), Block::Water);
+        world.set_block(Point3::new(4, 0, 0), Block::StainedGlass);
+
+        let mesh = mesh(&world, Leaves::Fancy);
+        assert_eq!(mesh.indices.len(), 6 * 6);
+        assert_eq!(mesh.translucent.indices.len(), 2 * 6 * 6);
+        assert_eq!(mesh.translucent.centers.len(), 2 * 6);
+
+        // Seen from the east, the water's west face comes first and the glass' east face last
+        let center = |indices: &[u32]| {
+            let x = indices
+                .iter()
+                .map(|&i| mesh.vertices[i as usize].position[0]);
+            x.sum::<f32>() / indices.len() as f32
+        };
+        let sorted = mesh.translucent.sorted(Point3::new(10.0, 0.5, 0.5));
+        assert_eq!(sorted.len(), mesh.translucent.indices.len());
+        assert_eq!(center(&sorted[..6]), 2.0);
+        assert_eq!(center(&sorted[sorted.len() - 6..]), 5.0);
+    }
+
+    #[test]
+    fn meshes_cutout_blocks() {
+        let mut world = World::new();
+        world.set_block(Point3::new(0, 0, 0), Block::Leaves);
+        world.set_block(Point3::new(1, 0, 0), Block::Leaves);
+        world.set_block(Point3::new(0, 1, 0), Block::Stone);
+        world.set_block(Point3::new(5, 0, 5), Block::Poppy);
+
+        // Fancy leaves keep the two faces between them, the stone above hides two
+        let fancy = mesh(&world, Leaves::Fancy);
+        let fast = mesh(&world, Leaves::Fast);
+        let cross = 2 * 6;
+        assert_eq!(fancy.cutout.len(), (12 - 1) * 6 + cross);
+        assert_eq!(fast.cutout.len(), (12 - 3) * 6 + cross);
+
+        // Leaves don't hide the stone's bottom face, and plants count as air at coarse levels
+        assert_eq!(fancy.indices.len(), 6 * 6);
+        let coarse = mesh_at(&world, 1, [false; 6], Leaves::Fancy);
+        assert!(coarse.cutout.is_empty());
+    }
+
+    #[test]
+    fn culls_model_quads_against_opaque_neighbours() {
+        let mut world = World::new();
+        world.set_block(Point3::new(0, 0, 0), Block::StoneSlab);
+        let quads = |world: &World| mesh(world, Leaves::Fancy).vertices.len() / 4;
+        assert_eq!(quads(&world), 6);
+
+        // Stone below hides the bottom but the top has no cullface, and slabs aren't cubes so
+        // they hide neither each other nor the stone
+        world.set_block(Point3::new(0, 1, 0), Block::Stone);
+        world.set_block(Point3::new(0, -1, 0), Block::Stone);
+        world.set_block(Point3::new(1, 0, 0), Block::StoneSlab);
+        assert_eq!(quads(&world), 5 + 6 + 6);
+
+        // Stairs turned towards the south have their tall side there
+        let mut world = World::new();
+        world.set_block(Point3::new(0, 0, 0), Block::StoneStairs(Face::South));
+        let mesh = mesh(&world, Leaves::Fancy);
+        let top = mesh
+            .vertices
+            .iter()
+            .filter(|v| v.position[1] == 1.0)
+            .all(|v| v.position[2] >= 0.5);
+        assert!(top);
+    }
+
+    fn slab(world: &mut World, chunk_x: i32) {
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE / 2 {
+                for z in 0..CHUNK_SIZE {
+                    world.set_block(Point3::new(chunk_x * CHUNK_SIZE + x, y, z), Block::Stone);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn coarser_levels_have_fewer_vertices() {
+        let mut world = World::new();
+        slab(&mut world, 0);
+
+        // Half a chunk thick, every level quarters the faces
+        let faces = [1024, 256, 64, 16];
+        for level in 0..=MAX_LOD {
+            let mesh = mesh_at(&world, level, [false; 6], Leaves::Fancy);
+            assert_eq!(
+                mesh.vertices.len(),
+                faces[level as usize] * 4,
+                "level {}",
+                level
+            );
+            assert_eq!(
+                mesh.vertices,
+                mesh_at(&world, level, [false; 6], Leaves::Fancy).vertices,
+                "level {} isn't deterministic",
+                level
+            );
+        }
+
+        // Cells less than half full vanish
+        let mut world = World::new();
+        world.set_block(Point3::new(0, 0, 0), Block::Stone);
+        assert!(mesh_at(&world, 1, [false; 6], Leaves::Fancy).is_empty());
+    }
+
+    #[test]
+    fn keeps_faces_on_seams() {
+        let mut world = World::new();
+        slab(&mut world, 0);
+        slab(&mut world, 1);
+
+        let culled = mesh(&world, Leaves::Fancy);
+        let mut seams = [false; 6];
+        seams[4] = true; // East
+        let sealed = mesh_at(&world, 0, seams, Leaves::Fancy);
+        assert_eq!(
+            sealed.vertices.len() - culled.vertices.len(),
+            (CHUNK_SIZE * CHUNK_SIZE / 2 * 4) as usize
+        );
+    }
+}
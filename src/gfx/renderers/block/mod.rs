@@ -0,0 +1,1119 @@
+//! # Block renderer
+//!
+//! Draws chunk meshes in three passes: opaque blocks first, then alpha tested cutout blocks like
+//! leaves and plants, then translucent ones like water and glass, blended back to front without
+//! writing depth. For that chunks are sorted by distance, and the quads of the chunk the camera
+//! is in get sorted every frame since they surround it.
+//!
+//! Chunks beyond the render distance are skipped, with fog hiding where they end. With shadows
+//! on, chunks get rendered into a shadow map first, see `shadows`. The targeted block gets
+//! outlined over the chunks, see `selection`.
+
+mod mesher;
+mod selection;
+mod shadows;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Cursor;
+use std::mem;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+use cgmath::{EuclideanSpace, Matrix4, MetricSpace, Point3};
+
+pub use self::mesher::Leaves;
+pub use self::selection::Selected;
+pub use self::shadows::ShadowQuality;
+
+use self::mesher::{Seams, Translucent, Vertex, MAX_LOD};
+use self::selection::Selection;
+use self::shadows::{Shadows, CASCADES};
+use crate::gfx::models::{BlockModels, MODELS_DIR};
+use crate::gfx::textures::{TextureArray, TEXTURES_DIR};
+use crate::gfx::vulkan::{
+    reload_shader, Allocation, Arena, Buffer, Descriptors, OutputEncoding, PipelineLayout, Shader,
+    Texture, FRAMES_IN_FLIGHT,
+};
+use crate::gfx::{Aabb, Camera, Error, Fog, Frustum, Result, Vulkan};
+use crate::shaders::spirv;
+use crate::world::{self, ChunkPos, Connectivity, Face, Time, World, CHUNK_SIZE};
+
+const SHADER_VERT: &str = "block.vert";
+const SHADER_FRAG: &str = "block.frag";
+
+/// Texels below this alpha are discarded by the cutout pass.
+const ALPHA_CUTOFF: f32 = 0.5;
+
+/// How much shadows darken blocks in full daylight.
+const SHADOW_STRENGTH: f32 = 0.5;
+/// Height of the sun above the horizon, as the y of its direction, where shadows are fully
+/// there. They fade out as it sets instead of being cast from below the horizon.
+const SHADOW_FADE: f32 = 0.2;
+
+/// Initial arena sizes, about what a 4×4 chunk world of terrain needs.
+const INITIAL_VERTICES: u32 = 1 << 16;
+const INITIAL_INDICES: u32 = 1 << 17;
+
+pub struct Block {
+    device: Arc<Device>,
+
+    textures: TextureArray,
+    _texture: Texture,
+    models: BlockModels,
+
+    shader_vert: Shader,
+    shader_frag: Shader,
+
+    layout: PipelineLayout,
+    descriptors: Descriptors,
+    /// Textures and shadows of each frame in flight.
+    sets: Vec<vk::DescriptorSet>,
+    shadow_uniforms: Vec<Buffer>,
+    shadows: Shadows,
+    selection: Selection,
+
+    /// Pipeline of each `Pass`.
+    pipelines: Vec<vk::Pipeline>,
+    multi_draw_indirect: bool,
+    surface_format: vk::SurfaceFormatKHR,
+    samples: vk::SampleCountFlags,
+
+    vertices: Arena<Vertex>,
+    indices: Arena<u32>,
+    meshes: HashMap<ChunkPos, ChunkMesh>,
+    /// Draw commands of each frame in flight, with room for a draw per pass and mesh.
+    indirect: Vec<Buffer>,
+    /// Sorted translucent indices of the camera's chunk for each frame in flight.
+    sorted: Vec<Buffer>,
+    connectivity: HashMap<ChunkPos, Connectivity>,
+    leaves: Leaves,
+    sky_light: f32,
+    fog: Fog,
+    /// Chunks further than this many chunks from the camera are skipped, `None` draws all.
+    render_distance: Option<u32>,
+    /// Level of detail each loaded chunk was meshed at.
+    details: HashMap<ChunkPos, Detail>,
+    /// Chunks within this distance get full detail, `None` disables LOD.
+    lod_distance: Option<u32>,
+    /// Chunk levels of detail are relative to, `None` if they need to be reconsidered.
+    lod_center: Option<ChunkPos>,
+    /// Smallest and largest loaded chunk position.
+    bounds: Option<(ChunkPos, ChunkPos)>,
+    /// Block to outline, see `set_selected`.
+    selected: Option<Selected>,
+    stats: DrawStats,
+}
+
+/// What the last frame drew, for the debug overlay.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DrawStats {
+    /// Chunks with a mesh.
+    pub considered: usize,
+    /// Chunks that passed frustum culling.
+    pub in_frustum: usize,
+    /// Chunks that also passed cave culling.
+    pub drawn: usize,
+}
+
+impl fmt::Display for DrawStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "chunks {}/{}/{}",
+            self.drawn, self.in_frustum, self.considered
+        )
+    }
+}
+
+/// Push constants shared by both stages.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Constants {
+    view_projection: [f32; 16],
+    output_encoding: OutputEncoding,
+    alpha_cutoff: f32,
+    sky_light: f32,
+    fog_color: [f32; 4],
+    eye: [f32; 3],
+    fog_start: f32,
+    fog_end: f32,
+}
+
+/// Uniform block of the fragment stage, laid out as std140.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ShadowUniforms {
+    cascades: [[f32; 16]; CASCADES],
+    /// Far distance of each cascade and shadow strength.
+    splits: [f32; 4],
+    /// View direction and the size of a shadow map texel in UV space.
+    forward: [f32; 4],
+}
+
+impl ShadowUniforms {
+    /// No shadows at all.
+    const NONE: ShadowUniforms = ShadowUniforms {
+        cascades: [[0.0; 16]; CASCADES],
+        splits: [0.0; 4],
+        forward: [0.0; 4],
+    };
+}
+
+/// How a chunk is meshed, see `mesher::mesh_lod`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Detail {
+    level: u32,
+    seams: Seams,
+}
+
+/// Which blocks a pipeline draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    Opaque,
+    /// Like opaque, with texels below `ALPHA_CUTOFF` discarded.
+    Cutout,
+    /// Blended over the opaque blocks, tested against but not writing depth.
+    Translucent,
+}
+
+impl Pass {
+    const ALL: [Pass; 3] = [Pass::Opaque, Pass::Cutout, Pass::Translucent];
+
+    fn alpha_cutoff(self) -> f32 {
+        match self {
+            Pass::Cutout => ALPHA_CUTOFF,
+            _ => 0.0,
+        }
+    }
+}
+
+/// A meshed chunk, stored in the vertex and index arenas.
+struct ChunkMesh {
+    vertices: Allocation,
+    indices: Allocation,
+    cutout: Allocation,
+    translucent: Allocation,
+    /// Translucent quads, to sort them when the camera is in the chunk.
+    quads: Translucent,
+    bounds: Aabb,
+}
+
+impl Block {
+    pub fn new(vulkan: &Vulkan) -> Result<Self> {
+        let device = vulkan.clone_device();
+
+        // === SHADERS ===
+
+        let mut vert_file = Cursor::new(spirv!("block.vert"));
+        let mut frag_file = Cursor::new(spirv!("block.frag"));
+
+        let shader_vert = vulkan.create_shader_module(&mut vert_file)?;
+        let shader_frag = vulkan.create_shader_module(&mut frag_file)?;
+
+        // === TEXTURES ===
+
+        let textures = TextureArray::load(TEXTURES_DIR);
+        let texture = Texture::new(vulkan, &textures);
+        let models = BlockModels::load(MODELS_DIR, &textures).map_err(Error::InvalidModel)?;
+
+        // === DESCRIPTORS ===
+
+        let layout = PipelineLayout::new(vulkan, &[&shader_vert, &shader_frag]);
+        let mut descriptors = Descriptors::new(vulkan);
+
+        let shadows = Shadows::new(vulkan, ShadowQuality::default(), &texture)?;
+        let shadow_uniforms: Vec<_> = (0..FRAMES_IN_FLIGHT)
+            .map(|_| {
+                let buffer = Buffer::new(
+                    vulkan,
+                    mem::size_of::<ShadowUniforms>() as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                );
+                buffer.write(&[ShadowUniforms::NONE]);
+                buffer
+            })
+            .collect();
+        let sets: Vec<_> = shadow_uniforms
+            .iter()
+            .map(|uniforms| {
+                let set = descriptors.allocate(&layout, 0);
+                descriptors.write_image(
+                    set,
+                    0,
+                    vk::DescriptorType::SAMPLED_IMAGE,
+                    texture.image_info(),
+                );
+                descriptors.write_image(
+                    set,
+                    1,
+                    vk::DescriptorType::SAMPLER,
+                    texture.sampler_info(),
+                );
+                descriptors.write_buffer(
+                    set,
+                    2,
+                    vk::DescriptorType::UNIFORM_BUFFER,
+                    vk::DescriptorBufferInfo {
+                        buffer: uniforms.buffer(),
+                        offset: 0,
+                        range: vk::WHOLE_SIZE,
+                    },
+                );
+                set
+            })
+            .collect();
+        write_shadow_map(&descriptors, &sets, &shadows);
+        let selection = Selection::new(vulkan, &textures, &texture)?;
+
+        // === PIPELINE ===
+
+        let pipelines = create_pipelines(
+            &device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &layout,
+            &[&shader_vert, &shader_frag],
+        );
+
+        Ok(Self {
+            device,
+
+            textures,
+            _texture: texture,
+            models,
+
+            shader_vert,
+            shader_frag,
+
+            layout,
+            descriptors,
+            sets,
+            shadow_uniforms,
+            shadows,
+            selection,
+
+            pipelines,
+            multi_draw_indirect: vulkan.multi_draw_indirect(),
+            surface_format: vulkan.surface_format(),
+            samples: vulkan.samples(),
+
+            vertices: Arena::new(
+                vulkan,
+                INITIAL_VERTICES,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            ),
+            indices: Arena::new(vulkan, INITIAL_INDICES, vk::BufferUsageFlags::INDEX_BUFFER),
+            meshes: HashMap::new(),
+            indirect: Vec::new(),
+            sorted: Vec::new(),
+            connectivity: HashMap::new(),
+            leaves: Leaves::default(),
+            sky_light: 1.0,
+            fog: Fog::NONE,
+            render_distance: None,
+            details: HashMap::new(),
+            lod_distance: None,
+            lod_center: None,
+            bounds: None,
+            selected: None,
+            stats: DrawStats::default(),
+        })
+    }
+
+    /// Swaps in a recompiled shader and rebuilds the pipeline if `name` is one of ours.
+    /// Returns whether the shader was used.
+    pub fn reload_shader(&mut self, vulkan: &Vulkan, name: &str, code: &[u32]) -> bool {
+        reload_shader(
+            self,
+            vulkan,
+            name,
+            code,
+            |renderer| {
+                [
+                    (SHADER_VERT, &mut renderer.shader_vert),
+                    (SHADER_FRAG, &mut renderer.shader_frag),
+                ]
+            },
+            Self::rebuild_pipeline,
+        ) || self.shadows.reload_shader(vulkan, name, code)
+            || self.selection.reload_shader(vulkan, name, code)
+    }
+
+    /// Follows changes of the surface format and multisampling, call before drawing. Pipelines
+    /// get rebuilt for the new render pass if the format itself or the sample count changed.
+    pub fn update_surface(&mut self, vulkan: &Vulkan) {
+        if vulkan.follow_surface(&mut self.surface_format, &mut self.samples) {
+            self.rebuild_pipeline(vulkan);
+            self.selection.rebuild_pipelines(vulkan);
+        }
+    }
+
+    fn rebuild_pipeline(&mut self, vulkan: &Vulkan) {
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+            for &pipeline in &self.pipelines {
+                self.device.destroy_pipeline(pipeline, None);
+            }
+        }
+
+        self.pipelines = create_pipelines(
+            &self.device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &self.layout,
+            &[&self.shader_vert, &self.shader_frag],
+        );
+    }
+
+    /// Meshes every chunk of `world`, replacing all previously loaded meshes.
+    pub fn load_world(&mut self, vulkan: &Vulkan, world: &World) {
+        unsafe { self.device.device_wait_idle().unwrap() };
+
+        for (_, mesh) in self.meshes.drain() {
+            self.vertices.remove(mesh.vertices);
+            self.indices.remove(mesh.indices);
+            self.indices.remove(mesh.cutout);
+            self.indices.remove(mesh.translucent);
+        }
+        self.connectivity.clear();
+        self.details.clear();
+        self.bounds = None;
+        for (pos, _) in world.chunks() {
+            self.update_chunk(vulkan, world, pos);
+        }
+    }
+
+    /// Remeshes the chunks the block at `pos` shows in after it changed, including neighbours
+    /// whose faces or corner shading it affects.
+    pub fn update_block(&mut self, vulkan: &Vulkan, world: &World, pos: Point3<i32>) {
+        unsafe { self.device.device_wait_idle().unwrap() };
+
+        let mut chunks = HashSet::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    chunks.insert(
+                        Point3::new(pos.x + dx, pos.y + dy, pos.z + dz)
+                            .map(|c| c.div_euclid(CHUNK_SIZE)),
+                    );
+                }
+            }
+        }
+        for chunk in chunks {
+            if world.chunk(chunk).is_some() || self.meshes.contains_key(&chunk) {
+                self.update_chunk(vulkan, world, chunk);
+            }
+        }
+    }
+
+    /// Remeshes the chunk at `pos`, e.g. after one of its blocks or a neighbour changed.
+    /// Meshes may still be in use by frames in flight, so wait for the device before calling.
+    pub fn update_chunk(&mut self, vulkan: &Vulkan, world: &World, pos: ChunkPos) {
+        match world.chunk(pos) {
+            Some(chunk) => {
+                self.connectivity.insert(pos, Connectivity::compute(chunk));
+                self.bounds = Some(match self.bounds {
+                    Some((min, max)) => (
+                        Point3::new(min.x.min(pos.x), min.y.min(pos.y), min.z.min(pos.z)),
+                        Point3::new(max.x.max(pos.x), max.y.max(pos.y), max.z.max(pos.z)),
+                    ),
+                    None => (pos, pos),
+                });
+            }
+            None => {
+                self.connectivity.remove(&pos);
+            }
+        }
+
+        if let Some(old) = self.meshes.remove(&pos) {
+            self.vertices.remove(old.vertices);
+            self.indices.remove(old.indices);
+            self.indices.remove(old.cutout);
+            self.indices.remove(old.translucent);
+        }
+
+        let detail = self.detail(pos);
+        self.details.insert(pos, detail);
+        let mesh = mesher::mesh_lod(
+            world,
+            pos,
+            detail.level,
+            detail.seams,
+            self.leaves,
+            &self.textures,
+            &self.models,
+        );
+        if mesh.is_empty() {
+            return;
+        }
+
+        let chunk_mesh = ChunkMesh {
+            vertices: self.vertices.insert(vulkan, &mesh.vertices),
+            indices: self.indices.insert(vulkan, &mesh.indices),
+            cutout: self.indices.insert(vulkan, &mesh.cutout),
+            translucent: self.indices.insert(vulkan, &mesh.translucent.indices),
+            quads: mesh.translucent,
+            bounds: chunk_bounds(pos),
+        };
+        self.meshes.insert(pos, chunk_mesh);
+        self.reserve_draws(vulkan);
+    }
+
+    /// Remeshes loaded chunks if the leaves quality changed.
+    pub fn set_leaves(&mut self, vulkan: &Vulkan, world: &World, leaves: Leaves) {
+        if leaves == self.leaves {
+            return;
+        }
+        self.leaves = leaves;
+        if !self.meshes.is_empty() {
+            self.load_world(vulkan, world);
+        }
+    }
+
+    /// Scales the brightness of blocks, following the time of day.
+    pub fn set_sky_light(&mut self, sky_light: f32) {
+        self.sky_light = sky_light;
+    }
+
+    /// Sets the fog blocks fade into, for the next frames.
+    pub fn set_fog(&mut self, fog: Fog) {
+        self.fog = fog;
+    }
+
+    /// Outlines `selected` from the next frame on, `None` to outline nothing.
+    pub fn set_selected(&mut self, selected: Option<Selected>) {
+        self.selected = selected;
+    }
+
+    /// Switches shadow quality, recreating the shadow map if it changed.
+    pub fn set_shadows(&mut self, vulkan: &Vulkan, quality: ShadowQuality) -> Result<()> {
+        if quality == self.shadows.quality() {
+            return Ok(());
+        }
+
+        unsafe { self.device.device_wait_idle().unwrap() };
+        self.shadows.set_quality(vulkan, quality)?;
+        write_shadow_map(&self.descriptors, &self.sets, &self.shadows);
+        Ok(())
+    }
+
+    /// Chunks beyond `distance` chunks from the camera aren't drawn, distance fog should end
+    /// there.
+    pub fn set_render_distance(&mut self, distance: Option<u32>) {
+        self.render_distance = distance;
+    }
+
+    /// Chunks further than `distance` chunks from the camera get coarser meshes, each level of
+    /// detail reaching twice as far as the previous one.
+    pub fn set_lod_distance(&mut self, distance: Option<u32>) {
+        if distance != self.lod_distance {
+            self.lod_distance = distance;
+            self.lod_center = None;
+        }
+    }
+
+    /// Remeshes chunks whose level of detail changed since the camera moved to `eye`.
+    pub fn update_lod(&mut self, vulkan: &Vulkan, world: &World, eye: Point3<f32>) {
+        let center = chunk_of(eye);
+        if self.lod_center == Some(center) {
+            return;
+        }
+        self.lod_center = Some(center);
+
+        let changed: Vec<_> = self
+            .details
+            .iter()
+            .filter(|&(&pos, &detail)| self.detail(pos) != detail)
+            .map(|(&pos, _)| pos)
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+
+        unsafe { self.device.device_wait_idle().unwrap() };
+        for pos in changed {
+            self.update_chunk(vulkan, world, pos);
+        }
+    }
+
+    fn level(&self, pos: ChunkPos) -> u32 {
+        let (distance, center) = match (self.lod_distance, self.lod_center) {
+            (Some(distance), Some(center)) => (distance.max(1) as i32, center),
+            _ => return 0,
+        };
+
+        let offset = pos - center;
+        let chebyshev = offset.x.abs().max(offset.y.abs()).max(offset.z.abs());
+        let mut level = 0;
+        while level < MAX_LOD && chebyshev >= distance << level {
+            level += 1;
+        }
+        level
+    }
+
+    fn detail(&self, pos: ChunkPos) -> Detail {
+        let level = self.level(pos);
+        let mut seams = [false; 6];
+        for (seam, &face) in seams.iter_mut().zip(&Face::ALL) {
+            let [dx, dy, dz] = face.normal();
+            let neighbour = Point3::new(pos.x + dx, pos.y + dy, pos.z + dz);
+            *seam = self.connectivity.contains_key(&neighbour) && self.level(neighbour) != level;
+        }
+        Detail { level, seams }
+    }
+
+    /// Grows the indirect buffers to fit all draws of every mesh, and the sorted index buffers
+    /// to fit the translucent quads of any chunk.
+    fn reserve_draws(&mut self, vulkan: &Vulkan) {
+        let stride = mem::size_of::<vk::DrawIndexedIndirectCommand>() as vk::DeviceSize;
+        let draws = (Pass::ALL.len() * self.meshes.len()) as vk::DeviceSize;
+        reserve(
+            vulkan,
+            &mut self.indirect,
+            draws * stride,
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+        );
+
+        let indices = self
+            .meshes
+            .values()
+            .map(|mesh| mesh.translucent.len)
+            .max()
+            .unwrap_or(0) as vk::DeviceSize;
+        let size = indices * mem::size_of::<u32>() as vk::DeviceSize;
+        reserve(
+            vulkan,
+            &mut self.sorted,
+            size,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        );
+    }
+
+    /// Renders the shadow map for the sun at `time`, call before the main render pass of the
+    /// frame with `Stage::Prepare`. Without shadows, or with the sun down, `draw` leaves blocks
+    /// unshadowed instead.
+    pub fn draw_shadows(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        camera: &Camera,
+        aspect: f32,
+        time: Time,
+    ) {
+        let uniforms = &self.shadow_uniforms[frame % FRAMES_IN_FLIGHT];
+
+        let sun = time.sun_direction();
+        let strength = SHADOW_STRENGTH * time.daylight() * (sun.y / SHADOW_FADE).clamp(0.0, 1.0);
+        if self.shadows.quality() == ShadowQuality::Off || strength <= 0.0 {
+            uniforms.write(&[ShadowUniforms::NONE]);
+            return;
+        }
+
+        let size = self.shadows.map().size();
+        let cascades = shadows::cascades(camera, aspect, sun, size);
+        let forward = camera.direction();
+        uniforms.write(&[ShadowUniforms {
+            cascades: cascades.map(|cascade| *cascade.view_projection.as_ref()),
+            splits: [cascades[0].far, cascades[1].far, cascades[2].far, strength],
+            forward: [forward.x, forward.y, forward.z, 1.0 / size as f32],
+        }]);
+
+        unsafe {
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertices.buffer()], &[0]);
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                self.indices.buffer(),
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
+
+        for (layer, cascade) in cascades.iter().enumerate() {
+            let frustum = Frustum::from_matrix(cascade.view_projection);
+            let casters: Vec<_> = self
+                .meshes
+                .values()
+                .filter(|mesh| frustum.intersects(&mesh.bounds))
+                .collect();
+
+            self.shadows
+                .render(command_buffer, layer, cascade, |pass| unsafe {
+                    for mesh in &casters {
+                        let indices = match pass {
+                            Pass::Cutout => &mesh.cutout,
+                            _ => &mesh.indices,
+                        };
+                        if indices.len == 0 {
+                            continue;
+                        }
+                        self.device.cmd_draw_indexed(
+                            command_buffer,
+                            indices.len,
+                            1,
+                            indices.offset,
+                            mesh.vertices.offset as i32,
+                            0,
+                        );
+                    }
+                });
+        }
+    }
+
+    /// Records the draw commands, inside the render pass begun by `Vulkan::draw_stages`.
+    pub fn draw(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        view_projection: Matrix4<f32>,
+        eye: Point3<f32>,
+    ) {
+        self.draw_chunks(command_buffer, frame, view_projection, eye);
+
+        if let Some(selected) = &self.selected {
+            let encoding = OutputEncoding::of(self.surface_format);
+            self.selection
+                .draw(command_buffer, frame, view_projection, encoding, selected);
+        }
+    }
+
+    /// Draws the chunks in view, pass by pass.
+    fn draw_chunks(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        view_projection: Matrix4<f32>,
+        eye: Point3<f32>,
+    ) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout.layout(),
+                0,
+                &[self.sets[frame % FRAMES_IN_FLIGHT]],
+                &[],
+            );
+
+            let fog = &self.fog;
+            let mut constants = Constants {
+                view_projection: *view_projection.as_ref(),
+                output_encoding: OutputEncoding::of(self.surface_format),
+                alpha_cutoff: 0.0,
+                sky_light: self.sky_light,
+                fog_color: [fog.color[0], fog.color[1], fog.color[2], 1.0],
+                eye: eye.into(),
+                fog_start: fog.start,
+                fog_end: fog.end,
+            };
+
+            let frustum = Frustum::from_matrix(view_projection);
+            let visible = self.visible_chunks(&frustum, eye);
+            self.stats = DrawStats {
+                considered: self.meshes.len(),
+                ..Default::default()
+            };
+
+            // Spherical like the fog, reaching the far side of chunks it partially covers
+            let reach = self.render_distance.map(|distance| {
+                let reach = (distance as i32 + 1) * CHUNK_SIZE;
+                (reach * reach) as f32
+            });
+
+            let mut drawn = Vec::with_capacity(self.meshes.len());
+            for (&pos, mesh) in &self.meshes {
+                let center = mesh.bounds.min.midpoint(mesh.bounds.max);
+                if reach.is_some_and(|reach| center.distance2(eye) > reach) {
+                    continue;
+                }
+                if !frustum.intersects(&mesh.bounds) {
+                    continue;
+                }
+                self.stats.in_frustum += 1;
+                if visible
+                    .as_ref()
+                    .is_some_and(|visible| !visible.contains(&pos))
+                {
+                    continue;
+                }
+                drawn.push((pos, mesh));
+            }
+            self.stats.drawn = drawn.len();
+            if drawn.is_empty() {
+                return;
+            }
+
+            let command = |mesh: &ChunkMesh, indices: &Allocation| vk::DrawIndexedIndirectCommand {
+                index_count: indices.len,
+                instance_count: 1,
+                first_index: indices.offset,
+                vertex_offset: mesh.vertices.offset as i32,
+                first_instance: 0,
+            };
+            let mut commands: Vec<_> = drawn
+                .iter()
+                .filter(|(_, mesh)| mesh.indices.len > 0)
+                .map(|(_, mesh)| command(mesh, &mesh.indices))
+                .collect();
+            let opaque = commands.len();
+            commands.extend(
+                drawn
+                    .iter()
+                    .filter(|(_, mesh)| mesh.cutout.len > 0)
+                    .map(|(_, mesh)| command(mesh, &mesh.cutout)),
+            );
+            let cutout = commands.len() - opaque;
+
+            // Translucent chunks back to front, the camera's chunk gets drawn with sorted quads
+            // in between the ones before and after it
+            drawn.retain(|(_, mesh)| mesh.translucent.len > 0);
+            let center = |mesh: &ChunkMesh| {
+                let bounds = mesh.bounds;
+                bounds.min.midpoint(bounds.max).distance2(eye)
+            };
+            drawn.sort_by(|(_, a), (_, b)| center(b).partial_cmp(&center(a)).unwrap());
+            let camera = chunk_of(eye);
+            let inside = drawn.iter().position(|&(pos, _)| pos == camera);
+            commands.extend(
+                drawn
+                    .iter()
+                    .filter(|&&(pos, _)| pos != camera)
+                    .map(|(_, mesh)| command(mesh, &mesh.translucent)),
+            );
+
+            let indirect = &self.indirect[frame % FRAMES_IN_FLIGHT];
+            if !commands.is_empty() {
+                indirect.write(&commands);
+            }
+
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertices.buffer()], &[0]);
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                self.indices.buffer(),
+                0,
+                vk::IndexType::UINT32,
+            );
+            self.begin_pass(command_buffer, Pass::Opaque, &mut constants);
+            self.draw_indirect(command_buffer, indirect, 0, opaque);
+            self.begin_pass(command_buffer, Pass::Cutout, &mut constants);
+            self.draw_indirect(command_buffer, indirect, opaque, cutout);
+
+            self.begin_pass(command_buffer, Pass::Translucent, &mut constants);
+            let translucent = opaque + cutout;
+            let inside = match inside {
+                Some(inside) => inside,
+                None => {
+                    let count = commands.len() - translucent;
+                    self.draw_indirect(command_buffer, indirect, translucent, count);
+                    return;
+                }
+            };
+            self.draw_indirect(command_buffer, indirect, translucent, inside);
+
+            let mesh = drawn[inside].1;
+            let sorted = &self.sorted[frame % FRAMES_IN_FLIGHT];
+            sorted.write(&mesh.quads.sorted(eye));
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                sorted.buffer(),
+                0,
+                vk::IndexType::UINT32,
+            );
+            self.device.cmd_draw_indexed(
+                command_buffer,
+                mesh.translucent.len,
+                1,
+                0,
+                mesh.vertices.offset as i32,
+                0,
+            );
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                self.indices.buffer(),
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            let after = translucent + inside;
+            self.draw_indirect(command_buffer, indirect, after, commands.len() - after);
+        }
+    }
+
+    /// Binds the pipeline of `pass` and pushes its constants.
+    unsafe fn begin_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pass: Pass,
+        constants: &mut Constants,
+    ) {
+        self.device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipelines[pass as usize],
+        );
+
+        constants.alpha_cutoff = pass.alpha_cutoff();
+        let range = self.layout.push_constants().unwrap();
+        self.device.cmd_push_constants(
+            command_buffer,
+            self.layout.layout(),
+            range.stage_flags,
+            0,
+            as_bytes(constants),
+        );
+    }
+
+    /// Draws `count` commands of `indirect` starting at `first`, in order.
+    unsafe fn draw_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        indirect: &Buffer,
+        first: usize,
+        count: usize,
+    ) {
+        let stride = mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        let offset = |i: usize| (i as u32 * stride) as vk::DeviceSize;
+        if count == 0 {
+            return;
+        }
+
+        if self.multi_draw_indirect {
+            self.device.cmd_draw_indexed_indirect(
+                command_buffer,
+                indirect.buffer(),
+                offset(first),
+                count as u32,
+                stride,
+            );
+        } else {
+            for i in first..first + count {
+                self.device.cmd_draw_indexed_indirect(
+                    command_buffer,
+                    indirect.buffer(),
+                    offset(i),
+                    1,
+                    stride,
+                );
+            }
+        }
+    }
+
+    /// Chunks in view and not hidden behind solid ground, see `world::visible_chunks`. `None`
+    /// if the camera is outside the loaded chunks, where only frustum culling applies.
+    fn visible_chunks(&self, frustum: &Frustum, eye: Point3<f32>) -> Option<HashSet<ChunkPos>> {
+        let (min, max) = self.bounds?;
+        let origin = chunk_of(eye);
+
+        // Missing chunks are air, the walk may pass through those next to loaded ones
+        let connectivity = |pos: ChunkPos| {
+            let near_world = (min.x - 1..=max.x + 1).contains(&pos.x)
+                && (min.y - 1..=max.y + 1).contains(&pos.y)
+                && (min.z - 1..=max.z + 1).contains(&pos.z);
+            match self.connectivity.get(&pos) {
+                Some(&connectivity) => Some(connectivity),
+                None if near_world => Some(Connectivity::ALL),
+                None => None,
+            }
+        };
+        connectivity(origin)?;
+
+        Some(world::visible_chunks(origin, connectivity, |pos| {
+            frustum.intersects(&chunk_bounds(pos))
+        }))
+    }
+
+    pub fn stats(&self) -> DrawStats {
+        self.stats
+    }
+}
+
+impl Drop for Block {
+    fn drop(&mut self) {
+        unsafe {
+            for &pipeline in &self.pipelines {
+                self.device.destroy_pipeline(pipeline, None);
+            }
+        }
+    }
+}
+
+fn create_pipelines(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+    layout: &PipelineLayout,
+    shaders: &[&Shader],
+) -> Vec<vk::Pipeline> {
+    Pass::ALL
+        .iter()
+        .map(|&pass| create_pipeline(device, render_pass, samples, layout, shaders, pass))
+        .collect()
+}
+
+fn create_pipeline(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+    layout: &PipelineLayout,
+    shaders: &[&Shader],
+    pass: Pass,
+) -> vk::Pipeline {
+    let stages: Vec<_> = shaders.iter().map(|s| s.stage_info()).collect();
+
+    let bindings = [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: mem::size_of::<Vertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+    let attributes = [
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 12,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 2,
+            binding: 0,
+            format: vk::Format::R32_SFLOAT,
+            offset: 24,
+        },
+    ];
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&bindings)
+        .vertex_attribute_descriptions(&attributes);
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    // Viewport and scissor follow the render target, see `dynamic_state`
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    let multisample =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(pass != Pass::Translucent)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+    let color_blend_attachments = [match pass {
+        Pass::Opaque | Pass::Cutout => vk::PipelineColorBlendAttachmentState {
+            color_write_mask: vk::ColorComponentFlags::all(),
+            ..Default::default()
+        },
+        // Straight alpha over what's behind, keeping the destination's alpha
+        Pass::Translucent => vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::TRUE,
+            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ZERO,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::all(),
+        },
+    }];
+    let color_blend =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport)
+        .rasterization_state(&rasterization)
+        .multisample_state(&multisample)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blend)
+        .dynamic_state(&dynamic_state)
+        .layout(layout.layout())
+        .render_pass(render_pass)
+        .subpass(0);
+
+    unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)
+            .unwrap()[0]
+    }
+}
+
+/// Replaces `buffers`, one per frame in flight, with larger ones if they hold less than `size`
+/// bytes.
+fn reserve(
+    vulkan: &Vulkan,
+    buffers: &mut Vec<Buffer>,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+) {
+    let capacity = buffers.first().map_or(0, |buffer| buffer.size());
+    if capacity >= size.max(1) {
+        return;
+    }
+
+    let size = size.max(1).next_power_of_two();
+    *buffers = (0..FRAMES_IN_FLIGHT)
+        .map(|_| {
+            Buffer::new(
+                vulkan,
+                size,
+                usage,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+        })
+        .collect();
+}
+
+/// Points the shadow map bindings of `sets` at the current map of `shadows`.
+fn write_shadow_map(descriptors: &Descriptors, sets: &[vk::DescriptorSet], shadows: &Shadows) {
+    for &set in sets {
+        descriptors.write_image(
+            set,
+            3,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            shadows.map().image_info(),
+        );
+        descriptors.write_image(
+            set,
+            4,
+            vk::DescriptorType::SAMPLER,
+            shadows.map().sampler_info(),
+        );
+    }
+}
+
+fn chunk_of(p: Point3<f32>) -> ChunkPos {
+    p.map(|c| (c.floor() as i32).div_euclid(CHUNK_SIZE))
+}
+
+fn chunk_bounds(pos: ChunkPos) -> Aabb {
+    let min = pos.map(|c| (c * CHUNK_SIZE) as f32);
+    let size = CHUNK_SIZE as f32;
+    Aabb::new(min, Point3::new(min.x + size, min.y + size, min.z + size))
+}
+
+fn as_bytes<T: Copy>(data: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data as *const T as *const u8, mem::size_of::<T>()) }
+}
@@ -0,0 +1,518 @@
+//! # Selection
+//!
+//! Highlights the targeted block: a wireframe of its outline boxes, see `world::Block::outline`,
+//! and cracks over them growing as it's being broken. Both are drawn over the blocks, blended
+//! and tested against depth without writing it, slightly outside the boxes so they don't fight
+//! with the block's own faces.
+
+use std::io::Cursor;
+use std::mem;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+use cgmath::{Matrix4, Point3, Vector3};
+
+use super::{as_bytes, Vertex};
+use crate::gfx::textures::TextureArray;
+use crate::gfx::vulkan::{
+    reload_shader, Buffer, Descriptors, OutputEncoding, PipelineLayout, Shader, Texture,
+    FRAMES_IN_FLIGHT,
+};
+use crate::gfx::{Result, Vulkan};
+use crate::shaders::spirv;
+use crate::world::{self, Face};
+
+const SHADER_VERT: &str = "selection.vert";
+const SHADER_FRAG: &str = "selection.frag";
+
+/// Crack textures `destroy_stage_0` and up, shown in turn as breaking progresses.
+const CRACK_STAGES: u32 = 10;
+
+/// Linear color and opacity of the wireframe.
+const OUTLINE_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.4];
+
+/// How far outside the boxes the wireframe and cracks are drawn, in blocks.
+const OUTLINE_OFFSET: f32 = 0.002;
+const CRACK_OFFSET: f32 = 0.001;
+
+/// Outline boxes a block may have, more are left out.
+const MAX_BOXES: usize = 4;
+const LINE_VERTICES: usize = 24;
+const CRACK_VERTICES: usize = 36;
+
+/// The block under the crosshair.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Selected {
+    pub pos: Point3<i32>,
+    pub block: world::Block,
+    /// How far along breaking it is, cracks show above `0.0`.
+    pub progress: f32,
+}
+
+/// Push constants shared by both stages.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Constants {
+    view_projection: [f32; 16],
+    color: [f32; 4],
+    output_encoding: OutputEncoding,
+    textured: u32,
+}
+
+/// Renders the outline and cracks of the selected block, see `Block::set_selected`.
+pub struct Selection {
+    device: Arc<Device>,
+
+    /// First layer of each crack stage.
+    cracks: Vec<u32>,
+
+    shader_vert: Shader,
+    shader_frag: Shader,
+
+    layout: PipelineLayout,
+    _descriptors: Descriptors,
+    texture_set: vk::DescriptorSet,
+    /// Vertices of each frame in flight, lines first.
+    vertices: Vec<Buffer>,
+
+    lines: vk::Pipeline,
+    decal: vk::Pipeline,
+}
+
+impl Selection {
+    /// Cracks are looked up in `textures`, uploaded as `texture`.
+    pub fn new(vulkan: &Vulkan, textures: &TextureArray, texture: &Texture) -> Result<Self> {
+        let device = vulkan.clone_device();
+
+        let cracks = (0..CRACK_STAGES)
+            .map(|stage| {
+                textures
+                    .texture(&format!("destroy_stage_{}", stage))
+                    .expect("missing crack texture")
+                    .layer
+            })
+            .collect();
+
+        let mut vert_file = Cursor::new(spirv!("selection.vert"));
+        let mut frag_file = Cursor::new(spirv!("selection.frag"));
+
+        let shader_vert = vulkan.create_shader_module(&mut vert_file)?;
+        let shader_frag = vulkan.create_shader_module(&mut frag_file)?;
+
+        let layout = PipelineLayout::new(vulkan, &[&shader_vert, &shader_frag]);
+        let mut descriptors = Descriptors::new(vulkan);
+
+        let texture_set = descriptors.allocate(&layout, 0);
+        descriptors.write_image(
+            texture_set,
+            0,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            texture.image_info(),
+        );
+        descriptors.write_image(
+            texture_set,
+            1,
+            vk::DescriptorType::SAMPLER,
+            texture.sampler_info(),
+        );
+
+        let vertices = (0..FRAMES_IN_FLIGHT)
+            .map(|_| {
+                Buffer::new(
+                    vulkan,
+                    (mem::size_of::<Vertex>() * MAX_BOXES * (LINE_VERTICES + CRACK_VERTICES))
+                        as vk::DeviceSize,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )
+            })
+            .collect();
+
+        let shaders = [&shader_vert, &shader_frag];
+        let lines = create_pipeline(
+            &device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &layout,
+            &shaders,
+            vk::PrimitiveTopology::LINE_LIST,
+        );
+        let decal = create_pipeline(
+            &device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &layout,
+            &shaders,
+            vk::PrimitiveTopology::TRIANGLE_LIST,
+        );
+
+        Ok(Self {
+            device,
+
+            cracks,
+
+            shader_vert,
+            shader_frag,
+
+            layout,
+            _descriptors: descriptors,
+            texture_set,
+            vertices,
+
+            lines,
+            decal,
+        })
+    }
+
+    /// Swaps in a recompiled shader and rebuilds the pipelines if `name` is one of ours.
+    /// Returns whether the shader was used.
+    pub fn reload_shader(&mut self, vulkan: &Vulkan, name: &str, code: &[u32]) -> bool {
+        reload_shader(
+            self,
+            vulkan,
+            name,
+            code,
+            |renderer| {
+                [
+                    (SHADER_VERT, &mut renderer.shader_vert),
+                    (SHADER_FRAG, &mut renderer.shader_frag),
+                ]
+            },
+            |selection, vulkan| {
+                unsafe { selection.device.device_wait_idle().unwrap() };
+                selection.rebuild_pipelines(vulkan);
+            },
+        )
+    }
+
+    /// Recreates the pipelines for the current render pass, wait for the device before calling.
+    pub fn rebuild_pipelines(&mut self, vulkan: &Vulkan) {
+        unsafe {
+            self.device.destroy_pipeline(self.lines, None);
+            self.device.destroy_pipeline(self.decal, None);
+        }
+
+        let shaders = [&self.shader_vert, &self.shader_frag];
+        self.lines = create_pipeline(
+            &self.device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &self.layout,
+            &shaders,
+            vk::PrimitiveTopology::LINE_LIST,
+        );
+        self.decal = create_pipeline(
+            &self.device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &self.layout,
+            &shaders,
+            vk::PrimitiveTopology::TRIANGLE_LIST,
+        );
+    }
+
+    /// Records the draws of `selected`'s outline and cracks, inside the main render pass.
+    pub fn draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        view_projection: Matrix4<f32>,
+        output_encoding: OutputEncoding,
+        selected: &Selected,
+    ) {
+        let mut boxes = selected.block.outline();
+        boxes.truncate(MAX_BOXES);
+        if boxes.is_empty() {
+            return;
+        }
+
+        let mut vertices = outline(selected.pos, &boxes);
+        let lines = vertices.len() as u32;
+        if selected.progress > 0.0 {
+            let stage = (selected.progress * CRACK_STAGES as f32) as usize;
+            let layer = self.cracks[stage.min(CRACK_STAGES as usize - 1)];
+            vertices.extend(cracks(selected.pos, &boxes, layer));
+        }
+        let buffer = &self.vertices[frame % FRAMES_IN_FLIGHT];
+        buffer.write(&vertices);
+
+        let mut constants = Constants {
+            view_projection: *view_projection.as_ref(),
+            color: OUTLINE_COLOR,
+            output_encoding,
+            textured: 0,
+        };
+        let range = self.layout.push_constants().unwrap();
+
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout.layout(),
+                0,
+                &[self.texture_set],
+                &[],
+            );
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &[buffer.buffer()], &[0]);
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.lines,
+            );
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.layout.layout(),
+                range.stage_flags,
+                0,
+                as_bytes(&constants),
+            );
+            self.device.cmd_draw(command_buffer, lines, 1, 0, 0);
+
+            let cracks = vertices.len() as u32 - lines;
+            if cracks > 0 {
+                constants.color = [1.0; 4];
+                constants.textured = 1;
+                self.device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.decal,
+                );
+                self.device.cmd_push_constants(
+                    command_buffer,
+                    self.layout.layout(),
+                    range.stage_flags,
+                    0,
+                    as_bytes(&constants),
+                );
+                self.device.cmd_draw(command_buffer, cracks, 1, lines, 0);
+            }
+        }
+    }
+}
+
+impl Drop for Selection {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.lines, None);
+            self.device.destroy_pipeline(self.decal, None);
+        }
+    }
+}
+
+/// Corners of a box of the block at `pos`, grown by `offset`. Bits 0, 1 and 2 of the index pick
+/// the max side along X, Y and Z.
+fn corners(pos: Point3<i32>, (min, max): ([f32; 3], [f32; 3]), offset: f32) -> [Point3<f32>; 8] {
+    let base = pos.map(|c| c as f32);
+    let min = base + Vector3::from(min) - Vector3::new(offset, offset, offset);
+    let max = base + Vector3::from(max) + Vector3::new(offset, offset, offset);
+    let mut corners = [min; 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        for axis in 0..3 {
+            if i & (1 << axis) != 0 {
+                corner[axis] = max[axis];
+            }
+        }
+    }
+    corners
+}
+
+fn vertex(position: Point3<f32>, uv: [f32; 2], layer: u32) -> Vertex {
+    Vertex {
+        position: position.into(),
+        uv: [uv[0], uv[1], layer as f32],
+        shade: 1.0,
+    }
+}
+
+/// Line list along the twelve edges of each box.
+fn outline(pos: Point3<i32>, boxes: &[([f32; 3], [f32; 3])]) -> Vec<Vertex> {
+    let mut vertices = Vec::with_capacity(boxes.len() * LINE_VERTICES);
+    for &bounds in boxes {
+        let corners = corners(pos, bounds, OUTLINE_OFFSET);
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    vertices.push(vertex(corners[i], [0.0; 2], 0));
+                    vertices.push(vertex(corners[i | axis], [0.0; 2], 0));
+                }
+            }
+        }
+    }
+    vertices
+}
+
+/// Triangles covering the faces of each box, textured with the crack at `layer` as it would lie
+/// across the whole block.
+fn cracks(pos: Point3<i32>, boxes: &[([f32; 3], [f32; 3])], layer: u32) -> Vec<Vertex> {
+    let base = pos.map(|c| c as f32);
+    let mut vertices = Vec::with_capacity(boxes.len() * CRACK_VERTICES);
+    for &bounds in boxes {
+        let corners = corners(pos, bounds, CRACK_OFFSET);
+        for face in Face::ALL {
+            let normal = face.normal();
+            let axis = normal.iter().position(|&n| n != 0).unwrap();
+            let side = if normal[axis] > 0 { 1 << axis } else { 0 };
+            // The other two axes, spanning the face
+            let (a, b) = match axis {
+                0 => (2, 1),
+                1 => (0, 2),
+                _ => (0, 1),
+            };
+            let quad = [0, 1 << a, (1 << a) | (1 << b), 1 << b].map(|i| {
+                let corner = corners[side | i];
+                let local = corner - base;
+                let v = if b == 1 { 1.0 - local.y } else { local[b] };
+                vertex(corner, [local[a], v], layer)
+            });
+            for i in [0, 1, 2, 0, 2, 3] {
+                vertices.push(quad[i]);
+            }
+        }
+    }
+    vertices
+}
+
+/// An alpha blended pipeline drawing over the blocks in `topology`.
+fn create_pipeline(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+    layout: &PipelineLayout,
+    shaders: &[&Shader],
+    topology: vk::PrimitiveTopology,
+) -> vk::Pipeline {
+    let stages: Vec<_> = shaders.iter().map(|s| s.stage_info()).collect();
+
+    let bindings = [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: mem::size_of::<Vertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+    let attributes = [
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 12,
+        },
+    ];
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&bindings)
+        .vertex_attribute_descriptions(&attributes);
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder().topology(topology);
+
+    // Viewport and scissor follow the render target, see `dynamic_state`
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    let multisample =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+    // Straight alpha over the blocks, keeping the destination's alpha
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState {
+        blend_enable: vk::TRUE,
+        src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ZERO,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::all(),
+    }];
+    let color_blend =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport)
+        .rasterization_state(&rasterization)
+        .multisample_state(&multisample)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blend)
+        .dynamic_state(&dynamic_state)
+        .layout(layout.layout())
+        .render_pass(render_pass)
+        .subpass(0);
+
+    unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)
+            .unwrap()[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outlines_follow_the_block_shape() {
+        let pos = Point3::new(1, 2, 3);
+
+        let cube = outline(pos, &world::Block::Stone.outline());
+        assert_eq!(cube.len(), LINE_VERTICES);
+        for pair in cube.chunks(2) {
+            let [a, b] = [pair[0].position, pair[1].position];
+            let differing = (0..3).filter(|&i| a[i] != b[i]).count();
+            assert_eq!(differing, 1, "edges run along one axis");
+        }
+        let (min, max) = (1.0 - OUTLINE_OFFSET, 2.0 + OUTLINE_OFFSET);
+        assert!(cube
+            .iter()
+            .all(|v| (v.position[0] - min).abs() < 1e-6 || (v.position[0] - max).abs() < 1e-6));
+
+        let stairs = world::Block::StoneStairs(Face::North).outline();
+        assert_eq!(outline(pos, &stairs).len(), 2 * LINE_VERTICES);
+        // The step of stairs facing north is on the north half
+        assert_eq!(stairs[1], ([0.0, 0.5, 0.0], [1.0, 1.0, 0.5]));
+
+        let torch = outline(pos, &world::Block::Torch.outline());
+        let top = torch.iter().map(|v| v.position[1]).fold(0.0, f32::max);
+        assert!((top - (2.0 + 10.0 / 16.0 + OUTLINE_OFFSET)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn cracks_span_the_block() {
+        let vertices = cracks(Point3::new(-1, 0, 0), &world::Block::Dirt.outline(), 7);
+        assert_eq!(vertices.len(), CRACK_VERTICES);
+        assert!(vertices.iter().all(|v| v.uv[2] == 7.0));
+        for v in &vertices {
+            assert!(v.uv[..2]
+                .iter()
+                .all(|&c| (-CRACK_OFFSET - 1e-6..=1.0 + CRACK_OFFSET + 1e-6).contains(&c)));
+        }
+
+        // A slab's side shows the lower half of the texture
+        let slab = cracks(Point3::new(0, 0, 0), &world::Block::StoneSlab.outline(), 0);
+        let side = &slab[12..18];
+        assert!(side.iter().all(|v| v.uv[1] >= 0.5 - CRACK_OFFSET - 1e-6));
+    }
+}
@@ -0,0 +1,498 @@
+//! # Shadows
+//!
+//! Cascaded shadow maps from the sun. The view frustum is split by distance into `CASCADES`
+//! slices, each rendered from the sun's direction into a layer of the shadow map, so shadows
+//! near the camera get more texels than ones far away. Each cascade covers the bounding sphere
+//! of its slice, which keeps its size the same however the camera turns, and moves in steps of
+//! whole texels, so shadow edges don't shimmer as the camera moves.
+
+use std::fmt;
+use std::io::Cursor;
+use std::mem;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+use cgmath::{
+    EuclideanSpace, Matrix4, MetricSpace, Point3, SquareMatrix, Transform, Vector3, Vector4,
+};
+
+use super::{as_bytes, Pass, Vertex};
+use crate::gfx::vulkan::{reload_shader, Descriptors, PipelineLayout, Shader, ShadowMap, Texture};
+use crate::gfx::{Camera, Result, Vulkan, VULKAN_CLIP};
+use crate::shaders::spirv;
+
+const SHADER_VERT: &str = "shadow.vert";
+const SHADER_FRAG: &str = "shadow.frag";
+
+pub const CASCADES: usize = 3;
+/// Far distance of each cascade along the view direction, in blocks. Nothing further away gets
+/// shadows.
+const SPLITS: [f32; CASCADES] = [16.0, 48.0, 128.0];
+/// How far towards the sun beyond a cascade blocks still cast shadows into it.
+const CASTER_DISTANCE: f32 = 128.0;
+
+/// Depth bias against shadow acne, in depth units and scaled by the slope of faces.
+const DEPTH_BIAS: f32 = 4.0;
+const DEPTH_BIAS_SLOPE: f32 = 2.0;
+
+/// Shadow quality, set by the `shadows` setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShadowQuality {
+    #[default]
+    Off,
+    Low,
+    High,
+}
+
+impl ShadowQuality {
+    /// Texels along each side of a cascade. Without shadows the map is a placeholder that never
+    /// gets rendered.
+    fn size(self) -> u32 {
+        match self {
+            ShadowQuality::Off => 1,
+            ShadowQuality::Low => 1024,
+            ShadowQuality::High => 2048,
+        }
+    }
+}
+
+impl FromStr for ShadowQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(ShadowQuality::Off),
+            "low" => Ok(ShadowQuality::Low),
+            "high" => Ok(ShadowQuality::High),
+            _ => Err(format!(
+                "unknown shadow quality '{}', expected off, low or high",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ShadowQuality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ShadowQuality::Off => "off",
+            ShadowQuality::Low => "low",
+            ShadowQuality::High => "high",
+        })
+    }
+}
+
+/// A slice of the view frustum as seen from the sun.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cascade {
+    /// Projection from world space into the cascade's layer of the shadow map.
+    pub view_projection: Matrix4<f32>,
+    /// Distance along the view direction the cascade ends at.
+    pub far: f32,
+}
+
+/// Cascades covering what `camera` sees with shadow maps of `size` texels, lit from the
+/// direction `light` points towards.
+pub fn cascades(
+    camera: &Camera,
+    aspect: f32,
+    light: Vector3<f32>,
+    size: u32,
+) -> [Cascade; CASCADES] {
+    // Looking from the sun, with any up vector that isn't parallel to the light
+    let up = if light.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let rotation = Matrix4::look_to_rh(Point3::origin(), -light, up);
+
+    let mut near = camera.near;
+    SPLITS.map(|far| {
+        let (center, radius) = bounding_sphere(camera, aspect, near, far);
+        near = far;
+
+        // Moving in whole texels, a texel covers the same blocks wherever the cascade is
+        let texel = 2.0 * radius / size as f32;
+        let center = rotation.transform_point(center);
+        let snapped = Vector3::new(
+            (center.x / texel).floor() * texel,
+            (center.y / texel).floor() * texel,
+            center.z,
+        );
+
+        let projection = cgmath::ortho(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            -(radius + CASTER_DISTANCE),
+            radius,
+        );
+        Cascade {
+            view_projection: VULKAN_CLIP
+                * projection
+                * Matrix4::from_translation(-snapped)
+                * rotation,
+            far,
+        }
+    })
+}
+
+/// Sphere around the part of the view frustum from `near` to `far`, with its radius rounded up
+/// to a whole block. The radius doesn't change as the camera turns, only the center moves.
+fn bounding_sphere(camera: &Camera, aspect: f32, near: f32, far: f32) -> (Point3<f32>, f32) {
+    let slice = Camera {
+        near,
+        far,
+        ..*camera
+    };
+    let inverse = slice.view_projection(aspect).invert().unwrap();
+
+    let corners: Vec<_> = [0.0, 1.0]
+        .iter()
+        .flat_map(|&z| [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| (x, y, z)))
+        .map(|(x, y, z)| {
+            let p = inverse * Vector4::new(x, y, z, 1.0);
+            Point3::from_vec(p.truncate() / p.w)
+        })
+        .collect();
+
+    let center = Point3::centroid(&corners);
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max);
+    (center, radius.ceil())
+}
+
+/// Push constants shared by both stages.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Constants {
+    light_view_projection: [f32; 16],
+    alpha_cutoff: f32,
+}
+
+/// Renders block shadows into the shadow map, see `Block::draw_shadows`.
+pub struct Shadows {
+    device: Arc<Device>,
+
+    quality: ShadowQuality,
+    map: ShadowMap,
+
+    shader_vert: Shader,
+    shader_frag: Shader,
+
+    layout: PipelineLayout,
+    _descriptors: Descriptors,
+    texture_set: vk::DescriptorSet,
+
+    pipeline: vk::Pipeline,
+}
+
+impl Shadows {
+    /// Shadows of `quality`, with cutout blocks tested against `texture`.
+    pub fn new(vulkan: &Vulkan, quality: ShadowQuality, texture: &Texture) -> Result<Self> {
+        let device = vulkan.clone_device();
+
+        let mut vert_file = Cursor::new(spirv!("shadow.vert"));
+        let mut frag_file = Cursor::new(spirv!("shadow.frag"));
+
+        let shader_vert = vulkan.create_shader_module(&mut vert_file)?;
+        let shader_frag = vulkan.create_shader_module(&mut frag_file)?;
+
+        let layout = PipelineLayout::new(vulkan, &[&shader_vert, &shader_frag]);
+        let mut descriptors = Descriptors::new(vulkan);
+
+        let texture_set = descriptors.allocate(&layout, 0);
+        descriptors.write_image(
+            texture_set,
+            0,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            texture.image_info(),
+        );
+        descriptors.write_image(
+            texture_set,
+            1,
+            vk::DescriptorType::SAMPLER,
+            texture.sampler_info(),
+        );
+
+        let map = ShadowMap::new(vulkan, quality.size(), CASCADES as u32)?;
+        let pipeline = create_pipeline(
+            &device,
+            map.render_pass(),
+            &layout,
+            &[&shader_vert, &shader_frag],
+        );
+
+        Ok(Self {
+            device,
+
+            quality,
+            map,
+
+            shader_vert,
+            shader_frag,
+
+            layout,
+            _descriptors: descriptors,
+            texture_set,
+
+            pipeline,
+        })
+    }
+
+    pub fn quality(&self) -> ShadowQuality {
+        self.quality
+    }
+
+    pub fn map(&self) -> &ShadowMap {
+        &self.map
+    }
+
+    /// Recreates the shadow map for `quality`, keeping the old one on failure. The old map may
+    /// still be in use by frames in flight, so wait for the device before calling.
+    pub fn set_quality(&mut self, vulkan: &Vulkan, quality: ShadowQuality) -> Result<()> {
+        self.map = ShadowMap::new(vulkan, quality.size(), CASCADES as u32)?;
+        self.quality = quality;
+        self.rebuild_pipeline();
+        Ok(())
+    }
+
+    /// Swaps in a recompiled shader and rebuilds the pipeline if `name` is one of ours.
+    /// Returns whether the shader was used.
+    pub fn reload_shader(&mut self, vulkan: &Vulkan, name: &str, code: &[u32]) -> bool {
+        reload_shader(
+            self,
+            vulkan,
+            name,
+            code,
+            |renderer| {
+                [
+                    (SHADER_VERT, &mut renderer.shader_vert),
+                    (SHADER_FRAG, &mut renderer.shader_frag),
+                ]
+            },
+            |shadows, _| {
+                unsafe { shadows.device.device_wait_idle().unwrap() };
+                shadows.rebuild_pipeline();
+            },
+        )
+    }
+
+    fn rebuild_pipeline(&mut self) {
+        unsafe { self.device.destroy_pipeline(self.pipeline, None) };
+
+        self.pipeline = create_pipeline(
+            &self.device,
+            self.map.render_pass(),
+            &self.layout,
+            &[&self.shader_vert, &self.shader_frag],
+        );
+    }
+
+    /// Records a pass rendering `cascade` into layer `layer` of the map, outside other render
+    /// passes. `draw` records the draws of each pass blocks cast shadows in, with the vertex and
+    /// index buffers bound.
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layer: usize,
+        cascade: &Cascade,
+        mut draw: impl FnMut(Pass),
+    ) {
+        self.map.render_layer(command_buffer, layer, || unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout.layout(),
+                0,
+                &[self.texture_set],
+                &[],
+            );
+
+            for pass in [Pass::Opaque, Pass::Cutout] {
+                let constants = Constants {
+                    light_view_projection: *cascade.view_projection.as_ref(),
+                    alpha_cutoff: pass.alpha_cutoff(),
+                };
+                let range = self.layout.push_constants().unwrap();
+                self.device.cmd_push_constants(
+                    command_buffer,
+                    self.layout.layout(),
+                    range.stage_flags,
+                    0,
+                    as_bytes(&constants),
+                );
+                draw(pass);
+            }
+        });
+    }
+}
+
+impl Drop for Shadows {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_pipeline(self.pipeline, None) };
+    }
+}
+
+/// A depth-only pipeline drawing block meshes, without colors or translucent blocks.
+fn create_pipeline(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    layout: &PipelineLayout,
+    shaders: &[&Shader],
+) -> vk::Pipeline {
+    let stages: Vec<_> = shaders.iter().map(|s| s.stage_info()).collect();
+
+    let bindings = [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: mem::size_of::<Vertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+    let attributes = [
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 12,
+        },
+    ];
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&bindings)
+        .vertex_attribute_descriptions(&attributes);
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    // Viewport and scissor cover the map, see `ShadowMap::render_layer`
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(true)
+        .depth_bias_constant_factor(DEPTH_BIAS)
+        .depth_bias_slope_factor(DEPTH_BIAS_SLOPE)
+        .line_width(1.0);
+
+    let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+    let color_blend = vk::PipelineColorBlendStateCreateInfo::builder();
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport)
+        .rasterization_state(&rasterization)
+        .multisample_state(&multisample)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blend)
+        .dynamic_state(&dynamic_state)
+        .layout(layout.layout())
+        .render_pass(render_pass)
+        .subpass(0);
+
+    unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)
+            .unwrap()[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::look_at(Point3::new(-24.0, 20.0, 24.0), Point3::new(0.0, 6.0, 0.0))
+    }
+
+    fn project(m: Matrix4<f32>, p: Point3<f32>) -> Point3<f32> {
+        Point3::from_homogeneous(m * p.to_homogeneous())
+    }
+
+    #[test]
+    fn cascades_cover_their_slices() {
+        let camera = camera();
+        let light = Vector3::new(0.3, 0.8, 0.2).normalize();
+        let cascades = cascades(&camera, 16.0 / 9.0, light, 1024);
+
+        let mut near = camera.near;
+        for cascade in &cascades {
+            let slice = Camera {
+                near,
+                far: cascade.far,
+                ..camera
+            };
+            let inverse = slice.view_projection(16.0 / 9.0).invert().unwrap();
+            for &(x, y, z) in &[
+                (-1.0, -1.0, 0.0),
+                (1.0, 1.0, 0.0),
+                (-1.0, 1.0, 1.0),
+                (1.0, -1.0, 1.0),
+            ] {
+                let corner = project(inverse, Point3::new(x, y, z));
+                let p = project(cascade.view_projection, corner);
+                assert!(p.x.abs() <= 1.0 && p.y.abs() <= 1.0, "{:?}", p);
+                assert!((0.0..=1.0).contains(&p.z), "{:?}", p);
+
+                // Casters between the slice and the sun are in the map too
+                let caster = project(cascade.view_projection, corner + light * 64.0);
+                assert!(caster.z >= 0.0, "{:?}", caster);
+            }
+            near = cascade.far;
+        }
+    }
+
+    #[test]
+    fn cascades_move_in_whole_texels() {
+        let size = 2048;
+        let light = Vector3::new(0.3, 0.8, 0.2).normalize();
+        let mut camera = camera();
+        let before = cascades(&camera, 1.5, light, size);
+        camera.position += Vector3::new(0.37, 0.11, -0.53);
+        let after = cascades(&camera, 1.5, light, size);
+
+        for (before, after) in before.iter().zip(&after) {
+            // A fixed point lands on the same spot within a texel, shifted by whole texels
+            let a = project(before.view_projection, Point3::new(3.0, 5.0, 7.0));
+            let b = project(after.view_projection, Point3::new(3.0, 5.0, 7.0));
+            for shift in [(b.x - a.x), (b.y - a.y)] {
+                let texels = shift * size as f32 / 2.0;
+                assert!((texels - texels.round()).abs() < 0.01, "{}", texels);
+            }
+        }
+    }
+}
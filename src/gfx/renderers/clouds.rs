@@ -0,0 +1,609 @@
+//! # Cloud renderer
+//!
+//! Draws a layer of clouds at a fixed altitude, drifting along with world time. The clouds come
+//! from a tiling noise texture generated at startup, every texel above `COVERAGE` being a cloud
+//! cell. Fast clouds are flat quads, fancy ones are boxes shaded by the sun's direction. The
+//! meshed tile repeats around the camera, so the layer seems endless, and fades out in the fog.
+
+use std::fmt;
+use std::io::Cursor;
+use std::mem;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+use cgmath::{Matrix4, Point3};
+
+use crate::gfx::vulkan::{
+    reload_shader, Buffer, Descriptors, OutputEncoding, PipelineLayout, Shader, FRAMES_IN_FLIGHT,
+};
+use crate::gfx::{Fog, Result, Vulkan};
+use crate::shaders::spirv;
+use crate::world::Time;
+
+/// Altitude of the bottom of the clouds.
+const CLOUD_HEIGHT: f32 = 128.0;
+
+const SHADER_VERT: &str = "clouds.vert";
+const SHADER_FRAG: &str = "clouds.frag";
+
+/// Texels of the noise texture along each side.
+const NOISE_SIZE: usize = 32;
+const NOISE_SEED: u32 = 0x5eed;
+/// Noise above this is cloud.
+const COVERAGE: f32 = 0.55;
+
+/// Size of a cloud cell in blocks, and how thick fancy clouds are.
+const CELL_SIZE: f32 = 12.0;
+const THICKNESS: f32 = 4.0;
+/// How fast clouds drift east, in blocks per second.
+const SPEED: f64 = 1.5;
+
+const DAY_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+const NIGHT_COLOR: [f32; 3] = [0.05, 0.06, 0.1];
+
+/// Cloud quality, set by the `clouds` setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CloudMode {
+    /// A flat, slightly see-through layer.
+    Fast,
+    /// Solid boxes with sides.
+    #[default]
+    Fancy,
+}
+
+impl CloudMode {
+    fn opacity(self) -> f32 {
+        match self {
+            CloudMode::Fast => 0.8,
+            CloudMode::Fancy => 1.0,
+        }
+    }
+}
+
+impl FromStr for CloudMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(CloudMode::Fast),
+            "fancy" => Ok(CloudMode::Fancy),
+            _ => Err(format!(
+                "unknown clouds quality '{}', expected fast or fancy",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CloudMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CloudMode::Fast => "fast",
+            CloudMode::Fancy => "fancy",
+        })
+    }
+}
+
+pub struct Clouds {
+    device: Arc<Device>,
+
+    /// Cloud density of each cell of the tile, row by row.
+    noise: Vec<f32>,
+    mode: CloudMode,
+
+    shader_vert: Shader,
+    shader_frag: Shader,
+
+    layout: PipelineLayout,
+    _descriptors: Descriptors,
+    /// The uniform buffer of each frame in flight.
+    sets: Vec<vk::DescriptorSet>,
+    uniforms: Vec<Buffer>,
+
+    pipeline: vk::Pipeline,
+    surface_format: vk::SurfaceFormatKHR,
+    samples: vk::SampleCountFlags,
+
+    vertices: Buffer,
+    indices: Buffer,
+    index_count: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+#[derive(Debug, Default)]
+struct Mesh {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+/// Uniform block shared by both stages, laid out as std140.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Uniforms {
+    view_projection: [f32; 16],
+    /// Corner of the first tile and the tile's size.
+    origin: [f32; 4],
+    eye: [f32; 4],
+    /// Direction towards the sun and daylight.
+    sun: [f32; 4],
+    /// Color and opacity.
+    color: [f32; 4],
+    fog_color: [f32; 4],
+    /// Fog start and end.
+    fog: [f32; 4],
+    /// Output encoding.
+    params: [u32; 4],
+}
+
+impl Clouds {
+    pub fn new(vulkan: &Vulkan) -> Result<Self> {
+        let device = vulkan.clone_device();
+
+        // === SHADERS ===
+
+        let mut vert_file = Cursor::new(spirv!("clouds.vert"));
+        let mut frag_file = Cursor::new(spirv!("clouds.frag"));
+
+        let shader_vert = vulkan.create_shader_module(&mut vert_file)?;
+        let shader_frag = vulkan.create_shader_module(&mut frag_file)?;
+
+        // === DESCRIPTORS ===
+
+        let layout = PipelineLayout::new(vulkan, &[&shader_vert, &shader_frag]);
+        let mut descriptors = Descriptors::new(vulkan);
+
+        let uniforms: Vec<_> = (0..FRAMES_IN_FLIGHT)
+            .map(|_| {
+                Buffer::new(
+                    vulkan,
+                    mem::size_of::<Uniforms>() as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )
+            })
+            .collect();
+        let sets = uniforms
+            .iter()
+            .map(|uniforms| {
+                let set = descriptors.allocate(&layout, 0);
+                descriptors.write_buffer(
+                    set,
+                    0,
+                    vk::DescriptorType::UNIFORM_BUFFER,
+                    vk::DescriptorBufferInfo {
+                        buffer: uniforms.buffer(),
+                        offset: 0,
+                        range: vk::WHOLE_SIZE,
+                    },
+                );
+                set
+            })
+            .collect();
+
+        // === PIPELINE ===
+
+        let pipeline = create_pipeline(
+            &device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &layout,
+            &[&shader_vert, &shader_frag],
+        );
+
+        // === MESH ===
+
+        let noise = noise(NOISE_SIZE, NOISE_SEED);
+        let mode = CloudMode::default();
+        let mesh = mesh(&noise, NOISE_SIZE, mode);
+
+        Ok(Self {
+            device,
+
+            noise,
+            mode,
+
+            shader_vert,
+            shader_frag,
+
+            layout,
+            _descriptors: descriptors,
+            sets,
+            uniforms,
+
+            pipeline,
+            surface_format: vulkan.surface_format(),
+            samples: vulkan.samples(),
+
+            vertices: Buffer::device_local(
+                vulkan,
+                &mesh.vertices,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            ),
+            indices: Buffer::device_local(
+                vulkan,
+                &mesh.indices,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+            ),
+            index_count: mesh.indices.len() as u32,
+        })
+    }
+
+    /// Swaps in a recompiled shader and rebuilds the pipeline if `name` is one of ours.
+    /// Returns whether the shader was used.
+    pub fn reload_shader(&mut self, vulkan: &Vulkan, name: &str, code: &[u32]) -> bool {
+        reload_shader(
+            self,
+            vulkan,
+            name,
+            code,
+            |renderer| {
+                [
+                    (SHADER_VERT, &mut renderer.shader_vert),
+                    (SHADER_FRAG, &mut renderer.shader_frag),
+                ]
+            },
+            Self::rebuild_pipeline,
+        )
+    }
+
+    /// Follows changes of the surface format and multisampling, call before drawing.
+    pub fn update_surface(&mut self, vulkan: &Vulkan) {
+        if vulkan.follow_surface(&mut self.surface_format, &mut self.samples) {
+            self.rebuild_pipeline(vulkan);
+        }
+    }
+
+    fn rebuild_pipeline(&mut self, vulkan: &Vulkan) {
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+            self.device.destroy_pipeline(self.pipeline, None);
+        }
+
+        self.pipeline = create_pipeline(
+            &self.device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &self.layout,
+            &[&self.shader_vert, &self.shader_frag],
+        );
+    }
+
+    /// Remeshes the clouds if their quality changed.
+    pub fn set_mode(&mut self, vulkan: &Vulkan, mode: CloudMode) {
+        if mode == self.mode {
+            return;
+        }
+        self.mode = mode;
+
+        let mesh = mesh(&self.noise, NOISE_SIZE, mode);
+        unsafe { self.device.device_wait_idle().unwrap() };
+        self.vertices =
+            Buffer::device_local(vulkan, &mesh.vertices, vk::BufferUsageFlags::VERTEX_BUFFER);
+        self.indices =
+            Buffer::device_local(vulkan, &mesh.indices, vk::BufferUsageFlags::INDEX_BUFFER);
+        self.index_count = mesh.indices.len() as u32;
+    }
+
+    /// Records the draw commands, inside the render pass begun by `Vulkan::draw_stages` after the
+    /// sky.
+    pub fn draw(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        view_projection: Matrix4<f32>,
+        eye: Point3<f32>,
+        time: Time,
+        fog: Fog,
+    ) {
+        let frame = frame % FRAMES_IN_FLIGHT;
+
+        let daylight = time.daylight();
+        let sun = time.sun_direction();
+        let color = [0, 1, 2].map(|i| NIGHT_COLOR[i] + (DAY_COLOR[i] - NIGHT_COLOR[i]) * daylight);
+        let [r, g, b] = fog.color;
+        let tile = tile_size(NOISE_SIZE);
+        let [x, y, z] = origin(eye, time, tile);
+        self.uniforms[frame].write(&[Uniforms {
+            view_projection: *view_projection.as_ref(),
+            origin: [x, y, z, tile],
+            eye: [eye.x, eye.y, eye.z, 1.0],
+            sun: [sun.x, sun.y, sun.z, daylight],
+            color: [color[0], color[1], color[2], self.mode.opacity()],
+            fog_color: [r, g, b, 1.0],
+            fog: [fog.start, fog.end, 0.0, 0.0],
+            params: [OutputEncoding::of(self.surface_format) as u32, 0, 0, 0],
+        }]);
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout.layout(),
+                0,
+                &[self.sets[frame]],
+                &[],
+            );
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertices.buffer()], &[0]);
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                self.indices.buffer(),
+                0,
+                vk::IndexType::UINT32,
+            );
+            // A 3×3 grid of tiles, see `origin`
+            self.device
+                .cmd_draw_indexed(command_buffer, self.index_count, 9, 0, 0, 0);
+        }
+    }
+}
+
+impl Drop for Clouds {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.pipeline, None);
+        }
+    }
+}
+
+/// Tiling value noise in `0..1`, `size` texels square, summed over a few octaves.
+fn noise(size: usize, seed: u32) -> Vec<f32> {
+    const OCTAVES: [(usize, f32); 3] = [(4, 0.6), (8, 0.3), (16, 0.1)];
+
+    let mut texels = vec![0.0; size * size];
+    for (octave, &(lattice, amplitude)) in OCTAVES.iter().enumerate() {
+        let value = |x: usize, y: usize| {
+            hash(
+                (x % lattice) as u32,
+                (y % lattice) as u32,
+                seed + octave as u32,
+            )
+        };
+
+        for (i, texel) in texels.iter_mut().enumerate() {
+            let fx = (i % size) as f32 * lattice as f32 / size as f32;
+            let fy = (i / size) as f32 * lattice as f32 / size as f32;
+            let (x, y) = (fx as usize, fy as usize);
+            let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+            let (tx, ty) = (smooth(fx.fract()), smooth(fy.fract()));
+
+            let top = value(x, y) + (value(x + 1, y) - value(x, y)) * tx;
+            let bottom = value(x, y + 1) + (value(x + 1, y + 1) - value(x, y + 1)) * tx;
+            *texel += (top + (bottom - top) * ty) * amplitude;
+        }
+    }
+    texels
+}
+
+/// Pseudo random value in `0..1` of a lattice point.
+fn hash(x: u32, y: u32, seed: u32) -> f32 {
+    let mut h = x
+        .wrapping_mul(0x27d4_eb2d)
+        .wrapping_add(y.wrapping_mul(0x1656_67b1))
+        .wrapping_add(seed.wrapping_mul(0x9e37_79b9));
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    (h & 0xff_ffff) as f32 / 0x100_0000 as f32
+}
+
+/// Meshes the cloud cells of a `size` square tile of `noise`. Sides between cells are left out,
+/// wrapping around the tile's edges since tiles are repeated.
+fn mesh(noise: &[f32], size: usize, mode: CloudMode) -> Mesh {
+    let cloud = |x: i32, z: i32| {
+        let (x, z) = (x.rem_euclid(size as i32), z.rem_euclid(size as i32));
+        noise[z as usize * size + x as usize] > COVERAGE
+    };
+
+    let mut mesh = Mesh::default();
+    for z in 0..size as i32 {
+        for x in 0..size as i32 {
+            if !cloud(x, z) {
+                continue;
+            }
+
+            let (x0, z0) = (x as f32 * CELL_SIZE, z as f32 * CELL_SIZE);
+            let (x1, z1) = (x0 + CELL_SIZE, z0 + CELL_SIZE);
+            if mode == CloudMode::Fast {
+                push_quad(
+                    &mut mesh,
+                    [[x0, 0.0, z0], [x0, 0.0, z1], [x1, 0.0, z1], [x1, 0.0, z0]],
+                    [0.0, 1.0, 0.0],
+                );
+                continue;
+            }
+
+            let (y0, y1) = (0.0, THICKNESS);
+            #[rustfmt::skip]
+            let faces = [
+                (true, [[x0, y1, z0], [x0, y1, z1], [x1, y1, z1], [x1, y1, z0]], [0.0, 1.0, 0.0]),
+                (true, [[x0, y0, z0], [x1, y0, z0], [x1, y0, z1], [x0, y0, z1]], [0.0, -1.0, 0.0]),
+                (!cloud(x, z - 1), [[x0, y0, z0], [x0, y1, z0], [x1, y1, z0], [x1, y0, z0]], [0.0, 0.0, -1.0]),
+                (!cloud(x, z + 1), [[x0, y0, z1], [x1, y0, z1], [x1, y1, z1], [x0, y1, z1]], [0.0, 0.0, 1.0]),
+                (!cloud(x + 1, z), [[x1, y0, z0], [x1, y1, z0], [x1, y1, z1], [x1, y0, z1]], [1.0, 0.0, 0.0]),
+                (!cloud(x - 1, z), [[x0, y0, z0], [x0, y0, z1], [x0, y1, z1], [x0, y1, z0]], [-1.0, 0.0, 0.0]),
+            ];
+            for (visible, corners, normal) in faces {
+                if visible {
+                    push_quad(&mut mesh, corners, normal);
+                }
+            }
+        }
+    }
+    mesh
+}
+
+fn push_quad(mesh: &mut Mesh, corners: [[f32; 3]; 4], normal: [f32; 3]) {
+    let base = mesh.vertices.len() as u32;
+    mesh.vertices
+        .extend(corners.iter().map(|&position| Vertex { position, normal }));
+    mesh.indices
+        .extend([0, 1, 2, 0, 2, 3].iter().map(|i| base + i));
+}
+
+fn tile_size(noise_size: usize) -> f32 {
+    noise_size as f32 * CELL_SIZE
+}
+
+/// Corner of the first of the 3×3 tiles drawn, such that the camera is above or below the
+/// middle one, with the clouds drifted east by `time`.
+fn origin(eye: Point3<f32>, time: Time, tile: f32) -> [f32; 3] {
+    // Wrapped in double precision, time grows large
+    let drift = (time.elapsed().as_secs_f64() * SPEED % tile as f64) as f32;
+
+    let x = ((eye.x - drift) / tile).floor() * tile + drift - tile;
+    let z = (eye.z / tile).floor() * tile - tile;
+    [x, CLOUD_HEIGHT, z]
+}
+
+fn create_pipeline(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+    layout: &PipelineLayout,
+    shaders: &[&Shader],
+) -> vk::Pipeline {
+    let stages: Vec<_> = shaders.iter().map(|s| s.stage_info()).collect();
+
+    let bindings = [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: mem::size_of::<Vertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+    let attributes = [
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 12,
+        },
+    ];
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&bindings)
+        .vertex_attribute_descriptions(&attributes);
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    // Viewport and scissor follow the render target, see `dynamic_state`
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    let multisample =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
+
+    // Drawn before blocks, writing depth hides the few far away ones behind clouds
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+    // Straight alpha over the sky, keeping the destination's alpha
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState {
+        blend_enable: vk::TRUE,
+        src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ZERO,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::all(),
+    }];
+    let color_blend =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport)
+        .rasterization_state(&rasterization)
+        .multisample_state(&multisample)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blend)
+        .dynamic_state(&dynamic_state)
+        .layout(layout.layout())
+        .render_pass(render_pass)
+        .subpass(0);
+
+    unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)
+            .unwrap()[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn noise_covers_part_of_the_sky() {
+        let noise = noise(NOISE_SIZE, NOISE_SEED);
+        assert!(noise.iter().all(|&n| (0.0..1.0).contains(&n)));
+
+        let cells = noise.iter().filter(|&&n| n > COVERAGE).count();
+        let coverage = cells as f32 / noise.len() as f32;
+        assert!(coverage > 0.15 && coverage < 0.6, "coverage {}", coverage);
+    }
+
+    #[test]
+    fn fancy_clouds_cull_sides_between_cells() {
+        // Two cells on opposite edges of the tile, which touch since tiles repeat
+        let mut noise = vec![0.0; 16];
+        noise[0] = 1.0;
+        noise[3] = 1.0;
+
+        let fast = mesh(&noise, 4, CloudMode::Fast);
+        assert_eq!(fast.vertices.len(), 2 * 4);
+
+        // Tops, bottoms and three sides each
+        let fancy = mesh(&noise, 4, CloudMode::Fancy);
+        assert_eq!(fancy.vertices.len(), (2 + 2 + 6) * 4);
+        assert_eq!(fancy.indices.len(), (2 + 2 + 6) * 6);
+    }
+
+    #[test]
+    fn camera_stays_over_the_middle_tile() {
+        let tile = tile_size(NOISE_SIZE);
+        for &(x, z, secs) in &[(0.0, 0.0, 0), (1000.0, -517.0, 37), (-3.0, 5000.0, 86_400)] {
+            let time = Time::new(Duration::from_secs(secs));
+            let [ox, oy, oz] = origin(Point3::new(x, 100.0, z), time, tile);
+            assert!(x >= ox + tile && x < ox + 2.0 * tile);
+            assert!(z >= oz + tile && z < oz + 2.0 * tile);
+            assert_eq!(oy, CLOUD_HEIGHT);
+        }
+    }
+}
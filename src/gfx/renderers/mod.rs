@@ -1 +1,9 @@
-mod block;
\ No newline at end of file
+mod block;
+mod clouds;
+mod particles;
+mod sky;
+
+pub use block::{Block, Leaves, Selected, ShadowQuality};
+pub use clouds::{CloudMode, Clouds};
+pub use particles::Particles;
+pub use sky::Sky;
@@ -0,0 +1,422 @@
+//! # Particle renderer
+//!
+//! Draws the particles of a `ParticleSystem` as billboards facing the camera, one instance per
+//! particle. Debris shows part of its block's texture, so the texture array holds the block
+//! textures along with the particles' own from `assets/particles/`. Particles are blended back
+//! to front after the blocks, tested against but not writing depth.
+
+use std::io::Cursor;
+use std::mem;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+use cgmath::{MetricSpace, Vector3};
+
+use crate::gfx::textures::{TextureArray, TEXTURES_DIR};
+use crate::gfx::vulkan::{
+    reload_shader, Buffer, Descriptors, OutputEncoding, PipelineLayout, Shader, Texture,
+    FRAMES_IN_FLIGHT,
+};
+use crate::gfx::{Camera, Fog, Result, Vulkan};
+use crate::shaders::spirv;
+use crate::world::{Face, Particle, ParticleSystem, Sprite, Time, MAX_PARTICLES};
+
+pub const PARTICLES_DIR: &str = "assets/particles";
+
+const SHADER_VERT: &str = "particles.vert";
+const SHADER_FRAG: &str = "particles.frag";
+
+pub struct Particles {
+    device: Arc<Device>,
+
+    textures: TextureArray,
+    _texture: Texture,
+
+    shader_vert: Shader,
+    shader_frag: Shader,
+
+    layout: PipelineLayout,
+    _descriptors: Descriptors,
+    /// Textures and the uniform buffer of each frame in flight.
+    sets: Vec<vk::DescriptorSet>,
+    uniforms: Vec<Buffer>,
+    /// Instances of each frame in flight, with room for every particle.
+    instances: Vec<Buffer>,
+
+    pipeline: vk::Pipeline,
+    surface_format: vk::SurfaceFormatKHR,
+    samples: vk::SampleCountFlags,
+}
+
+/// Per instance attributes of a particle.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Instance {
+    /// Center and texture layer.
+    position: [f32; 4],
+    /// Corner and size of the part of the texture shown.
+    uv: [f32; 4],
+    /// Width, height, brightness and opacity.
+    size: [f32; 4],
+}
+
+/// Uniform block shared by both stages, laid out as std140.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Uniforms {
+    view_projection: [f32; 16],
+    /// Camera axes billboards are spanned by.
+    right: [f32; 4],
+    up: [f32; 4],
+    eye: [f32; 4],
+    fog_color: [f32; 4],
+    /// Fog start and end.
+    fog: [f32; 4],
+    /// Output encoding.
+    params: [u32; 4],
+}
+
+impl Particles {
+    pub fn new(vulkan: &Vulkan) -> Result<Self> {
+        let device = vulkan.clone_device();
+
+        // === SHADERS ===
+
+        let mut vert_file = Cursor::new(spirv!("particles.vert"));
+        let mut frag_file = Cursor::new(spirv!("particles.frag"));
+
+        let shader_vert = vulkan.create_shader_module(&mut vert_file)?;
+        let shader_frag = vulkan.create_shader_module(&mut frag_file)?;
+
+        // === TEXTURES ===
+
+        let textures = TextureArray::builder()
+            .dir(TEXTURES_DIR)
+            .dir(PARTICLES_DIR)
+            .build();
+        let texture = Texture::new(vulkan, &textures);
+
+        // === DESCRIPTORS ===
+
+        let layout = PipelineLayout::new(vulkan, &[&shader_vert, &shader_frag]);
+        let mut descriptors = Descriptors::new(vulkan);
+
+        let host_buffer = |size: usize, usage| {
+            Buffer::new(
+                vulkan,
+                size as vk::DeviceSize,
+                usage,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+        };
+        let uniforms: Vec<_> = (0..FRAMES_IN_FLIGHT)
+            .map(|_| {
+                host_buffer(
+                    mem::size_of::<Uniforms>(),
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                )
+            })
+            .collect();
+        let instances = (0..FRAMES_IN_FLIGHT)
+            .map(|_| {
+                host_buffer(
+                    mem::size_of::<Instance>() * MAX_PARTICLES,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                )
+            })
+            .collect();
+        let sets = uniforms
+            .iter()
+            .map(|uniforms| {
+                let set = descriptors.allocate(&layout, 0);
+                descriptors.write_image(
+                    set,
+                    0,
+                    vk::DescriptorType::SAMPLED_IMAGE,
+                    texture.image_info(),
+                );
+                descriptors.write_image(
+                    set,
+                    1,
+                    vk::DescriptorType::SAMPLER,
+                    texture.sampler_info(),
+                );
+                descriptors.write_buffer(
+                    set,
+                    2,
+                    vk::DescriptorType::UNIFORM_BUFFER,
+                    vk::DescriptorBufferInfo {
+                        buffer: uniforms.buffer(),
+                        offset: 0,
+                        range: vk::WHOLE_SIZE,
+                    },
+                );
+                set
+            })
+            .collect();
+
+        // === PIPELINE ===
+
+        let pipeline = create_pipeline(
+            &device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &layout,
+            &[&shader_vert, &shader_frag],
+        );
+
+        Ok(Self {
+            device,
+
+            textures,
+            _texture: texture,
+
+            shader_vert,
+            shader_frag,
+
+            layout,
+            _descriptors: descriptors,
+            sets,
+            uniforms,
+            instances,
+
+            pipeline,
+            surface_format: vulkan.surface_format(),
+            samples: vulkan.samples(),
+        })
+    }
+
+    /// Swaps in a recompiled shader and rebuilds the pipeline if `name` is one of ours.
+    /// Returns whether the shader was used.
+    pub fn reload_shader(&mut self, vulkan: &Vulkan, name: &str, code: &[u32]) -> bool {
+        reload_shader(
+            self,
+            vulkan,
+            name,
+            code,
+            |renderer| {
+                [
+                    (SHADER_VERT, &mut renderer.shader_vert),
+                    (SHADER_FRAG, &mut renderer.shader_frag),
+                ]
+            },
+            Self::rebuild_pipeline,
+        )
+    }
+
+    /// Follows changes of the surface format and multisampling, call before drawing.
+    pub fn update_surface(&mut self, vulkan: &Vulkan) {
+        if vulkan.follow_surface(&mut self.surface_format, &mut self.samples) {
+            self.rebuild_pipeline(vulkan);
+        }
+    }
+
+    fn rebuild_pipeline(&mut self, vulkan: &Vulkan) {
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+            self.device.destroy_pipeline(self.pipeline, None);
+        }
+
+        self.pipeline = create_pipeline(
+            &self.device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &self.layout,
+            &[&self.shader_vert, &self.shader_frag],
+        );
+    }
+
+    /// Records the draw commands, inside the render pass begun by `Vulkan::draw_stages` after the
+    /// blocks.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        camera: &Camera,
+        aspect: f32,
+        system: &ParticleSystem,
+        time: Time,
+        fog: Fog,
+    ) {
+        let frame = frame % FRAMES_IN_FLIGHT;
+
+        let eye = camera.position;
+        let mut particles: Vec<_> = system.particles().iter().collect();
+        if particles.is_empty() {
+            return;
+        }
+        // Back to front, blending over the ones behind
+        particles.sort_by(|a, b| {
+            let (a, b) = (a.position.distance2(eye), b.position.distance2(eye));
+            b.total_cmp(&a)
+        });
+        let brightness = time.sky_light();
+        let instances: Vec<_> = particles
+            .iter()
+            .filter_map(|particle| self.instance(particle, brightness))
+            .collect();
+        self.instances[frame].write(&instances);
+
+        // The view's rows are the camera's axes
+        let view = camera.view();
+        let right = Vector3::new(view.x.x, view.y.x, view.z.x);
+        let up = Vector3::new(view.x.y, view.y.y, view.z.y);
+        let [r, g, b] = fog.color;
+        self.uniforms[frame].write(&[Uniforms {
+            view_projection: *camera.view_projection(aspect).as_ref(),
+            right: [right.x, right.y, right.z, 0.0],
+            up: [up.x, up.y, up.z, 0.0],
+            eye: [eye.x, eye.y, eye.z, 1.0],
+            fog_color: [r, g, b, 1.0],
+            fog: [fog.start, fog.end, 0.0, 0.0],
+            params: [OutputEncoding::of(self.surface_format) as u32, 0, 0, 0],
+        }]);
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout.layout(),
+                0,
+                &[self.sets[frame]],
+                &[],
+            );
+            self.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[self.instances[frame].buffer()],
+                &[0],
+            );
+            self.device
+                .cmd_draw(command_buffer, 6, instances.len() as u32, 0, 0);
+        }
+    }
+
+    /// Attributes of `particle`, `None` if its texture is missing.
+    fn instance(&self, particle: &Particle, brightness: f32) -> Option<Instance> {
+        let name = match particle.sprite {
+            Sprite::Debris(block) => block.texture(Face::North)?,
+            Sprite::Smoke => "smoke",
+            Sprite::Rain => "rain",
+            Sprite::Splash => "splash",
+        };
+        let texture = self.textures.texture(name)?;
+        // Smoke plays its frames over its life, block textures stay on their first one
+        let frame = match particle.sprite {
+            Sprite::Smoke => {
+                let frame = (particle.progress() * texture.frames as f32) as u32;
+                frame.min(texture.frames - 1)
+            }
+            _ => 0,
+        };
+
+        let p = particle.position;
+        let [width, height] = particle.size;
+        Some(Instance {
+            position: [p.x, p.y, p.z, (texture.layer + frame) as f32],
+            uv: particle.uv,
+            size: [width, height, brightness, particle.alpha()],
+        })
+    }
+}
+
+impl Drop for Particles {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.pipeline, None);
+        }
+    }
+}
+
+fn create_pipeline(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+    layout: &PipelineLayout,
+    shaders: &[&Shader],
+) -> vk::Pipeline {
+    let stages: Vec<_> = shaders.iter().map(|s| s.stage_info()).collect();
+
+    // Quad corners come from the vertex index, the rest from each particle's instance
+    let bindings = [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: mem::size_of::<Instance>() as u32,
+        input_rate: vk::VertexInputRate::INSTANCE,
+    }];
+    let attributes = [0, 1, 2].map(|location| vk::VertexInputAttributeDescription {
+        location,
+        binding: 0,
+        format: vk::Format::R32G32B32A32_SFLOAT,
+        offset: location * 16,
+    });
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&bindings)
+        .vertex_attribute_descriptions(&attributes);
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    // Viewport and scissor follow the render target, see `dynamic_state`
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    let multisample =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+    // Straight alpha over what's behind, keeping the destination's alpha
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState {
+        blend_enable: vk::TRUE,
+        src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ZERO,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::all(),
+    }];
+    let color_blend =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport)
+        .rasterization_state(&rasterization)
+        .multisample_state(&multisample)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blend)
+        .dynamic_state(&dynamic_state)
+        .layout(layout.layout())
+        .render_pass(render_pass)
+        .subpass(0);
+
+    unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)
+            .unwrap()[0]
+    }
+}
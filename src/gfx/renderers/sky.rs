@@ -0,0 +1,448 @@
+//! # Sky renderer
+//!
+//! Draws the sky behind everything else: a gradient dome from the horizon to the zenith with
+//! stars at night, and the sun and moon as billboards crossing it. Colors, the sun's position
+//! and the moon's phase all follow the world's time of day.
+
+use std::io::Cursor;
+use std::mem;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+use cgmath::{Matrix4, Point3, SquareMatrix, Vector3};
+
+use crate::gfx::textures::TextureArray;
+use crate::gfx::vulkan::{
+    reload_shader, Buffer, Descriptors, OutputEncoding, PipelineLayout, Shader, Texture,
+    FRAMES_IN_FLIGHT,
+};
+use crate::gfx::{Camera, Fog, Result, Vulkan};
+use crate::shaders::spirv;
+use crate::world::Time;
+
+pub const SKY_DIR: &str = "assets/sky";
+
+const SHADER_VERT: &str = "sky.vert";
+const SHADER_FRAG: &str = "sky.frag";
+
+/// Sky textures are larger than block ones, the sun and moon cover a good part of the screen.
+const SKY_TEXTURE_SIZE: u32 = 32;
+
+/// Half the size of the billboards, relative to their distance.
+const SUN_SIZE: f32 = 0.12;
+const MOON_SIZE: f32 = 0.09;
+
+const DAY_ZENITH: [f32; 3] = [0.18, 0.36, 0.85];
+const DAY_HORIZON: [f32; 3] = [0.5, 0.7, 1.0];
+const NIGHT_ZENITH: [f32; 3] = [0.004, 0.006, 0.02];
+const NIGHT_HORIZON: [f32; 3] = [0.02, 0.03, 0.07];
+const SUNSET_GLOW: [f32; 3] = [1.0, 0.4, 0.1];
+
+pub struct Sky {
+    device: Arc<Device>,
+
+    textures: TextureArray,
+    _texture: Texture,
+
+    shader_vert: Shader,
+    shader_frag: Shader,
+
+    layout: PipelineLayout,
+    _descriptors: Descriptors,
+    /// Textures and the uniform buffer of each frame in flight.
+    sets: Vec<vk::DescriptorSet>,
+    uniforms: Vec<Buffer>,
+
+    pipeline: vk::Pipeline,
+    surface_format: vk::SurfaceFormatKHR,
+    samples: vk::SampleCountFlags,
+}
+
+/// What a draw call renders, picked by the push constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Body {
+    Dome,
+    Sun,
+    Moon,
+}
+
+/// Uniform block shared by both stages, laid out as std140.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Uniforms {
+    /// Rotation only, the sky is infinitely far away.
+    view_projection: [f32; 16],
+    inverse_view_projection: [f32; 16],
+    /// Alpha is star visibility.
+    zenith: [f32; 4],
+    horizon: [f32; 4],
+    glow: [f32; 4],
+    /// Direction and billboard size.
+    sun: [f32; 4],
+    moon: [f32; 4],
+    /// Axis and angle the sky turned.
+    stars: [f32; 4],
+    /// Fog color and whether it hides the sky.
+    fog: [f32; 4],
+    /// Output encoding, sun layer and moon layer.
+    params: [u32; 4],
+}
+
+/// Sky colors at some time of day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Colors {
+    zenith: [f32; 3],
+    horizon: [f32; 3],
+    glow: [f32; 3],
+    stars: f32,
+}
+
+impl Sky {
+    pub fn new(vulkan: &Vulkan) -> Result<Self> {
+        let device = vulkan.clone_device();
+
+        // === SHADERS ===
+
+        let mut vert_file = Cursor::new(spirv!("sky.vert"));
+        let mut frag_file = Cursor::new(spirv!("sky.frag"));
+
+        let shader_vert = vulkan.create_shader_module(&mut vert_file)?;
+        let shader_frag = vulkan.create_shader_module(&mut frag_file)?;
+
+        // === TEXTURES ===
+
+        let textures = TextureArray::builder()
+            .size(SKY_TEXTURE_SIZE)
+            .dir(SKY_DIR)
+            .build();
+        let texture = Texture::new(vulkan, &textures);
+
+        // === DESCRIPTORS ===
+
+        let layout = PipelineLayout::new(vulkan, &[&shader_vert, &shader_frag]);
+        let mut descriptors = Descriptors::new(vulkan);
+
+        let uniforms: Vec<_> = (0..FRAMES_IN_FLIGHT)
+            .map(|_| {
+                Buffer::new(
+                    vulkan,
+                    mem::size_of::<Uniforms>() as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )
+            })
+            .collect();
+        let sets = uniforms
+            .iter()
+            .map(|uniforms| {
+                let set = descriptors.allocate(&layout, 0);
+                descriptors.write_image(
+                    set,
+                    0,
+                    vk::DescriptorType::SAMPLED_IMAGE,
+                    texture.image_info(),
+                );
+                descriptors.write_image(
+                    set,
+                    1,
+                    vk::DescriptorType::SAMPLER,
+                    texture.sampler_info(),
+                );
+                descriptors.write_buffer(
+                    set,
+                    2,
+                    vk::DescriptorType::UNIFORM_BUFFER,
+                    vk::DescriptorBufferInfo {
+                        buffer: uniforms.buffer(),
+                        offset: 0,
+                        range: vk::WHOLE_SIZE,
+                    },
+                );
+                set
+            })
+            .collect();
+
+        // === PIPELINE ===
+
+        let pipeline = create_pipeline(
+            &device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &layout,
+            &[&shader_vert, &shader_frag],
+        );
+
+        Ok(Self {
+            device,
+
+            textures,
+            _texture: texture,
+
+            shader_vert,
+            shader_frag,
+
+            layout,
+            _descriptors: descriptors,
+            sets,
+            uniforms,
+
+            pipeline,
+            surface_format: vulkan.surface_format(),
+            samples: vulkan.samples(),
+        })
+    }
+
+    /// Swaps in a recompiled shader and rebuilds the pipeline if `name` is one of ours.
+    /// Returns whether the shader was used.
+    pub fn reload_shader(&mut self, vulkan: &Vulkan, name: &str, code: &[u32]) -> bool {
+        reload_shader(
+            self,
+            vulkan,
+            name,
+            code,
+            |renderer| {
+                [
+                    (SHADER_VERT, &mut renderer.shader_vert),
+                    (SHADER_FRAG, &mut renderer.shader_frag),
+                ]
+            },
+            Self::rebuild_pipeline,
+        )
+    }
+
+    /// Follows changes of the surface format and multisampling, call before drawing.
+    pub fn update_surface(&mut self, vulkan: &Vulkan) {
+        if vulkan.follow_surface(&mut self.surface_format, &mut self.samples) {
+            self.rebuild_pipeline(vulkan);
+        }
+    }
+
+    fn rebuild_pipeline(&mut self, vulkan: &Vulkan) {
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+            self.device.destroy_pipeline(self.pipeline, None);
+        }
+
+        self.pipeline = create_pipeline(
+            &self.device,
+            vulkan.render_pass(),
+            vulkan.samples(),
+            &self.layout,
+            &[&self.shader_vert, &self.shader_frag],
+        );
+    }
+
+    /// Records the draw commands, first thing inside the render pass begun by
+    /// `Vulkan::draw_stages`.
+    pub fn draw(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        camera: &Camera,
+        aspect: f32,
+        time: Time,
+        fog: Fog,
+    ) {
+        let frame = frame % FRAMES_IN_FLIGHT;
+
+        let view = Matrix4::look_to_rh(
+            Point3::new(0.0, 0.0, 0.0),
+            camera.direction(),
+            Vector3::unit_y(),
+        );
+        let view_projection = camera.projection(aspect) * view;
+        let inverse = view_projection.invert().unwrap_or_else(Matrix4::identity);
+
+        let colors = Colors::at(time);
+        let sun = time.sun_direction();
+        let moon = time.moon_direction();
+        let axis = time.sky_axis();
+        let layer = |name| {
+            self.textures
+                .texture(name)
+                .expect("missing sky texture")
+                .layer
+        };
+        self.uniforms[frame].write(&[Uniforms {
+            view_projection: *view_projection.as_ref(),
+            inverse_view_projection: *inverse.as_ref(),
+            zenith: with_alpha(colors.zenith, colors.stars),
+            horizon: with_alpha(colors.horizon, 1.0),
+            glow: with_alpha(colors.glow, 1.0),
+            sun: [sun.x, sun.y, sun.z, SUN_SIZE],
+            moon: [moon.x, moon.y, moon.z, MOON_SIZE],
+            stars: [axis.x, axis.y, axis.z, time.sky_angle()],
+            fog: with_alpha(fog.color, if fog.hides_sky { 1.0 } else { 0.0 }),
+            params: [
+                OutputEncoding::of(self.surface_format) as u32,
+                layer("sun"),
+                layer("moon") + time.moon_phase(),
+                0,
+            ],
+        }]);
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout.layout(),
+                0,
+                &[self.sets[frame]],
+                &[],
+            );
+
+            self.draw_body(command_buffer, Body::Dome, 3);
+            self.draw_body(command_buffer, Body::Sun, 6);
+            self.draw_body(command_buffer, Body::Moon, 6);
+        }
+    }
+
+    /// Color of the sky at the horizon, what distance fog fades into.
+    pub fn horizon(time: Time) -> [f32; 3] {
+        Colors::at(time).horizon
+    }
+
+    unsafe fn draw_body(&self, command_buffer: vk::CommandBuffer, body: Body, vertices: u32) {
+        let range = self.layout.push_constants().unwrap();
+        self.device.cmd_push_constants(
+            command_buffer,
+            self.layout.layout(),
+            range.stage_flags,
+            0,
+            &(body as u32).to_ne_bytes(),
+        );
+        self.device.cmd_draw(command_buffer, vertices, 1, 0, 0);
+    }
+}
+
+impl Drop for Sky {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.pipeline, None);
+        }
+    }
+}
+
+impl Colors {
+    /// Day colors while the sun is up and night ones while it's down, with a glow around the
+    /// sun as it crosses the horizon.
+    fn at(time: Time) -> Self {
+        let daylight = time.daylight();
+        let mix = |night: [f32; 3], day: [f32; 3]| {
+            [0, 1, 2].map(|i| night[i] + (day[i] - night[i]) * daylight)
+        };
+
+        let height = time.sun_direction().y.abs();
+        let glow = (1.0 - height / 0.35).clamp(0.0, 1.0);
+
+        Self {
+            zenith: mix(NIGHT_ZENITH, DAY_ZENITH),
+            horizon: mix(NIGHT_HORIZON, DAY_HORIZON),
+            glow: SUNSET_GLOW.map(|c| c * glow * glow),
+            stars: 1.0 - daylight,
+        }
+    }
+}
+
+fn with_alpha([r, g, b]: [f32; 3], a: f32) -> [f32; 4] {
+    [r, g, b, a]
+}
+
+fn create_pipeline(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+    layout: &PipelineLayout,
+    shaders: &[&Shader],
+) -> vk::Pipeline {
+    let stages: Vec<_> = shaders.iter().map(|s| s.stage_info()).collect();
+
+    // Vertices come from the vertex index
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    // Viewport and scissor follow the render target, see `dynamic_state`
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    let multisample =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(samples);
+
+    // Behind everything, blocks draw over it
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false)
+        .depth_write_enable(false);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState {
+        blend_enable: vk::TRUE,
+        src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ONE,
+        dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::all(),
+    }];
+    let color_blend =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport)
+        .rasterization_state(&rasterization)
+        .multisample_state(&multisample)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blend)
+        .dynamic_state(&dynamic_state)
+        .layout(layout.layout())
+        .render_pass(render_pass)
+        .subpass(0);
+
+    unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)
+            .unwrap()[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_follow_the_sun() {
+        let noon = Colors::at(Time::default());
+        assert_eq!(noon.zenith, DAY_ZENITH);
+        assert_eq!(noon.glow, [0.0; 3]);
+        assert_eq!(noon.stars, 0.0);
+
+        let midnight = Colors::at(Time::of_day(0.75));
+        assert_eq!(midnight.horizon, NIGHT_HORIZON);
+        assert_eq!(midnight.stars, 1.0);
+
+        let sunset = Colors::at(Time::of_day(0.5));
+        assert!(sunset.glow[0] > 0.99);
+        assert!(sunset.stars > 0.0 && sunset.stars < 1.0);
+    }
+}
@@ -0,0 +1,329 @@
+//! # Textures
+//!
+//! The `textures` module loads block face textures from `assets/textures/` and packs them into
+//! layers of a 2D texture array. Animated textures are vertical strips of square frames, every
+//! frame taking up one layer, so a texture is referenced by its first layer and a frame count.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+
+pub const TEXTURES_DIR: &str = "assets/textures";
+pub const TEXTURE_SIZE: u32 = 16;
+
+const CHANNELS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureRef {
+    pub layer: u32,
+    pub frames: u32,
+}
+
+pub struct TextureArray {
+    size: u32,
+    layers: u32,
+    mip_levels: u32,
+    // RGBA8 texels ordered by mip level, then layer
+    data: Vec<u8>,
+    textures: HashMap<String, TextureRef>,
+}
+
+impl TextureArray {
+    pub fn builder() -> TextureArrayBuilder {
+        Default::default()
+    }
+
+    /// Loads every `.png` in `dir` using its file stem as the texture name.
+    pub fn load(dir: impl AsRef<Path>) -> Self {
+        Self::builder().dir(dir).build()
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn texture(&self, name: &str) -> Option<TextureRef> {
+        self.textures.get(name).copied()
+    }
+
+    pub fn mip_size(&self, level: u32) -> u32 {
+        (self.size >> level).max(1)
+    }
+
+    /// Byte offset of the given mip level within `data`.
+    pub fn mip_offset(&self, level: u32) -> usize {
+        (0..level).map(|l| self.mip_len(l)).sum()
+    }
+
+    #[cfg(test)]
+    pub fn mip_data(&self, level: u32) -> &[u8] {
+        let offset = self.mip_offset(level);
+        &self.data[offset..offset + self.mip_len(level)]
+    }
+
+    fn mip_len(&self, level: u32) -> usize {
+        let size = self.mip_size(level) as usize;
+        size * size * CHANNELS * self.layers as usize
+    }
+}
+
+pub struct TextureArrayBuilder {
+    size: u32,
+    textures: Vec<(String, Vec<Vec<u8>>)>,
+}
+
+impl Default for TextureArrayBuilder {
+    fn default() -> Self {
+        Self {
+            size: TEXTURE_SIZE,
+            textures: Vec::new(),
+        }
+    }
+}
+
+impl TextureArrayBuilder {
+    pub fn size(mut self, size: u32) -> Self {
+        assert!(
+            size.is_power_of_two(),
+            "texture size must be a power of two"
+        );
+        self.size = size;
+        self
+    }
+
+    /// Adds RGBA8 texels of a `size` wide texture. Taller textures are split into frames.
+    pub fn texture(mut self, name: &str, width: u32, height: u32, rgba: &[u8]) -> Self {
+        assert!(
+            width == self.size && height > 0 && height.is_multiple_of(self.size),
+            "texture '{}' is {}x{}, expected {} wide strip of square frames",
+            name,
+            width,
+            height,
+            self.size,
+        );
+        assert_eq!(rgba.len(), (width * height) as usize * CHANNELS);
+
+        let frame_len = (self.size * self.size) as usize * CHANNELS;
+        let frames = rgba.chunks(frame_len).map(|f| f.to_vec()).collect();
+
+        self.textures.push((name.to_string(), frames));
+        self
+    }
+
+    pub fn png(self, name: &str, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let (width, height, rgba) = decode_png(path);
+
+        self.texture(name, width, height, &rgba)
+    }
+
+    pub fn dir(mut self, dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .unwrap_or_else(|e| panic!("failed to read '{}': {}", dir.display(), e))
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+            .collect();
+        // Stable layer indices between runs
+        paths.sort();
+
+        for path in paths {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            self = self.png(&name, &path);
+        }
+        self
+    }
+
+    pub fn build(self) -> TextureArray {
+        let mut textures = HashMap::new();
+        let mut base = Vec::new();
+
+        for (name, frames) in self.textures {
+            let texture = TextureRef {
+                layer: base.len() as u32,
+                frames: frames.len() as u32,
+            };
+            textures.insert(name, texture);
+            base.extend(frames);
+        }
+
+        let mip_levels = 32 - self.size.leading_zeros();
+        let mut levels = vec![base];
+        for level in 1..mip_levels {
+            let size = (self.size >> (level - 1)) as usize;
+            let next = levels[level as usize - 1]
+                .iter()
+                .map(|layer| downsample(layer, size))
+                .collect();
+            levels.push(next);
+        }
+
+        TextureArray {
+            size: self.size,
+            layers: levels[0].len() as u32,
+            mip_levels,
+            data: levels.into_iter().flatten().flatten().collect(),
+            textures,
+        }
+    }
+}
+
+/// Halves a square RGBA8 image by averaging 2x2 texel blocks.
+fn downsample(texels: &[u8], size: usize) -> Vec<u8> {
+    let half = size / 2;
+    let mut out = Vec::with_capacity(half * half * CHANNELS);
+
+    for y in 0..half {
+        for x in 0..half {
+            for c in 0..CHANNELS {
+                let texel = |dx: usize, dy: usize| {
+                    u32::from(texels[((y * 2 + dy) * size + x * 2 + dx) * CHANNELS + c])
+                };
+                let sum = texel(0, 0) + texel(1, 0) + texel(0, 1) + texel(1, 1);
+                out.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+    out
+}
+
+/// Reads a PNG file as RGBA8 texels, returning its width, height and texels.
+pub fn decode_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let file =
+        File::open(path).unwrap_or_else(|e| panic!("failed to open '{}': {}", path.display(), e));
+
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::EXPAND);
+
+    let (info, mut reader) = decoder
+        .read_info()
+        .unwrap_or_else(|e| panic!("failed to decode '{}': {}", path.display(), e));
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).unwrap();
+
+    assert_eq!(
+        info.bit_depth,
+        png::BitDepth::Eight,
+        "'{}' must be 8 bits per channel",
+        path.display(),
+    );
+
+    let rgba = match info.color_type {
+        png::ColorType::RGBA => buf,
+        png::ColorType::RGB => buf
+            .chunks(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks(2)
+            .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| vec![g, g, g, 255]).collect(),
+        png::ColorType::Indexed => unreachable!("indexed colors are expanded"),
+    };
+
+    (info.width, info.height, rgba)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(size: u32, frames: u32, rgba: [u8; 4]) -> Vec<u8> {
+        rgba.iter()
+            .copied()
+            .cycle()
+            .take((size * size * frames) as usize * CHANNELS)
+            .collect()
+    }
+
+    #[test]
+    fn packs_animated_strips_into_consecutive_layers() {
+        let array = TextureArray::builder()
+            .texture("a", 16, 16, &solid(16, 1, [255, 0, 0, 255]))
+            .texture("b", 16, 64, &solid(16, 4, [0, 255, 0, 255]))
+            .texture("c", 16, 16, &solid(16, 1, [0, 0, 255, 255]))
+            .build();
+
+        assert_eq!(array.layers(), 6);
+        assert_eq!(
+            array.texture("a"),
+            Some(TextureRef {
+                layer: 0,
+                frames: 1
+            })
+        );
+        assert_eq!(
+            array.texture("b"),
+            Some(TextureRef {
+                layer: 1,
+                frames: 4
+            })
+        );
+        assert_eq!(
+            array.texture("c"),
+            Some(TextureRef {
+                layer: 5,
+                frames: 1
+            })
+        );
+        assert_eq!(array.texture("d"), None);
+    }
+
+    #[test]
+    fn generates_full_mip_chain() {
+        let array = TextureArray::builder()
+            .texture("a", 16, 32, &solid(16, 2, [10, 20, 30, 40]))
+            .build();
+
+        assert_eq!(array.mip_levels(), 5);
+        let sizes: Vec<_> = (0..5).map(|l| array.mip_size(l)).collect();
+        assert_eq!(sizes, [16, 8, 4, 2, 1]);
+
+        let texels: u32 = sizes.iter().map(|s| s * s).sum();
+        assert_eq!(array.data().len(), (texels * 2) as usize * CHANNELS);
+        assert_eq!(array.mip_data(4), [10, 20, 30, 40, 10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn averages_texels_when_downsampling() {
+        // 2x2 checkerboard of black and white
+        let texels = [
+            0, 0, 0, 255, 255, 255, 255, 255, //
+            255, 255, 255, 255, 0, 0, 0, 255,
+        ];
+        let array = TextureArray::builder()
+            .size(2)
+            .texture("checker", 2, 2, &texels)
+            .build();
+
+        assert_eq!(array.mip_levels(), 2);
+        assert_eq!(array.mip_data(1), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_non_square_frames() {
+        TextureArray::builder().texture("bad", 16, 24, &solid(16, 1, [0; 4]));
+    }
+
+    #[test]
+    fn loads_block_textures() {
+        let array = TextureArray::load(TEXTURES_DIR);
+
+        assert_eq!(array.texture("stone").map(|t| t.frames), Some(1));
+        assert_eq!(array.texture("water_still").map(|t| t.frames), Some(4));
+    }
+}
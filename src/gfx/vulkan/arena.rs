@@ -0,0 +1,224 @@
+//! # Arena
+//!
+//! One large device local buffer holding many small allocations, e.g. the meshes of all chunks,
+//! so they can be bound once and drawn with indirect draws. Allocations are found first fit and
+//! the buffer doubles in size when full.
+
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+
+use ash::vk;
+
+use super::{Buffer, Vulkan};
+
+/// A range of elements in an `Arena`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    pub offset: u32,
+    pub len: u32,
+}
+
+impl Allocation {
+    fn range(&self) -> Range<u32> {
+        self.offset..self.offset + self.len
+    }
+}
+
+pub struct Arena<T> {
+    buffer: Buffer,
+    usage: vk::BufferUsageFlags,
+    allocator: Allocator,
+    _element: PhantomData<T>,
+}
+
+impl<T: Copy> Arena<T> {
+    pub fn new(vulkan: &Vulkan, capacity: u32, usage: vk::BufferUsageFlags) -> Self {
+        Self {
+            buffer: create_buffer::<T>(vulkan, capacity, usage),
+            usage,
+            allocator: Allocator::new(capacity),
+            _element: PhantomData,
+        }
+    }
+
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer.buffer()
+    }
+
+    /// Uploads `data` into a free range, growing the buffer if needed. Growing replaces the
+    /// buffer, so it must not be in use by the device.
+    pub fn insert(&mut self, vulkan: &Vulkan, data: &[T]) -> Allocation {
+        let len = data.len() as u32;
+        let offset = match self.allocator.allocate(len) {
+            Some(offset) => offset,
+            None => {
+                self.grow(vulkan, len);
+                self.allocator.allocate(len).unwrap()
+            }
+        };
+
+        if !data.is_empty() {
+            self.buffer
+                .upload(vulkan, offset as vk::DeviceSize * element_size::<T>(), data);
+        }
+        Allocation { offset, len }
+    }
+
+    pub fn remove(&mut self, allocation: Allocation) {
+        self.allocator.free(allocation.range());
+    }
+
+    fn grow(&mut self, vulkan: &Vulkan, needed: u32) {
+        let old_capacity = self.allocator.capacity;
+        let free_at_end = self.allocator.free_at_end();
+        let mut capacity = old_capacity.max(1);
+        while capacity - old_capacity + free_at_end < needed || capacity == old_capacity {
+            capacity *= 2;
+        }
+
+        let buffer = create_buffer::<T>(vulkan, capacity, self.usage);
+        buffer.copy_from(vulkan, &self.buffer, self.buffer.size());
+
+        self.buffer = buffer;
+        self.allocator.grow(capacity);
+    }
+}
+
+fn element_size<T>() -> vk::DeviceSize {
+    mem::size_of::<T>() as vk::DeviceSize
+}
+
+fn create_buffer<T>(vulkan: &Vulkan, capacity: u32, usage: vk::BufferUsageFlags) -> Buffer {
+    Buffer::new(
+        vulkan,
+        capacity.max(1) as vk::DeviceSize * element_size::<T>(),
+        usage | vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )
+}
+
+/// First fit allocation of ranges in `0..capacity`.
+#[derive(Debug)]
+struct Allocator {
+    capacity: u32,
+    /// Sorted, non-adjacent free ranges.
+    free: Vec<Range<u32>>,
+}
+
+impl Allocator {
+    fn new(capacity: u32) -> Self {
+        let mut allocator = Self {
+            capacity: 0,
+            free: Vec::new(),
+        };
+        allocator.grow(capacity);
+        allocator
+    }
+
+    fn allocate(&mut self, len: u32) -> Option<u32> {
+        if len == 0 {
+            return Some(0);
+        }
+
+        let index = self
+            .free
+            .iter()
+            .position(|range| range.len() >= len as usize)?;
+        let range = &mut self.free[index];
+        let offset = range.start;
+        range.start += len;
+        if range.start == range.end {
+            self.free.remove(index);
+        }
+        Some(offset)
+    }
+
+    fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        debug_assert!(range.end <= self.capacity, "freeing outside the arena");
+
+        let index = self
+            .free
+            .iter()
+            .position(|free| free.start > range.start)
+            .unwrap_or(self.free.len());
+        self.free.insert(index, range);
+
+        // Merge with the following and preceding range
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            self.free[index].end = self.free.remove(index + 1).end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            self.free[index - 1].end = self.free.remove(index).end;
+        }
+    }
+
+    /// Free elements at the end, which a grown allocation can extend.
+    fn free_at_end(&self) -> u32 {
+        match self.free.last() {
+            Some(range) if range.end == self.capacity => range.end - range.start,
+            _ => 0,
+        }
+    }
+
+    fn grow(&mut self, capacity: u32) {
+        let old_capacity = self.capacity;
+        self.capacity = capacity;
+        self.free(old_capacity..capacity);
+    }
+
+    #[cfg(test)]
+    fn used(&self) -> u32 {
+        self.capacity
+            - self
+                .free
+                .iter()
+                .map(|range| range.end - range.start)
+                .sum::<u32>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_first_fit_and_merges() {
+        let mut allocator = Allocator::new(100);
+        assert_eq!(allocator.allocate(30), Some(0));
+        assert_eq!(allocator.allocate(30), Some(30));
+        assert_eq!(allocator.allocate(30), Some(60));
+        assert_eq!(allocator.allocate(30), None);
+
+        allocator.free(0..30);
+        allocator.free(60..90);
+        assert_eq!(allocator.allocate(20), Some(0));
+        // Freeing the middle merges it with both neighbours
+        allocator.free(30..60);
+        assert_eq!(
+            allocator.free,
+            [Range {
+                start: 20,
+                end: 100
+            }]
+        );
+        assert_eq!(allocator.used(), 20);
+        assert_eq!(allocator.allocate(80), Some(20));
+    }
+
+    #[test]
+    fn grows_into_free_space_at_the_end() {
+        let mut allocator = Allocator::new(10);
+        assert_eq!(allocator.allocate(6), Some(0));
+        assert_eq!(allocator.free_at_end(), 4);
+        assert_eq!(allocator.allocate(8), None);
+
+        allocator.grow(20);
+        assert_eq!(allocator.free, [Range { start: 6, end: 20 }]);
+        assert_eq!(allocator.allocate(8), Some(6));
+        assert_eq!(allocator.free_at_end(), 6);
+    }
+}
@@ -0,0 +1,174 @@
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+
+use super::Vulkan;
+
+pub struct Buffer {
+    device: Arc<Device>,
+
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+}
+
+impl Buffer {
+    pub fn new(
+        vulkan: &Vulkan,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        properties: vk::MemoryPropertyFlags,
+    ) -> Self {
+        let device = vulkan.clone_device();
+
+        unsafe {
+            let buffer_info = vk::BufferCreateInfo::builder()
+                .size(size)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+            let buffer = device.create_buffer(&buffer_info, None).unwrap();
+
+            let requirements = device.get_buffer_memory_requirements(buffer);
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(vulkan.find_memory_type(&requirements, properties));
+
+            let memory = device.allocate_memory(&allocate_info, None).unwrap();
+            device.bind_buffer_memory(buffer, memory, 0).unwrap();
+
+            Self {
+                device,
+
+                buffer,
+                memory,
+                size,
+            }
+        }
+    }
+
+    /// Creates a host visible buffer filled with `data`, used as a transfer source.
+    pub fn staging<T: Copy>(vulkan: &Vulkan, data: &[T]) -> Self {
+        let buffer = Self::new(
+            vulkan,
+            mem::size_of_val(data) as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        buffer.write(data);
+        buffer
+    }
+
+    /// Creates a device local buffer with `data` uploaded through a staging buffer.
+    pub fn device_local<T: Copy>(vulkan: &Vulkan, data: &[T], usage: vk::BufferUsageFlags) -> Self {
+        let staging = Self::staging(vulkan, data);
+        let buffer = Self::new(
+            vulkan,
+            staging.size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+
+        vulkan.one_time_commands(|device, command_buffer| unsafe {
+            let regions = [vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size: staging.size,
+            }];
+            device.cmd_copy_buffer(command_buffer, staging.buffer, buffer.buffer, &regions);
+        });
+
+        buffer
+    }
+
+    /// Uploads `data` at byte `offset` through a staging buffer. The buffer needs
+    /// `TRANSFER_DST` usage and must not be in use by the device.
+    pub fn upload<T: Copy>(&self, vulkan: &Vulkan, offset: vk::DeviceSize, data: &[T]) {
+        let staging = Self::staging(vulkan, data);
+        assert!(
+            offset + staging.size <= self.size,
+            "uploading {} bytes at {} into {} byte buffer",
+            staging.size,
+            offset,
+            self.size
+        );
+
+        vulkan.one_time_commands(|device, command_buffer| unsafe {
+            let regions = [vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: offset,
+                size: staging.size,
+            }];
+            device.cmd_copy_buffer(command_buffer, staging.buffer, self.buffer, &regions);
+        });
+    }
+
+    /// Copies the first `size` bytes of `src` to the start of this buffer, which needs
+    /// `TRANSFER_DST` usage.
+    pub fn copy_from(&self, vulkan: &Vulkan, src: &Buffer, size: vk::DeviceSize) {
+        vulkan.one_time_commands(|device, command_buffer| unsafe {
+            let regions = [vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size,
+            }];
+            device.cmd_copy_buffer(command_buffer, src.buffer, self.buffer, &regions);
+        });
+    }
+
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// Copies `data` to the start of the buffer. Memory must be host visible and coherent.
+    pub fn write<T: Copy>(&self, data: &[T]) {
+        let len = mem::size_of_val(data) as vk::DeviceSize;
+        assert!(
+            len <= self.size,
+            "writing {} bytes into {} byte buffer",
+            len,
+            self.size
+        );
+
+        unsafe {
+            let dst = self
+                .device
+                .map_memory(self.memory, 0, len, vk::MemoryMapFlags::empty())
+                .unwrap();
+            ptr::copy_nonoverlapping(data.as_ptr() as *const u8, dst as *mut u8, len as usize);
+            self.device.unmap_memory(self.memory);
+        }
+    }
+
+    /// Copies the whole buffer to the host. Memory must be host visible and coherent.
+    pub fn read(&self) -> Vec<u8> {
+        let mut data = vec![0; self.size as usize];
+
+        unsafe {
+            let src = self
+                .device
+                .map_memory(self.memory, 0, self.size, vk::MemoryMapFlags::empty())
+                .unwrap();
+            ptr::copy_nonoverlapping(src as *const u8, data.as_mut_ptr(), data.len());
+            self.device.unmap_memory(self.memory);
+        }
+
+        data
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_buffer(self.buffer, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
@@ -1,17 +1,154 @@
 use std::borrow::Cow;
 use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::str::FromStr;
+use std::sync::Mutex;
 
-use ash::vk;
+use ash::extensions::ext::DebugUtils;
+use ash::{vk, Entry, Instance};
+use log::{Level, LevelFilter};
+
+use crate::gfx::{Error, Result};
+use crate::settings::parse_bool;
+
+/// Overrides the `validation` setting, e.g. `MINECRAFT_VALIDATION=1` in release builds.
+pub const VALIDATION_ENV: &str = "MINECRAFT_VALIDATION";
+
+/// Validation layer options, validation gets skipped with a warning when the layers aren't
+/// installed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Validation {
+    pub enabled: bool,
+    /// Least severe message to log, `off` skips validation like `enabled: false`.
+    pub level: LevelFilter,
+    /// Panics on the next frame after a validation error, for tests.
+    pub panic_on_error: bool,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self {
+            enabled: cfg!(debug_assertions),
+            level: LevelFilter::Warn,
+            panic_on_error: false,
+        }
+    }
+}
+
+impl Validation {
+    /// Whether to load the layers and create a messenger, which needs at least one severity.
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.level != LevelFilter::Off
+    }
+
+    /// Sets an option by its settings key, see `Settings::set`.
+    pub fn set(&mut self, key: &str, value: &str) -> std::result::Result<(), String> {
+        match key {
+            "validation" => self.enabled = parse_bool(value)?,
+            "validation_level" => {
+                self.level = LevelFilter::from_str(value)
+                    .map_err(|_| format!("invalid log level '{}'", value))?
+            }
+            "validation_panic" => self.panic_on_error = parse_bool(value)?,
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Routes validation layer messages to the `log` facade.
+pub struct Messenger {
+    loader: DebugUtils,
+    messenger: vk::DebugUtilsMessengerEXT,
+    // Boxed so the callback's pointer stays valid when the messenger moves
+    errors: Box<Errors>,
+}
+
+struct Errors {
+    collect: bool,
+    messages: Mutex<Vec<String>>,
+}
+
+impl Messenger {
+    pub unsafe fn new(entry: &Entry, instance: &Instance, validation: &Validation) -> Result<Self> {
+        let errors = Box::new(Errors {
+            collect: validation.panic_on_error,
+            messages: Mutex::new(Vec::new()),
+        });
+
+        let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+            .message_severity(severity_flags(validation.level))
+            .message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
+            .pfn_user_callback(Some(vulkan_debug_callback))
+            .user_data(&*errors as *const Errors as *mut c_void);
+
+        let loader = DebugUtils::new(entry, instance);
+        let messenger = loader
+            .create_debug_utils_messenger(&debug_info, None)
+            .map_err(Error::vulkan("creating the debug messenger"))?;
+
+        Ok(Self {
+            loader,
+            messenger,
+            errors,
+        })
+    }
+
+    /// Panics with the validation errors reported since the last check, if collecting them.
+    /// Panicking inside the callback would abort, it's called from C.
+    pub fn check(&self) {
+        let messages: Vec<_> = self.errors.messages.lock().unwrap().drain(..).collect();
+        if !messages.is_empty() {
+            panic!("Vulkan validation failed:\n{}", messages.join("\n"));
+        }
+    }
+}
+
+impl Drop for Messenger {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader
+                .destroy_debug_utils_messenger(self.messenger, None);
+        }
+    }
+}
+
+/// Severities the messenger reports for a log level, more verbose levels include less verbose ones.
+fn severity_flags(level: LevelFilter) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+    let mut flags = vk::DebugUtilsMessageSeverityFlagsEXT::empty();
+    if level >= LevelFilter::Error {
+        flags |= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    }
+    if level >= LevelFilter::Warn {
+        flags |= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+    }
+    if level >= LevelFilter::Info {
+        flags |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+    }
+    if level >= LevelFilter::Debug {
+        flags |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+    }
+    flags
+}
+
+fn log_level(severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> Level {
+    match severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => Level::Error,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => Level::Warn,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => Level::Info,
+        _ => Level::Debug,
+    }
+}
 
 // https://github.com/MaikKlein/ash/blob/master/examples/src/lib.rs#L87
-pub unsafe extern "system" fn vulkan_debug_callback(
+unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _user_data: *mut std::os::raw::c_void,
+    p_user_data: *mut c_void,
 ) -> vk::Bool32 {
     let callback_data = *p_callback_data;
-    let message_id_number: i32 = callback_data.message_id_number as i32;
+    let message_id_number: i32 = callback_data.message_id_number;
 
     let message_id_name = if callback_data.p_message_id_name.is_null() {
         Cow::from("")
@@ -25,14 +162,60 @@ pub unsafe extern "system" fn vulkan_debug_callback(
         CStr::from_ptr(callback_data.p_message).to_string_lossy()
     };
 
-    println!(
-        "{:?}:\n{:?} [{} ({})] : {}\n",
-        message_severity,
+    let level = log_level(message_severity);
+    log::log!(
+        target: "vulkan",
+        level,
+        "{:?} [{} ({})] : {}",
         message_type,
         message_id_name,
-        &message_id_number.to_string(),
+        message_id_number,
         message,
     );
 
+    let errors = &*(p_user_data as *const Errors);
+    if errors.collect && level == Level::Error {
+        if let Ok(mut messages) = errors.messages.lock() {
+            messages.push(format!("[{}] {}", message_id_name, message));
+        }
+    }
+
     vk::FALSE
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severity_follows_level() {
+        use vk::DebugUtilsMessageSeverityFlagsEXT as Severity;
+
+        let off = Validation {
+            enabled: true,
+            level: LevelFilter::Off,
+            panic_on_error: false,
+        };
+        assert!(!off.is_active());
+        assert!(Validation {
+            level: LevelFilter::Error,
+            ..off
+        }
+        .is_active());
+        assert_eq!(
+            severity_flags(LevelFilter::Warn),
+            Severity::ERROR | Severity::WARNING
+        );
+        assert_eq!(severity_flags(LevelFilter::Trace), Severity::all());
+
+        for &(severity, level) in &[
+            (Severity::ERROR, Level::Error),
+            (Severity::WARNING, Level::Warn),
+            (Severity::INFO, Level::Info),
+            (Severity::VERBOSE, Level::Debug),
+        ] {
+            assert_eq!(log_level(severity), level);
+            assert!(severity_flags(level.to_level_filter()).contains(severity));
+        }
+    }
+}
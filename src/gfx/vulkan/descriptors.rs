@@ -0,0 +1,333 @@
+//! # Descriptors
+//!
+//! Pipeline layouts derived from shader reflection and a descriptor set allocator with growable
+//! pools. Runtime sized descriptor arrays in shaders become bindless bindings when the device
+//! supports descriptor indexing.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+
+use super::reflect::DescriptorBinding;
+use super::shader::Shader;
+use super::Vulkan;
+
+/// Upper bound of descriptors in a bindless binding, further clamped by device limits.
+const BINDLESS_CAPACITY: u32 = 4096;
+const SETS_PER_POOL: u32 = 64;
+const POOL_RATIOS: [(vk::DescriptorType, u32); 6] = [
+    (vk::DescriptorType::UNIFORM_BUFFER, 2),
+    (vk::DescriptorType::STORAGE_BUFFER, 2),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 2),
+    (vk::DescriptorType::SAMPLED_IMAGE, 2),
+    (vk::DescriptorType::SAMPLER, 2),
+    (vk::DescriptorType::STORAGE_IMAGE, 1),
+];
+
+struct SetLayout {
+    layout: vk::DescriptorSetLayout,
+    sizes: Vec<vk::DescriptorPoolSize>,
+    variable_count: Option<u32>,
+}
+
+pub struct PipelineLayout {
+    device: Arc<Device>,
+
+    sets: Vec<SetLayout>,
+    push_constants: Option<vk::PushConstantRange>,
+    layout: vk::PipelineLayout,
+}
+
+impl PipelineLayout {
+    pub fn new(vulkan: &Vulkan, shaders: &[&Shader]) -> Self {
+        let device = vulkan.clone_device();
+
+        // Merge bindings of all stages
+        let mut merged = BTreeMap::<(u32, u32), DescriptorBinding>::new();
+        for binding in shaders.iter().flat_map(|s| &s.reflection().bindings) {
+            merged
+                .entry((binding.set, binding.binding))
+                .and_modify(|b| {
+                    assert_eq!(
+                        b.ty, binding.ty,
+                        "set {} binding {} differs between stages",
+                        binding.set, binding.binding,
+                    );
+                    b.stages |= binding.stages;
+                })
+                .or_insert(*binding);
+        }
+
+        let set_count = merged.keys().map(|&(set, _)| set + 1).max().unwrap_or(0);
+        let sets = (0..set_count)
+            .map(|set| {
+                let bindings: Vec<_> = merged.values().filter(|b| b.set == set).copied().collect();
+                create_set_layout(vulkan, &device, &bindings)
+            })
+            .collect::<Vec<_>>();
+
+        let push_constants = shaders
+            .iter()
+            .filter(|s| s.reflection().push_constants > 0)
+            .fold(None, |range: Option<vk::PushConstantRange>, s| {
+                let range = range.unwrap_or_default();
+                Some(vk::PushConstantRange {
+                    stage_flags: range.stage_flags | s.stage(),
+                    offset: 0,
+                    size: range.size.max(s.reflection().push_constants),
+                })
+            });
+
+        let set_layouts: Vec<_> = sets.iter().map(|s| s.layout).collect();
+        let push_constant_ranges: Vec<_> = push_constants.iter().copied().collect();
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+
+        let layout = unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() };
+
+        Self {
+            device,
+
+            sets,
+            push_constants,
+            layout,
+        }
+    }
+
+    pub fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    pub fn push_constants(&self) -> Option<vk::PushConstantRange> {
+        self.push_constants
+    }
+}
+
+impl Drop for PipelineLayout {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline_layout(self.layout, None);
+
+            for set in &self.sets {
+                self.device.destroy_descriptor_set_layout(set.layout, None);
+            }
+        }
+    }
+}
+
+fn create_set_layout(
+    vulkan: &Vulkan,
+    device: &Device,
+    bindings: &[DescriptorBinding],
+) -> SetLayout {
+    let bindless = bindings.iter().any(|b| b.is_bindless());
+    assert!(
+        !bindless || vulkan.descriptor_indexing(),
+        "shader uses runtime descriptor arrays, but the device lacks descriptor indexing",
+    );
+
+    let capacity = BINDLESS_CAPACITY.min(vulkan.max_bindless_descriptors());
+    let last = bindings.iter().map(|b| b.binding).max();
+
+    let mut variable_count = None;
+    let mut layout_bindings = Vec::with_capacity(bindings.len());
+    let mut binding_flags = Vec::with_capacity(bindings.len());
+
+    for b in bindings {
+        let mut flags = vk::DescriptorBindingFlags::empty();
+        let mut count = b.count;
+
+        if b.is_bindless() {
+            count = capacity;
+            flags |= vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+            // The only kind of descriptor updating after bind is enabled for
+            if b.ty == vk::DescriptorType::SAMPLED_IMAGE {
+                flags |= vk::DescriptorBindingFlags::UPDATE_AFTER_BIND;
+            }
+
+            // Only the last binding of a set may be variably sized
+            if Some(b.binding) == last {
+                flags |= vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT;
+                variable_count = Some(capacity);
+            }
+        }
+
+        layout_bindings.push(
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(b.binding)
+                .descriptor_type(b.ty)
+                .descriptor_count(count)
+                .stage_flags(b.stages)
+                .build(),
+        );
+        binding_flags.push(flags);
+    }
+
+    let mut sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
+    for b in &layout_bindings {
+        match sizes.iter_mut().find(|s| s.ty == b.descriptor_type) {
+            Some(size) => size.descriptor_count += b.descriptor_count,
+            None => sizes.push(vk::DescriptorPoolSize {
+                ty: b.descriptor_type,
+                descriptor_count: b.descriptor_count,
+            }),
+        }
+    }
+
+    let mut flags_info =
+        vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&binding_flags);
+    let mut layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+    if bindless {
+        layout_info = layout_info
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .push_next(&mut flags_info);
+    }
+
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .unwrap()
+    };
+
+    SetLayout {
+        layout,
+        sizes,
+        variable_count,
+    }
+}
+
+/// Allocates descriptor sets from growable pools, sets live as long as the allocator.
+pub struct Descriptors {
+    device: Arc<Device>,
+
+    update_after_bind: bool,
+    pools: Vec<vk::DescriptorPool>,
+    /// Pool allocated from, earlier ones are full.
+    current: usize,
+}
+
+impl Descriptors {
+    pub fn new(vulkan: &Vulkan) -> Self {
+        Self {
+            device: vulkan.clone_device(),
+
+            update_after_bind: vulkan.descriptor_indexing(),
+            pools: vec![],
+            current: 0,
+        }
+    }
+
+    pub fn write_image(
+        &self,
+        set: vk::DescriptorSet,
+        binding: u32,
+        ty: vk::DescriptorType,
+        info: vk::DescriptorImageInfo,
+    ) {
+        let infos = [info];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(binding)
+            .descriptor_type(ty)
+            .image_info(&infos)
+            .build()];
+
+        unsafe { self.device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    pub fn write_buffer(
+        &self,
+        set: vk::DescriptorSet,
+        binding: u32,
+        ty: vk::DescriptorType,
+        info: vk::DescriptorBufferInfo,
+    ) {
+        let infos = [info];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(binding)
+            .descriptor_type(ty)
+            .buffer_info(&infos)
+            .build()];
+
+        unsafe { self.device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    pub fn allocate(&mut self, layout: &PipelineLayout, set: u32) -> vk::DescriptorSet {
+        let set = &layout.sets[set as usize];
+        let set_layouts = [set.layout];
+        let counts: Vec<_> = set.variable_count.iter().copied().collect();
+
+        loop {
+            if self.current == self.pools.len() {
+                let pool = self.create_pool(&set.sizes);
+                self.pools.push(pool);
+            }
+            let pool = self.pools[self.current];
+
+            let mut count_info = vk::DescriptorSetVariableDescriptorCountAllocateInfo::builder()
+                .descriptor_counts(&counts);
+            let mut allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&set_layouts);
+            if !counts.is_empty() {
+                allocate_info = allocate_info.push_next(&mut count_info);
+            }
+
+            match unsafe { self.device.allocate_descriptor_sets(&allocate_info) } {
+                Ok(sets) => return sets[0],
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+                | Err(vk::Result::ERROR_FRAGMENTED_POOL) => self.current += 1,
+                Err(e) => panic!("failed to allocate descriptor set: {}", e),
+            }
+        }
+    }
+
+    /// Creates a pool fitting the common descriptor mix, grown to fit `required` at least once.
+    fn create_pool(&self, required: &[vk::DescriptorPoolSize]) -> vk::DescriptorPool {
+        let mut sizes: Vec<_> = POOL_RATIOS
+            .iter()
+            .map(|&(ty, ratio)| vk::DescriptorPoolSize {
+                ty,
+                descriptor_count: ratio * SETS_PER_POOL,
+            })
+            .collect();
+
+        for r in required {
+            match sizes.iter_mut().find(|s| s.ty == r.ty) {
+                Some(size) => size.descriptor_count = size.descriptor_count.max(r.descriptor_count),
+                None => sizes.push(*r),
+            }
+        }
+
+        let flags = if self.update_after_bind {
+            vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+        } else {
+            vk::DescriptorPoolCreateFlags::empty()
+        };
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(flags)
+            .pool_sizes(&sizes)
+            .max_sets(SETS_PER_POOL);
+
+        unsafe {
+            self.device
+                .create_descriptor_pool(&pool_info, None)
+                .unwrap()
+        }
+    }
+}
+
+impl Drop for Descriptors {
+    fn drop(&mut self) {
+        unsafe {
+            for &pool in &self.pools {
+                self.device.destroy_descriptor_pool(pool, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+
+use crate::gfx::{Error, Result};
+
+/// Command buffer and synchronization of one frame in flight.
+pub struct Frame {
+    device: Arc<Device>,
+
+    pub command_buffer: vk::CommandBuffer,
+    pub in_flight: vk::Fence,
+    pub image_available: vk::Semaphore,
+    pub render_finished: vk::Semaphore,
+}
+
+impl Frame {
+    pub fn new(device: Arc<Device>, command_pool: vk::CommandPool) -> Result<Self> {
+        unsafe {
+            let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .command_pool(command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+
+            let command_buffer = device
+                .allocate_command_buffers(&allocate_info)
+                .map_err(Error::vulkan("allocating a command buffer"))?[0];
+
+            let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+            let semaphore_info = vk::SemaphoreCreateInfo::default();
+
+            let sync = Error::vulkan("creating frame synchronization");
+
+            Ok(Self {
+                command_buffer,
+                in_flight: device.create_fence(&fence_info, None).map_err(&sync)?,
+                image_available: device
+                    .create_semaphore(&semaphore_info, None)
+                    .map_err(&sync)?,
+                render_finished: device
+                    .create_semaphore(&semaphore_info, None)
+                    .map_err(&sync)?,
+
+                device,
+            })
+        }
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        // The command buffer is freed with its pool
+        unsafe {
+            self.device.destroy_semaphore(self.render_finished, None);
+            self.device.destroy_semaphore(self.image_available, None);
+            self.device.destroy_fence(self.in_flight, None);
+        }
+    }
+}
@@ -0,0 +1,472 @@
+//! # GPU selection
+//!
+//! Picks the physical device to render with. Suitable devices are ranked discrete, integrated,
+//! virtual and then CPU, unless the user asks for a device by index or name. `report` describes
+//! every device for `--list-gpus`, to debug machines we can't get our hands on.
+
+use std::ffi::CStr;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use ash::extensions::khr::{Surface, Swapchain as AshSwapchain};
+use ash::version::InstanceV1_0;
+use ash::{vk, Instance};
+
+use crate::gfx::Error;
+
+/// Formats listed in reports, the ones we render to or might.
+const REPORTED_FORMATS: [vk::Format; 9] = [
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::B8G8R8A8_SRGB,
+    vk::Format::R8G8B8A8_UNORM,
+    vk::Format::B8G8R8A8_UNORM,
+    vk::Format::A2B10G10R10_UNORM_PACK32,
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::D16_UNORM,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D32_SFLOAT,
+];
+
+/// A user's choice of GPU, by index as listed by `--list-gpus` or by part of its name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpuSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for GpuSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("empty GPU name".to_string());
+        }
+
+        Ok(s.parse()
+            .map_or_else(|_| GpuSelector::Name(s.to_string()), GpuSelector::Index))
+    }
+}
+
+impl fmt::Display for GpuSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GpuSelector::Index(index) => write!(f, "{}", index),
+            GpuSelector::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A physical device and whether we can render with it.
+struct Candidate {
+    name: String,
+    device_type: vk::PhysicalDeviceType,
+    /// Queue family to use, or why the device is unsuitable.
+    suitability: Result<u32, String>,
+}
+
+/// Picks a physical device and its graphics queue family. Windowed rendering passes the surface
+/// to present to, which the queue family must support.
+pub unsafe fn select(
+    instance: &Instance,
+    surface: Option<(&Surface, vk::SurfaceKHR)>,
+    selector: Option<&GpuSelector>,
+) -> Result<(vk::PhysicalDevice, u32), Error> {
+    let physical_devices = instance
+        .enumerate_physical_devices()
+        .map_err(Error::vulkan("enumerating GPUs"))?;
+
+    let candidates: Vec<_> = physical_devices
+        .iter()
+        .map(|&pdevice| inspect(instance, pdevice, surface))
+        .collect();
+
+    let index = choose(&candidates, selector).map_err(Error::NoSuitableDevice)?;
+    let candidate = &candidates[index];
+    log::info!(
+        "using GPU {} '{}' ({})",
+        index,
+        candidate.name,
+        device_type_name(candidate.device_type)
+    );
+
+    Ok((
+        physical_devices[index],
+        *candidate.suitability.as_ref().unwrap(),
+    ))
+}
+
+unsafe fn inspect(
+    instance: &Instance,
+    pdevice: vk::PhysicalDevice,
+    surface: Option<(&Surface, vk::SurfaceKHR)>,
+) -> Candidate {
+    let properties = instance.get_physical_device_properties(pdevice);
+
+    Candidate {
+        name: device_name(&properties),
+        device_type: properties.device_type,
+        suitability: check(instance, pdevice, &properties, surface),
+    }
+}
+
+unsafe fn check(
+    instance: &Instance,
+    pdevice: vk::PhysicalDevice,
+    properties: &vk::PhysicalDeviceProperties,
+    surface: Option<(&Surface, vk::SurfaceKHR)>,
+) -> Result<u32, String> {
+    // Required for `get_physical_device_features2`
+    if properties.api_version < vk::make_version(1, 1, 0) {
+        return Err(format!(
+            "supports Vulkan {}, 1.1 is required",
+            version_name(properties.api_version)
+        ));
+    }
+
+    let features = instance.get_physical_device_features(pdevice);
+    if features.shader_clip_distance != vk::TRUE {
+        return Err("missing feature shaderClipDistance".to_string());
+    }
+
+    if surface.is_some() {
+        let extensions = instance
+            .enumerate_device_extension_properties(pdevice)
+            .map_err(|e| format!("failed to enumerate extensions: {}", e))?;
+        let swapchain = extensions
+            .iter()
+            .any(|ext| CStr::from_ptr(ext.extension_name.as_ptr()) == AshSwapchain::name());
+        if !swapchain {
+            return Err(format!(
+                "missing extension {}",
+                AshSwapchain::name().to_string_lossy()
+            ));
+        }
+    }
+
+    instance
+        .get_physical_device_queue_family_properties(pdevice)
+        .iter()
+        .enumerate()
+        .position(|(index, info)| {
+            let supports_graphics = info.queue_flags.contains(vk::QueueFlags::GRAPHICS);
+            let supports_surface = match surface {
+                Some((surface_loader, surface)) => surface_loader
+                    .get_physical_device_surface_support(pdevice, index as u32, surface)
+                    .unwrap_or(false),
+                None => true,
+            };
+
+            supports_graphics && supports_surface
+        })
+        .map(|index| index as u32)
+        .ok_or_else(|| match surface {
+            Some(_) => "no queue family supports graphics and presenting to the window".to_string(),
+            None => "no queue family supports graphics".to_string(),
+        })
+}
+
+/// Index of the candidate to use, or a message explaining why there is none.
+fn choose(candidates: &[Candidate], selector: Option<&GpuSelector>) -> Result<usize, String> {
+    let index = match selector {
+        Some(GpuSelector::Index(index)) => {
+            if *index >= candidates.len() {
+                return Err(format!(
+                    "GPU {} requested, but only {} found, see --list-gpus",
+                    index,
+                    candidates.len()
+                ));
+            }
+            *index
+        }
+        Some(GpuSelector::Name(name)) => {
+            let needle = name.to_lowercase();
+            let matching: Vec<_> = (0..candidates.len())
+                .filter(|&i| candidates[i].name.to_lowercase().contains(&needle))
+                .collect();
+
+            match matching
+                .iter()
+                .find(|&&i| candidates[i].suitability.is_ok())
+            {
+                Some(&index) => index,
+                None if matching.is_empty() => {
+                    return Err(format!("no GPU named '{}', see --list-gpus", name))
+                }
+                None => matching[0],
+            }
+        }
+        None => {
+            let best = (0..candidates.len())
+                .filter(|&i| candidates[i].suitability.is_ok())
+                .fold(None, |best: Option<usize>, i| match best {
+                    Some(b)
+                        if score(candidates[b].device_type) >= score(candidates[i].device_type) =>
+                    {
+                        Some(b)
+                    }
+                    _ => Some(i),
+                });
+
+            return best.ok_or_else(|| {
+                let mut message = String::from("no suitable GPU found");
+                if candidates.is_empty() {
+                    message.push_str(", is a Vulkan driver installed?");
+                }
+                for (i, candidate) in candidates.iter().enumerate() {
+                    let reason = candidate.suitability.as_ref().unwrap_err();
+                    write!(message, "\n  {} '{}': {}", i, candidate.name, reason).unwrap();
+                }
+                message
+            });
+        }
+    };
+
+    match &candidates[index].suitability {
+        Ok(_) => Ok(index),
+        Err(reason) => Err(format!(
+            "GPU {} '{}' can't be used: {}",
+            index, candidates[index].name, reason
+        )),
+    }
+}
+
+fn score(device_type: vk::PhysicalDeviceType) -> u32 {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 1,
+        _ => 0,
+    }
+}
+
+/// Describes every physical device with its queue families, limits and format support.
+pub unsafe fn report(instance: &Instance) -> String {
+    let physical_devices = match instance.enumerate_physical_devices() {
+        Ok(devices) => devices,
+        Err(e) => return format!("failed to enumerate Vulkan physical devices: {}\n", e),
+    };
+    if physical_devices.is_empty() {
+        return "no Vulkan physical devices found, is a Vulkan driver installed?\n".to_string();
+    }
+
+    let mut out = String::new();
+    for (i, &pdevice) in physical_devices.iter().enumerate() {
+        let properties = instance.get_physical_device_properties(pdevice);
+        let limits = &properties.limits;
+
+        writeln!(
+            out,
+            "GPU {}: {} ({})",
+            i,
+            device_name(&properties),
+            device_type_name(properties.device_type)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  Vulkan {}, driver {:#x}, vendor {:#06x}, device {:#06x}",
+            version_name(properties.api_version),
+            properties.driver_version,
+            properties.vendor_id,
+            properties.device_id
+        )
+        .unwrap();
+        match check(instance, pdevice, &properties, None) {
+            Ok(_) => writeln!(out, "  Suitable for headless rendering").unwrap(),
+            Err(reason) => writeln!(out, "  Unsuitable: {}", reason).unwrap(),
+        }
+
+        writeln!(out, "  Queue families:").unwrap();
+        for (index, family) in instance
+            .get_physical_device_queue_family_properties(pdevice)
+            .iter()
+            .enumerate()
+        {
+            writeln!(
+                out,
+                "    {}: {} queues, {:?}",
+                index, family.queue_count, family.queue_flags
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "  Memory heaps:").unwrap();
+        let memory = instance.get_physical_device_memory_properties(pdevice);
+        for (index, heap) in memory.memory_heaps[..memory.memory_heap_count as usize]
+            .iter()
+            .enumerate()
+        {
+            writeln!(
+                out,
+                "    {}: {} MiB, {:?}",
+                index,
+                heap.size / (1024 * 1024),
+                heap.flags
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "  Limits:").unwrap();
+        writeln!(out, "    max image size: {}", limits.max_image_dimension2_d).unwrap();
+        writeln!(
+            out,
+            "    max array layers: {}",
+            limits.max_image_array_layers
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    max push constants: {} bytes",
+            limits.max_push_constants_size
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    max bound descriptor sets: {}",
+            limits.max_bound_descriptor_sets
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    max sampler anisotropy: {}",
+            limits.max_sampler_anisotropy
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    framebuffer samples: color {:?}, depth {:?}",
+            limits.framebuffer_color_sample_counts, limits.framebuffer_depth_sample_counts
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    max draw indirect count: {}",
+            limits.max_draw_indirect_count
+        )
+        .unwrap();
+
+        writeln!(out, "  Formats (optimal tiling):").unwrap();
+        for &format in &REPORTED_FORMATS {
+            let features = instance
+                .get_physical_device_format_properties(pdevice, format)
+                .optimal_tiling_features;
+            writeln!(out, "    {:?}: {}", format, format_features_name(features)).unwrap();
+        }
+    }
+    out
+}
+
+fn device_name(properties: &vk::PhysicalDeviceProperties) -> String {
+    unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn device_type_name(device_type: vk::PhysicalDeviceType) -> &'static str {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => "discrete GPU",
+        vk::PhysicalDeviceType::INTEGRATED_GPU => "integrated GPU",
+        vk::PhysicalDeviceType::VIRTUAL_GPU => "virtual GPU",
+        vk::PhysicalDeviceType::CPU => "CPU",
+        _ => "other",
+    }
+}
+
+fn version_name(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::version_major(version),
+        vk::version_minor(version),
+        vk::version_patch(version)
+    )
+}
+
+fn format_features_name(features: vk::FormatFeatureFlags) -> String {
+    let names: Vec<_> = [
+        (vk::FormatFeatureFlags::SAMPLED_IMAGE, "sampled"),
+        (vk::FormatFeatureFlags::COLOR_ATTACHMENT, "color attachment"),
+        (vk::FormatFeatureFlags::COLOR_ATTACHMENT_BLEND, "blending"),
+        (
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+            "depth attachment",
+        ),
+        (vk::FormatFeatureFlags::TRANSFER_SRC, "transfer source"),
+    ]
+    .iter()
+    .filter(|(flag, _)| features.contains(*flag))
+    .map(|&(_, name)| name)
+    .collect();
+
+    if names.is_empty() {
+        "unsupported".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, device_type: vk::PhysicalDeviceType, suitable: bool) -> Candidate {
+        Candidate {
+            name: name.to_string(),
+            device_type,
+            suitability: if suitable {
+                Ok(0)
+            } else {
+                Err("missing feature".to_string())
+            },
+        }
+    }
+
+    #[test]
+    fn prefers_discrete_over_integrated_over_cpu() {
+        let candidates = [
+            candidate("llvmpipe", vk::PhysicalDeviceType::CPU, true),
+            candidate("Intel UHD", vk::PhysicalDeviceType::INTEGRATED_GPU, true),
+            candidate("GeForce", vk::PhysicalDeviceType::DISCRETE_GPU, true),
+            candidate("Radeon", vk::PhysicalDeviceType::DISCRETE_GPU, true),
+        ];
+        assert_eq!(choose(&candidates, None), Ok(2));
+        assert_eq!(choose(&candidates[..2], None), Ok(1));
+    }
+
+    #[test]
+    fn skips_unsuitable_devices() {
+        let candidates = [
+            candidate("GeForce", vk::PhysicalDeviceType::DISCRETE_GPU, false),
+            candidate("llvmpipe", vk::PhysicalDeviceType::CPU, true),
+        ];
+        assert_eq!(choose(&candidates, None), Ok(1));
+
+        let error = choose(&candidates[..1], None).unwrap_err();
+        assert!(error.contains("0 'GeForce': missing feature"), "{}", error);
+    }
+
+    #[test]
+    fn selects_by_index_or_name() {
+        let candidates = [
+            candidate("GeForce", vk::PhysicalDeviceType::DISCRETE_GPU, true),
+            candidate("llvmpipe (LLVM 12)", vk::PhysicalDeviceType::CPU, true),
+        ];
+        let select = |s: &str| choose(&candidates, Some(&s.parse().unwrap()));
+
+        assert_eq!(select("1"), Ok(1));
+        assert_eq!(select("LLVMpipe"), Ok(1));
+        assert!(select("2").unwrap_err().contains("only 2 found"));
+        assert!(select("radeon").unwrap_err().contains("no GPU named"));
+    }
+
+    #[test]
+    fn rejects_selected_unsuitable_device() {
+        let candidates = [
+            candidate("GeForce", vk::PhysicalDeviceType::DISCRETE_GPU, true),
+            candidate("Quadro", vk::PhysicalDeviceType::DISCRETE_GPU, false),
+        ];
+        let error = choose(&candidates, Some(&GpuSelector::Index(1))).unwrap_err();
+        assert_eq!(error, "GPU 1 'Quadro' can't be used: missing feature");
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+
+use crate::gfx::{Error, Result};
+
+/// A single level 2D image in device local memory with a view of the whole image.
+pub struct Image {
+    device: Arc<Device>,
+
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+
+    format: vk::Format,
+    extent: vk::Extent2D,
+}
+
+impl Image {
+    pub fn new(
+        device: Arc<Device>,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect: vk::ImageAspectFlags,
+    ) -> Result<Self> {
+        Self::multisampled(
+            device,
+            memory_properties,
+            extent,
+            format,
+            usage,
+            aspect,
+            vk::SampleCountFlags::TYPE_1,
+        )
+    }
+
+    /// An image with `samples` per pixel, for multisampled attachments.
+    pub fn multisampled(
+        device: Arc<Device>,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect: vk::ImageAspectFlags,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self> {
+        unsafe {
+            let image_info = vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(format)
+                .extent(vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(samples)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED);
+
+            let error = Error::vulkan("creating an image");
+
+            let image = device.create_image(&image_info, None).map_err(&error)?;
+
+            let requirements = device.get_image_memory_requirements(image);
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(find_memory_type(
+                    memory_properties,
+                    &requirements,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                ));
+
+            let memory = device
+                .allocate_memory(&allocate_info, None)
+                .map_err(&error)?;
+            device.bind_image_memory(image, memory, 0).map_err(&error)?;
+
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: aspect,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .image(image);
+
+            let view = device.create_image_view(&view_info, None).map_err(&error)?;
+
+            Ok(Self {
+                device,
+
+                image,
+                memory,
+                view,
+
+                format,
+                extent,
+            })
+        }
+    }
+
+    pub fn image(&self) -> vk::Image {
+        self.image
+    }
+
+    pub fn view(&self) -> vk::ImageView {
+        self.view
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+pub fn find_memory_type(
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    requirements: &vk::MemoryRequirements,
+    flags: vk::MemoryPropertyFlags,
+) -> u32 {
+    memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+        .iter()
+        .enumerate()
+        .find(|(index, memory_type)| {
+            (1 << index) & requirements.memory_type_bits != 0
+                && memory_type.property_flags.contains(flags)
+        })
+        .map(|(index, _)| index as u32)
+        .expect("no suitable Vulkan memory type")
+}
@@ -1,5 +1,32 @@
+mod arena;
+mod buffer;
 mod debug;
+mod descriptors;
+mod frame;
+mod gpu;
+mod image;
+mod msaa;
+mod present;
+mod reflect;
+mod shader;
+mod shadow_map;
+mod surface;
 mod swapchain;
+mod target;
+mod texture;
+#[allow(clippy::module_inception)]
 mod vulkan;
 
-pub use vulkan::Vulkan;
+pub use arena::{Allocation, Arena};
+pub use buffer::Buffer;
+pub use debug::{Validation, VALIDATION_ENV};
+pub use descriptors::{Descriptors, PipelineLayout};
+pub use gpu::GpuSelector;
+pub use msaa::Msaa;
+pub use present::PresentMode;
+pub use shader::{reload_shader, Shader};
+pub use shadow_map::ShadowMap;
+pub use surface::OutputEncoding;
+pub use target::MIN_RENDER_SCALE;
+pub use texture::Texture;
+pub use vulkan::{Stage, Vulkan, FRAMES_IN_FLIGHT};
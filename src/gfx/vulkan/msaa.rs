@@ -0,0 +1,94 @@
+//! # Multisampling
+//!
+//! Multisample anti-aliasing of the main render pass, chosen by the `msaa` setting. Devices
+//! support different sample counts, so the closest one they do without going over is used.
+
+use std::fmt;
+use std::str::FromStr;
+
+use ash::vk;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Msaa {
+    #[default]
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl Msaa {
+    pub fn to_vk(self) -> vk::SampleCountFlags {
+        match self {
+            Msaa::Off => vk::SampleCountFlags::TYPE_1,
+            Msaa::X2 => vk::SampleCountFlags::TYPE_2,
+            Msaa::X4 => vk::SampleCountFlags::TYPE_4,
+            Msaa::X8 => vk::SampleCountFlags::TYPE_8,
+        }
+    }
+
+    /// The most samples up to `self` that are in `supported`, one sample is always supported.
+    pub fn choose(self, supported: vk::SampleCountFlags) -> vk::SampleCountFlags {
+        [Msaa::X8, Msaa::X4, Msaa::X2]
+            .iter()
+            .map(|msaa| msaa.to_vk())
+            .find(|&samples| {
+                samples.as_raw() <= self.to_vk().as_raw() && supported.contains(samples)
+            })
+            .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
+}
+
+impl FromStr for Msaa {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "1" => Ok(Msaa::Off),
+            "2" => Ok(Msaa::X2),
+            "4" => Ok(Msaa::X4),
+            "8" => Ok(Msaa::X8),
+            _ => Err(format!(
+                "unknown msaa mode '{}', expected off, 2, 4 or 8",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Msaa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Msaa::Off => "no msaa",
+            Msaa::X2 => "2x msaa",
+            Msaa::X4 => "4x msaa",
+            Msaa::X8 => "8x msaa",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_to_supported_samples() {
+        let supported = vk::SampleCountFlags::TYPE_1
+            | vk::SampleCountFlags::TYPE_2
+            | vk::SampleCountFlags::TYPE_4;
+        assert_eq!(Msaa::X8.choose(supported), vk::SampleCountFlags::TYPE_4);
+        assert_eq!(Msaa::X2.choose(supported), vk::SampleCountFlags::TYPE_2);
+        assert_eq!(Msaa::Off.choose(supported), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(
+            Msaa::X4.choose(vk::SampleCountFlags::TYPE_1),
+            vk::SampleCountFlags::TYPE_1
+        );
+    }
+
+    #[test]
+    fn parses_sample_counts() {
+        assert_eq!("4".parse(), Ok(Msaa::X4));
+        assert_eq!("off".parse(), Ok(Msaa::Off));
+        assert!("16".parse::<Msaa>().is_err());
+    }
+}
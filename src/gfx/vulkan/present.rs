@@ -0,0 +1,96 @@
+//! # Present modes
+//!
+//! How swapchain images reach the screen, chosen by the `vsync` setting. FIFO is the only mode
+//! every driver supports, so others fall back to it.
+
+use std::fmt;
+use std::str::FromStr;
+
+use ash::vk;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// VSync on, waits for the vertical blank.
+    Fifo,
+    /// Adaptive VSync, late frames are presented right away and may tear.
+    FifoRelaxed,
+    /// VSync off, tears.
+    Immediate,
+    /// Renders unthrottled without tearing, replacing queued frames with newer ones.
+    #[default]
+    Mailbox,
+}
+
+impl PresentMode {
+    pub fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+        }
+    }
+
+    /// `self` if the surface supports it, FIFO otherwise.
+    pub fn choose(self, supported: &[vk::PresentModeKHR]) -> PresentMode {
+        if supported.contains(&self.to_vk()) {
+            self
+        } else {
+            PresentMode::Fifo
+        }
+    }
+}
+
+impl FromStr for PresentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "on" | "fifo" => Ok(PresentMode::Fifo),
+            "adaptive" | "fifo_relaxed" => Ok(PresentMode::FifoRelaxed),
+            "off" | "immediate" => Ok(PresentMode::Immediate),
+            "mailbox" => Ok(PresentMode::Mailbox),
+            _ => Err(format!(
+                "unknown vsync mode '{}', expected on, off, adaptive or mailbox",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PresentMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PresentMode::Fifo => "vsync",
+            PresentMode::FifoRelaxed => "adaptive vsync",
+            PresentMode::Immediate => "no vsync",
+            PresentMode::Mailbox => "mailbox",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_fifo() {
+        let supported = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
+        assert_eq!(
+            PresentMode::Immediate.choose(&supported),
+            PresentMode::Immediate
+        );
+        assert_eq!(PresentMode::Mailbox.choose(&supported), PresentMode::Fifo);
+        assert_eq!(
+            PresentMode::FifoRelaxed.choose(&supported),
+            PresentMode::Fifo
+        );
+    }
+
+    #[test]
+    fn parses_aliases() {
+        assert_eq!("off".parse(), Ok(PresentMode::Immediate));
+        assert_eq!("adaptive".parse(), Ok(PresentMode::FifoRelaxed));
+        assert!("sometimes".parse::<PresentMode>().is_err());
+    }
+}
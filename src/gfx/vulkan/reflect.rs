@@ -0,0 +1,501 @@
+//! # Reflect
+//!
+//! Minimal SPIR-V reflection extracting what pipeline layouts need from a shader module: its
+//! stage, the descriptor bindings it declares and the size of its push constant block.
+//!
+//! https://www.khronos.org/registry/SPIR-V/specs/unified1/SPIRV.html
+
+use std::collections::HashMap;
+
+use ash::vk;
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_LEN: usize = 5;
+
+// Opcodes
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Decorations
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub ty: vk::DescriptorType,
+    /// Array length, `0` for runtime sized (bindless) arrays.
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+}
+
+impl DescriptorBinding {
+    pub fn is_bindless(&self) -> bool {
+        self.count == 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Reflection {
+    pub stage: vk::ShaderStageFlags,
+    pub bindings: Vec<DescriptorBinding>,
+    /// Size of the push constant block in bytes, `0` if the shader has none.
+    pub push_constants: u32,
+}
+
+#[derive(Clone, Copy)]
+enum Type {
+    Scalar(u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Image {
+        dim: u32,
+        sampled: u32,
+    },
+    Sampler,
+    SampledImage,
+    Array(u32, u32),
+    RuntimeArray(u32),
+    Struct,
+    /// Pointee type, the storage class isn't needed.
+    Pointer(u32),
+}
+
+#[derive(Default)]
+struct Module {
+    stage: vk::ShaderStageFlags,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<(u32, u32, u32)>,
+    members: HashMap<u32, Vec<u32>>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+pub fn reflect(words: &[u32]) -> Result<Reflection, String> {
+    if words.len() < HEADER_LEN || words[0] != MAGIC {
+        return Err("not a SPIR-V module".to_string());
+    }
+
+    let mut module = Module::default();
+    let mut i = HEADER_LEN;
+    while i < words.len() {
+        let len = (words[i] >> 16) as usize;
+        let opcode = words[i] & 0xffff;
+        if len == 0 || i + len > words.len() {
+            return Err(format!("malformed SPIR-V instruction at word {}", i));
+        }
+
+        module
+            .instruction(opcode, &words[i + 1..i + len])
+            .map_err(|e| format!("{} at word {}", e, i))?;
+        i += len;
+    }
+
+    module.reflection()
+}
+
+/// Operand words the SPIR-V spec requires at least for the instructions reflected here.
+fn min_operands(opcode: u32) -> usize {
+    match opcode {
+        OP_TYPE_SAMPLER | OP_TYPE_STRUCT => 1,
+        OP_TYPE_FLOAT | OP_TYPE_SAMPLED_IMAGE | OP_TYPE_RUNTIME_ARRAY | OP_DECORATE => 2,
+        OP_ENTRY_POINT | OP_TYPE_INT | OP_TYPE_VECTOR | OP_TYPE_MATRIX | OP_TYPE_ARRAY
+        | OP_TYPE_POINTER | OP_CONSTANT | OP_VARIABLE | OP_MEMBER_DECORATE => 3,
+        OP_TYPE_IMAGE => 8,
+        _ => 0,
+    }
+}
+
+impl Module {
+    fn instruction(&mut self, opcode: u32, ops: &[u32]) -> Result<(), String> {
+        if ops.len() < min_operands(opcode) {
+            return Err(format!("truncated SPIR-V instruction {}", opcode));
+        }
+
+        match opcode {
+            OP_ENTRY_POINT => self.stage |= execution_model_stage(ops[0]),
+            OP_TYPE_INT | OP_TYPE_FLOAT => {
+                self.types.insert(ops[0], Type::Scalar(ops[1] / 8));
+            }
+            OP_TYPE_VECTOR => {
+                self.types.insert(ops[0], Type::Vector(ops[1], ops[2]));
+            }
+            OP_TYPE_MATRIX => {
+                self.types.insert(ops[0], Type::Matrix(ops[1], ops[2]));
+            }
+            OP_TYPE_IMAGE => {
+                let image = Type::Image {
+                    dim: ops[2],
+                    sampled: ops[6],
+                };
+                self.types.insert(ops[0], image);
+            }
+            OP_TYPE_SAMPLER => {
+                self.types.insert(ops[0], Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                self.types.insert(ops[0], Type::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                self.types.insert(ops[0], Type::Array(ops[1], ops[2]));
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(ops[0], Type::RuntimeArray(ops[1]));
+            }
+            OP_TYPE_STRUCT => {
+                self.types.insert(ops[0], Type::Struct);
+                self.members.insert(ops[0], ops[1..].to_vec());
+            }
+            OP_TYPE_POINTER => {
+                self.types.insert(ops[0], Type::Pointer(ops[2]));
+            }
+            // Only 32-bit constants are used as array lengths
+            OP_CONSTANT if ops.len() == 3 => {
+                self.constants.insert(ops[1], ops[2]);
+            }
+            OP_VARIABLE => self.variables.push((ops[0], ops[1], ops[2])),
+            OP_DECORATE => {
+                let value = ops.get(2).copied().unwrap_or(0);
+                self.decorations.insert((ops[0], ops[1]), value);
+            }
+            OP_MEMBER_DECORATE => {
+                let value = ops.get(3).copied().unwrap_or(0);
+                self.member_decorations
+                    .insert((ops[0], ops[1], ops[2]), value);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn reflection(&self) -> Result<Reflection, String> {
+        let mut bindings = Vec::new();
+        let mut push_constants = 0;
+
+        for &(pointer, id, storage) in &self.variables {
+            let pointee = match self.types.get(&pointer) {
+                Some(&Type::Pointer(pointee)) => pointee,
+                _ => continue,
+            };
+
+            match storage {
+                STORAGE_PUSH_CONSTANT => push_constants = self.size_of(pointee, None)?,
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    let (set, binding) = match (
+                        self.decorations.get(&(id, DECORATION_DESCRIPTOR_SET)),
+                        self.decorations.get(&(id, DECORATION_BINDING)),
+                    ) {
+                        (Some(&set), Some(&binding)) => (set, binding),
+                        _ => continue,
+                    };
+
+                    let (element, count) = match self.type_of(pointee)? {
+                        Type::Array(element, length) => (element, self.constant(length)?),
+                        Type::RuntimeArray(element) => (element, 0),
+                        _ => (pointee, 1),
+                    };
+
+                    bindings.push(DescriptorBinding {
+                        set,
+                        binding,
+                        ty: self.descriptor_type(element, storage)?,
+                        count,
+                        stages: self.stage,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        bindings.sort_by_key(|b| (b.set, b.binding));
+
+        Ok(Reflection {
+            stage: self.stage,
+            bindings,
+            push_constants,
+        })
+    }
+
+    fn type_of(&self, id: u32) -> Result<Type, String> {
+        self.types
+            .get(&id)
+            .copied()
+            .ok_or_else(|| format!("unsupported or missing type %{}", id))
+    }
+
+    /// Value of a constant, array lengths from specialization constants aren't supported.
+    fn constant(&self, id: u32) -> Result<u32, String> {
+        self.constants
+            .get(&id)
+            .copied()
+            .ok_or_else(|| format!("array length %{} is not a constant", id))
+    }
+
+    fn descriptor_type(&self, id: u32, storage: u32) -> Result<vk::DescriptorType, String> {
+        Ok(match (self.type_of(id)?, storage) {
+            (Type::Sampler, _) => vk::DescriptorType::SAMPLER,
+            (Type::SampledImage, _) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (Type::Image { dim, sampled }, _) => match (dim, sampled) {
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            (_, STORAGE_STORAGE_BUFFER) => vk::DescriptorType::STORAGE_BUFFER,
+            // Pre SPIR-V 1.3 storage buffers
+            _ if self
+                .decorations
+                .contains_key(&(id, DECORATION_BUFFER_BLOCK)) =>
+            {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            _ => vk::DescriptorType::UNIFORM_BUFFER,
+        })
+    }
+
+    /// Size in bytes of a type laid out by explicit offsets and strides.
+    fn size_of(&self, id: u32, matrix_stride: Option<u32>) -> Result<u32, String> {
+        Ok(match self.type_of(id)? {
+            Type::Scalar(bytes) => bytes,
+            Type::Vector(component, count) => self.size_of(component, None)? * count,
+            Type::Matrix(column, count) => match matrix_stride {
+                Some(stride) => stride * count,
+                None => self.size_of(column, None)? * count,
+            },
+            Type::Array(element, length) => {
+                let stride = match self.decorations.get(&(id, DECORATION_ARRAY_STRIDE)) {
+                    Some(&stride) => stride,
+                    None => self.size_of(element, None)?,
+                };
+                stride * self.constant(length)?
+            }
+            Type::Struct => self
+                .members
+                .get(&id)
+                .ok_or_else(|| format!("struct %{} has no members", id))?
+                .iter()
+                .enumerate()
+                .map(|(index, &member)| {
+                    let index = index as u32;
+                    let offset = self
+                        .member_decorations
+                        .get(&(id, index, DECORATION_OFFSET))
+                        .copied()
+                        .unwrap_or(0);
+                    let stride = self
+                        .member_decorations
+                        .get(&(id, index, DECORATION_MATRIX_STRIDE))
+                        .copied();
+                    Ok(offset + self.size_of(member, stride)?)
+                })
+                .collect::<Result<Vec<_>, String>>()?
+                .into_iter()
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        })
+    }
+}
+
+fn execution_model_stage(model: u32) -> vk::ShaderStageFlags {
+    match model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        _ => vk::ShaderStageFlags::empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ash::util::read_spv;
+
+    use super::*;
+    use crate::shaders::spirv;
+
+    fn op(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    #[test]
+    fn reflects_block_shaders() {
+        let mut frag = Cursor::new(spirv!("block.frag"));
+        let frag = reflect(&read_spv(&mut frag).unwrap()).unwrap();
+
+        assert_eq!(frag.stage, vk::ShaderStageFlags::FRAGMENT);
+        let types: Vec<_> = frag
+            .bindings
+            .iter()
+            .map(|b| (b.set, b.binding, b.ty))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                (0, 0, vk::DescriptorType::SAMPLED_IMAGE),
+                (0, 1, vk::DescriptorType::SAMPLER),
+                (0, 2, vk::DescriptorType::UNIFORM_BUFFER),
+                (0, 3, vk::DescriptorType::SAMPLED_IMAGE),
+                (0, 4, vk::DescriptorType::SAMPLER),
+            ]
+        );
+
+        let mut vert = Cursor::new(spirv!("block.vert"));
+        let vert = reflect(&read_spv(&mut vert).unwrap()).unwrap();
+
+        assert_eq!(vert.stage, vk::ShaderStageFlags::VERTEX);
+        assert!(vert.bindings.is_empty());
+    }
+
+    #[test]
+    fn reflects_buffers_arrays_and_push_constants() {
+        const FLOAT: u32 = 1;
+        const VEC4: u32 = 2;
+        const BLOCK: u32 = 3;
+        const UNIFORM_PTR: u32 = 4;
+        const UBO: u32 = 5;
+        const SAMPLER: u32 = 6;
+        const SAMPLERS: u32 = 7;
+        const SAMPLERS_PTR: u32 = 8;
+        const BINDLESS: u32 = 9;
+        const PUSH: u32 = 10;
+        const PUSH_PTR: u32 = 11;
+        const PUSH_VAR: u32 = 12;
+
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 13, 0];
+        // OpEntryPoint Fragment %0 "main"
+        words.extend(op(OP_ENTRY_POINT, &[4, 0, u32::from_le_bytes(*b"main"), 0]));
+        words.extend(op(OP_DECORATE, &[UBO, DECORATION_DESCRIPTOR_SET, 0]));
+        words.extend(op(OP_DECORATE, &[UBO, DECORATION_BINDING, 2]));
+        words.extend(op(OP_DECORATE, &[BINDLESS, DECORATION_DESCRIPTOR_SET, 1]));
+        words.extend(op(OP_DECORATE, &[BINDLESS, DECORATION_BINDING, 0]));
+        words.extend(op(OP_MEMBER_DECORATE, &[PUSH, 0, DECORATION_OFFSET, 0]));
+        words.extend(op(OP_MEMBER_DECORATE, &[PUSH, 1, DECORATION_OFFSET, 16]));
+        words.extend(op(OP_TYPE_FLOAT, &[FLOAT, 32]));
+        words.extend(op(OP_TYPE_VECTOR, &[VEC4, FLOAT, 4]));
+        words.extend(op(OP_TYPE_STRUCT, &[BLOCK, VEC4]));
+        words.extend(op(OP_TYPE_POINTER, &[UNIFORM_PTR, STORAGE_UNIFORM, BLOCK]));
+        words.extend(op(OP_VARIABLE, &[UNIFORM_PTR, UBO, STORAGE_UNIFORM]));
+        words.extend(op(OP_TYPE_SAMPLER, &[SAMPLER]));
+        words.extend(op(OP_TYPE_RUNTIME_ARRAY, &[SAMPLERS, SAMPLER]));
+        words.extend(op(
+            OP_TYPE_POINTER,
+            &[SAMPLERS_PTR, STORAGE_UNIFORM_CONSTANT, SAMPLERS],
+        ));
+        words.extend(op(
+            OP_VARIABLE,
+            &[SAMPLERS_PTR, BINDLESS, STORAGE_UNIFORM_CONSTANT],
+        ));
+        words.extend(op(OP_TYPE_STRUCT, &[PUSH, FLOAT, VEC4]));
+        words.extend(op(
+            OP_TYPE_POINTER,
+            &[PUSH_PTR, STORAGE_PUSH_CONSTANT, PUSH],
+        ));
+        words.extend(op(
+            OP_VARIABLE,
+            &[PUSH_PTR, PUSH_VAR, STORAGE_PUSH_CONSTANT],
+        ));
+
+        let reflection = reflect(&words).unwrap();
+
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(reflection.push_constants, 32);
+        assert_eq!(
+            reflection.bindings,
+            [
+                DescriptorBinding {
+                    set: 0,
+                    binding: 2,
+                    ty: vk::DescriptorType::UNIFORM_BUFFER,
+                    count: 1,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+                DescriptorBinding {
+                    set: 1,
+                    binding: 0,
+                    ty: vk::DescriptorType::SAMPLER,
+                    count: 0,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+            ]
+        );
+        assert!(reflection.bindings[1].is_bindless());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(reflect(&[1, 2, 3, 4, 5]).is_err());
+        assert!(reflect(&[MAGIC, 0, 0, 0, 0, 0x0005_0000]).is_err());
+
+        // Truncated instructions that fit the module but lack operands
+        let header = vec![MAGIC, 0x0001_0000, 0, 6, 0];
+        for &(opcode, ops) in &[
+            (OP_DECORATE, &[1][..]),
+            (OP_TYPE_POINTER, &[1, STORAGE_UNIFORM_CONSTANT][..]),
+            (OP_VARIABLE, &[1, 2][..]),
+            (OP_TYPE_ARRAY, &[1, 2][..]),
+        ] {
+            let mut words = header.clone();
+            words.extend(op(opcode, ops));
+            assert!(reflect(&words).is_err());
+        }
+
+        // A sampler array sized by a specialization constant the reflector doesn't track
+        const SAMPLER: u32 = 1;
+        const LENGTH: u32 = 2;
+        const SAMPLERS: u32 = 3;
+        const SAMPLERS_PTR: u32 = 4;
+        const VAR: u32 = 5;
+
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 6, 0];
+        words.extend(op(OP_DECORATE, &[VAR, DECORATION_DESCRIPTOR_SET, 0]));
+        words.extend(op(OP_DECORATE, &[VAR, DECORATION_BINDING, 0]));
+        words.extend(op(OP_TYPE_SAMPLER, &[SAMPLER]));
+        words.extend(op(OP_TYPE_ARRAY, &[SAMPLERS, SAMPLER, LENGTH]));
+        words.extend(op(
+            OP_TYPE_POINTER,
+            &[SAMPLERS_PTR, STORAGE_UNIFORM_CONSTANT, SAMPLERS],
+        ));
+        words.extend(op(
+            OP_VARIABLE,
+            &[SAMPLERS_PTR, VAR, STORAGE_UNIFORM_CONSTANT],
+        ));
+        assert!(reflect(&words).is_err());
+    }
+}
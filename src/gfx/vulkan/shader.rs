@@ -0,0 +1,100 @@
+use std::ffi::CStr;
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+
+use super::reflect::{self, Reflection};
+use super::Vulkan;
+use crate::gfx::{Error, Result};
+
+const ENTRY_POINT: &[u8] = b"main\0";
+
+pub struct Shader {
+    device: Arc<Device>,
+
+    module: vk::ShaderModule,
+    reflection: Reflection,
+}
+
+impl Shader {
+    pub fn new(device: Arc<Device>, code: &[u32]) -> Result<Self> {
+        let reflection = reflect::reflect(code).map_err(Error::InvalidShader)?;
+        let create_info = vk::ShaderModuleCreateInfo::builder().code(code);
+
+        let module = unsafe {
+            device
+                .create_shader_module(&create_info, None)
+                .map_err(Error::vulkan("creating a shader module"))?
+        };
+
+        Ok(Self {
+            device,
+
+            module,
+            reflection,
+        })
+    }
+
+    pub fn stage(&self) -> vk::ShaderStageFlags {
+        self.reflection.stage
+    }
+
+    pub fn reflection(&self) -> &Reflection {
+        &self.reflection
+    }
+
+    pub fn stage_info(&self) -> vk::PipelineShaderStageCreateInfo {
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(self.stage())
+            .module(self.module)
+            .name(CStr::from_bytes_with_nul(ENTRY_POINT).unwrap())
+            .build()
+    }
+}
+
+/// Hot reloads `code` into whichever shader of `renderer` is named `name`, as listed by
+/// `shaders`, then calls `rebuild` for its pipelines. Returns whether the shader got swapped in,
+/// not if it isn't one of `renderer`'s, fails to load or changes the descriptor layout, which
+/// only takes effect after a restart.
+pub fn reload_shader<T>(
+    renderer: &mut T,
+    vulkan: &Vulkan,
+    name: &str,
+    code: &[u32],
+    shaders: fn(&mut T) -> [(&'static str, &mut Shader); 2],
+    rebuild: impl FnOnce(&mut T, &Vulkan),
+) -> bool {
+    let shader = match IntoIterator::into_iter(shaders(renderer))
+        .find(|(shader_name, _)| *shader_name == name)
+    {
+        Some((_, shader)) => shader,
+        None => return false,
+    };
+
+    let reloaded = match Shader::new(vulkan.clone_device(), code) {
+        Ok(shader) => shader,
+        Err(e) => {
+            log::error!("{}: {}", name, e);
+            return false;
+        }
+    };
+    if reloaded.reflection().bindings != shader.reflection().bindings
+        || reloaded.reflection().push_constants != shader.reflection().push_constants
+    {
+        log::warn!("{}: descriptor layout changed, restart to apply", name);
+        return false;
+    }
+    *shader = reloaded;
+
+    rebuild(renderer, vulkan);
+    true
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_shader_module(self.module, None);
+        }
+    }
+}
@@ -0,0 +1,362 @@
+//! # Shadow map
+//!
+//! A depth texture array with a layer per shadow cascade, rendered in depth-only passes of its
+//! own before the main render pass and sampled with depth comparison afterwards. Layers stay in
+//! a read-only layout between passes, so the map can be bound before it's ever rendered.
+
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+
+use super::Vulkan;
+use crate::gfx::{Error, Result};
+
+pub struct ShadowMap {
+    device: Arc<Device>,
+
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    /// View of all layers for sampling.
+    view: vk::ImageView,
+    /// A view and framebuffer for rendering each layer.
+    layer_views: Vec<vk::ImageView>,
+    framebuffers: Vec<vk::Framebuffer>,
+    render_pass: vk::RenderPass,
+    sampler: vk::Sampler,
+
+    size: u32,
+}
+
+impl ShadowMap {
+    pub const FORMAT: vk::Format = vk::Format::D16_UNORM;
+
+    /// Layout layers are in outside of shadow passes.
+    const LAYOUT: vk::ImageLayout = vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL;
+
+    pub fn new(vulkan: &Vulkan, size: u32, layers: u32) -> Result<Self> {
+        let device = vulkan.clone_device();
+
+        let subresource_range = |base_array_layer, layer_count| vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer,
+            layer_count,
+        };
+
+        unsafe {
+            let error = Error::vulkan("creating the shadow map");
+
+            // === IMAGE ===
+
+            let image_info = vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(Self::FORMAT)
+                .extent(vk::Extent3D {
+                    width: size,
+                    height: size,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(layers)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(
+                    vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                        | vk::ImageUsageFlags::SAMPLED
+                        | vk::ImageUsageFlags::TRANSFER_DST,
+                )
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED);
+
+            let image = device.create_image(&image_info, None).map_err(&error)?;
+
+            let requirements = device.get_image_memory_requirements(image);
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(
+                    vulkan.find_memory_type(&requirements, vk::MemoryPropertyFlags::DEVICE_LOCAL),
+                );
+
+            let memory = device
+                .allocate_memory(&allocate_info, None)
+                .map_err(&error)?;
+            device.bind_image_memory(image, memory, 0).map_err(&error)?;
+
+            // Cleared to the far plane, so a map that was never rendered shadows nothing
+            vulkan.one_time_commands(|device, command_buffer| {
+                let to_transfer = [vk::ImageMemoryBarrier::builder()
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .image(image)
+                    .subresource_range(subresource_range(0, layers))
+                    .build()];
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &to_transfer,
+                );
+
+                device.cmd_clear_depth_stencil_image(
+                    command_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &vk::ClearDepthStencilValue {
+                        depth: 1.0,
+                        stencil: 0,
+                    },
+                    &[subresource_range(0, layers)],
+                );
+
+                let to_shader = [vk::ImageMemoryBarrier::builder()
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(Self::LAYOUT)
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .image(image)
+                    .subresource_range(subresource_range(0, layers))
+                    .build()];
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &to_shader,
+                );
+            });
+
+            // === VIEWS ===
+
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                .format(Self::FORMAT)
+                .subresource_range(subresource_range(0, layers))
+                .image(image);
+            let view = device.create_image_view(&view_info, None).map_err(&error)?;
+
+            let layer_views = (0..layers)
+                .map(|layer| {
+                    let view_info = vk::ImageViewCreateInfo::builder()
+                        .view_type(vk::ImageViewType::TYPE_2D)
+                        .format(Self::FORMAT)
+                        .subresource_range(subresource_range(layer, 1))
+                        .image(image);
+                    device.create_image_view(&view_info, None).map_err(&error)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            // === RENDER PASS ===
+
+            let render_pass = create_render_pass(&device)?;
+            let framebuffers = layer_views
+                .iter()
+                .map(|&view| {
+                    let attachments = [view];
+                    let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                        .render_pass(render_pass)
+                        .attachments(&attachments)
+                        .width(size)
+                        .height(size)
+                        .layers(1);
+                    device
+                        .create_framebuffer(&framebuffer_info, None)
+                        .map_err(&error)
+                })
+                .collect::<Result<_>>()?;
+
+            // === SAMPLER ===
+
+            // Compares depths, filtering the results for a bit of softness. Outside the map
+            // nothing is in shadow.
+            let sampler_info = vk::SamplerCreateInfo::builder()
+                .mag_filter(vk::Filter::LINEAR)
+                .min_filter(vk::Filter::LINEAR)
+                .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+                .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+                .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+                .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+                .compare_enable(true)
+                .compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+            let sampler = device.create_sampler(&sampler_info, None).map_err(&error)?;
+
+            Ok(Self {
+                device,
+
+                image,
+                memory,
+                view,
+                layer_views,
+                framebuffers,
+                render_pass,
+                sampler,
+
+                size,
+            })
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Render pass shadow pipelines are built for.
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.render_pass
+    }
+
+    /// Records a depth-only pass clearing and rendering `layer`, outside other render passes.
+    pub fn render_layer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layer: usize,
+        record: impl FnOnce(),
+    ) {
+        let extent = vk::Extent2D {
+            width: self.size,
+            height: self.size,
+        };
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }];
+        let render_pass_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffers[layer])
+            .render_area(render_area)
+            .clear_values(&clear_values);
+
+        unsafe {
+            self.device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_info,
+                vk::SubpassContents::INLINE,
+            );
+
+            let viewports = [vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: self.size as f32,
+                height: self.size as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            }];
+            self.device.cmd_set_viewport(command_buffer, 0, &viewports);
+            self.device
+                .cmd_set_scissor(command_buffer, 0, &[render_area]);
+
+            record();
+
+            self.device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    /// Descriptor info for a `texture2DArray` binding.
+    pub fn image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: self.view,
+            image_layout: Self::LAYOUT,
+        }
+    }
+
+    /// Descriptor info for a `samplerShadow` binding.
+    pub fn sampler_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            ..Default::default()
+        }
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler(self.sampler, None);
+            for &framebuffer in &self.framebuffers {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+            self.device.destroy_render_pass(self.render_pass, None);
+            for &view in &self.layer_views {
+                self.device.destroy_image_view(view, None);
+            }
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// A depth-only pass leaving the layer ready for sampling. Dependencies order it after
+/// fragment shaders of previous frames still reading the layer, and before the ones reading it
+/// next.
+fn create_render_pass(device: &Device) -> Result<vk::RenderPass> {
+    let attachments = [vk::AttachmentDescription {
+        format: ShadowMap::FORMAT,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: ShadowMap::LAYOUT,
+        ..Default::default()
+    }];
+    let depth_attachment_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+    let dependencies = [
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::SHADER_READ,
+            dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ..Default::default()
+        },
+        vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            ..Default::default()
+        },
+    ];
+
+    let subpasses = [vk::SubpassDescription::builder()
+        .depth_stencil_attachment(&depth_attachment_ref)
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .build()];
+
+    let render_pass_info = vk::RenderPassCreateInfo::builder()
+        .attachments(&attachments)
+        .subpasses(&subpasses)
+        .dependencies(&dependencies);
+
+    unsafe {
+        device
+            .create_render_pass(&render_pass_info, None)
+            .map_err(Error::vulkan("creating the shadow render pass"))
+    }
+}
@@ -0,0 +1,131 @@
+//! # Surface formats
+//!
+//! Picks the swapchain format and tells shaders how to encode their linear output for it.
+//! sRGB formats encode in hardware, linear-only surfaces get gamma applied in the shader, and
+//! HDR surfaces get scRGB or HDR10 output when the `hdr` setting asks for them.
+
+use ash::vk;
+
+/// How a fragment shader writing to the surface encodes its linear Rec.709 colors, passed in push
+/// constants. Values match `ENCODING_*` in the shaders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum OutputEncoding {
+    /// The format encodes itself, or expects linear values like scRGB.
+    Linear = 0,
+    /// A UNORM format shown as sRGB, the shader applies the sRGB curve.
+    Srgb = 1,
+    /// HDR10, the shader converts to Rec.2020 and applies the PQ curve.
+    Pq = 2,
+}
+
+impl OutputEncoding {
+    pub fn of(format: vk::SurfaceFormatKHR) -> Self {
+        match format.color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => OutputEncoding::Pq,
+            vk::ColorSpaceKHR::SRGB_NONLINEAR if !is_srgb(format.format) => OutputEncoding::Srgb,
+            _ => OutputEncoding::Linear,
+        }
+    }
+}
+
+const SRGB_FORMATS: [vk::Format; 2] = [vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB];
+const UNORM_FORMATS: [vk::Format; 2] = [vk::Format::B8G8R8A8_UNORM, vk::Format::R8G8B8A8_UNORM];
+
+/// HDR formats in order of preference, each needs `VK_EXT_swapchain_colorspace`.
+const HDR_FORMATS: [(vk::Format, vk::ColorSpaceKHR); 3] = [
+    (
+        vk::Format::R16G16B16A16_SFLOAT,
+        vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+    ),
+    (
+        vk::Format::A2B10G10R10_UNORM_PACK32,
+        vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+    ),
+    (
+        vk::Format::A2R10G10B10_UNORM_PACK32,
+        vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+    ),
+];
+
+fn is_srgb(format: vk::Format) -> bool {
+    SRGB_FORMATS.contains(&format)
+}
+
+/// Picks a format from the ones the surface supports. `previous` wins if still supported, so
+/// recreated swapchains stay compatible with the render pass.
+pub fn choose_format(
+    supported: &[vk::SurfaceFormatKHR],
+    hdr: bool,
+    previous: Option<vk::SurfaceFormatKHR>,
+) -> Option<vk::SurfaceFormatKHR> {
+    // A single undefined format means anything goes
+    if let [only] = supported {
+        if only.format == vk::Format::UNDEFINED {
+            return Some(vk::SurfaceFormatKHR {
+                format: SRGB_FORMATS[0],
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            });
+        }
+    }
+
+    let find = |format: vk::Format, color_space: vk::ColorSpaceKHR| {
+        supported
+            .iter()
+            .find(|f| f.format == format && f.color_space == color_space)
+            .copied()
+    };
+    let srgb = |formats: &[vk::Format]| {
+        formats
+            .iter()
+            .find_map(|&format| find(format, vk::ColorSpaceKHR::SRGB_NONLINEAR))
+    };
+
+    previous
+        .filter(|previous| supported.contains(previous))
+        .or_else(|| {
+            HDR_FORMATS
+                .iter()
+                .filter(|_| hdr)
+                .find_map(|&(format, color_space)| find(format, color_space))
+        })
+        .or_else(|| srgb(&SRGB_FORMATS))
+        .or_else(|| srgb(&UNORM_FORMATS))
+        .or_else(|| supported.first().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        }
+    }
+
+    #[test]
+    fn prefers_srgb_then_hdr_when_asked() {
+        let unorm = format(
+            vk::Format::B8G8R8A8_UNORM,
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        );
+        let srgb = format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+        let hdr10 = format(
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        );
+        let supported = [unorm, hdr10, srgb];
+
+        assert_eq!(choose_format(&supported, false, None), Some(srgb));
+        assert_eq!(choose_format(&supported, true, None), Some(hdr10));
+        assert_eq!(choose_format(&supported, true, Some(unorm)), Some(unorm));
+        assert_eq!(choose_format(&supported[..1], false, None), Some(unorm));
+        assert_eq!(choose_format(&[], false, None), None);
+
+        assert_eq!(OutputEncoding::of(srgb), OutputEncoding::Linear);
+        assert_eq!(OutputEncoding::of(unorm), OutputEncoding::Srgb);
+        assert_eq!(OutputEncoding::of(hdr10), OutputEncoding::Pq);
+    }
+}
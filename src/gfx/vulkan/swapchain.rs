@@ -1,15 +1,24 @@
 use ash::extensions::khr::{Surface, Swapchain as AshSwapchain};
-use ash::version::{DeviceV1_0};
+use ash::version::DeviceV1_0;
 use ash::{vk, Device, Instance};
 use std::sync::Arc;
 use winit::dpi::PhysicalSize;
 
+use super::present::PresentMode;
+use super::surface;
+use crate::gfx::{Error, Result};
+
 pub struct Swapchain {
     device: Arc<Device>,
 
     swapchain: vk::SwapchainKHR,
     swapchain_loader: Arc<AshSwapchain>,
 
+    format: vk::SurfaceFormatKHR,
+    extent: vk::Extent2D,
+    image_usage: vk::ImageUsageFlags,
+    present_mode: PresentMode,
+
     present_images: Vec<vk::Image>,
     present_image_views: Vec<vk::ImageView>,
 }
@@ -19,10 +28,6 @@ impl Swapchain {
         Default::default()
     }
 
-    pub fn device(&self) -> &Arc<Device> {
-        &self.device
-    }
-
     pub fn swapchain(&self) -> vk::SwapchainKHR {
         self.swapchain
     }
@@ -31,6 +36,22 @@ impl Swapchain {
         &self.swapchain_loader
     }
 
+    pub fn format(&self) -> vk::SurfaceFormatKHR {
+        self.format
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn image_usage(&self) -> vk::ImageUsageFlags {
+        self.image_usage
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
     pub fn present_images(&self) -> &Vec<vk::Image> {
         &self.present_images
     }
@@ -56,6 +77,7 @@ impl Drop for Swapchain {
     }
 }
 
+#[derive(Default)]
 pub struct SwapchainBuilder {
     instance: Option<Arc<Instance>>,
     surface: Option<vk::SurfaceKHR>,
@@ -63,19 +85,11 @@ pub struct SwapchainBuilder {
 
     physical_device: Option<vk::PhysicalDevice>,
     device: Option<Arc<Device>>,
-}
 
-impl Default for SwapchainBuilder {
-    fn default() -> Self {
-        Self {
-            instance: None,
-            surface: None,
-            surface_loader: None,
-
-            physical_device: None,
-            device: None,
-        }
-    }
+    present_mode: PresentMode,
+    hdr: bool,
+    previous_format: Option<vk::SurfaceFormatKHR>,
+    old_swapchain: Option<vk::SwapchainKHR>,
 }
 
 impl SwapchainBuilder {
@@ -104,7 +118,31 @@ impl SwapchainBuilder {
         self
     }
 
-    pub fn build(&mut self, size: &PhysicalSize<u32>) -> Swapchain {
+    /// Requested present mode, falls back to FIFO when unsupported.
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// Prefers HDR formats, which need `VK_EXT_swapchain_colorspace` enabled.
+    pub fn hdr(mut self, hdr: bool) -> Self {
+        self.hdr = hdr;
+        self
+    }
+
+    /// Format of the swapchain being replaced, kept if still supported.
+    pub fn previous_format(mut self, format: vk::SurfaceFormatKHR) -> Self {
+        self.previous_format = Some(format);
+        self
+    }
+
+    /// The swapchain being replaced, which gets retired but must still be destroyed.
+    pub fn old_swapchain(mut self, old_swapchain: vk::SwapchainKHR) -> Self {
+        self.old_swapchain = Some(old_swapchain);
+        self
+    }
+
+    pub fn build(&mut self, size: &PhysicalSize<u32>) -> Result<Swapchain> {
         let instance = self.instance.take().unwrap();
         let surface = self.surface.unwrap();
         let surface_loader = self.surface_loader.take().unwrap();
@@ -116,13 +154,18 @@ impl SwapchainBuilder {
         let height = size.height;
 
         unsafe {
-            let surface_format = surface_loader
+            let error = Error::vulkan("creating the swapchain");
+
+            let surface_formats = surface_loader
                 .get_physical_device_surface_formats(physical_device, surface)
-                .unwrap()[0];
+                .map_err(&error)?;
+            let surface_format =
+                surface::choose_format(&surface_formats, self.hdr, self.previous_format)
+                    .ok_or(Error::SurfaceLost)?;
 
             let surface_capabilities = surface_loader
                 .get_physical_device_surface_capabilities(physical_device, surface)
-                .unwrap();
+                .map_err(&error)?;
 
             let image_count = surface_capabilities.min_image_count + 1;
             let image_count_max = surface_capabilities.max_image_count;
@@ -149,13 +192,21 @@ impl SwapchainBuilder {
 
             let present_modes = surface_loader
                 .get_physical_device_surface_present_modes(physical_device, surface)
-                .unwrap();
+                .map_err(&error)?;
+
+            let present_mode = self.present_mode.choose(&present_modes);
+            if present_mode != self.present_mode {
+                log::warn!(
+                    "{} is not supported, using {}",
+                    self.present_mode,
+                    present_mode
+                );
+            }
 
-            let present_mode = present_modes
-                .iter()
-                .cloned()
-                .find(|&mode| mode == vk::PresentModeKHR::MAILBOX)
-                .unwrap_or(vk::PresentModeKHR::FIFO);
+            // Transfers allow screenshots and upscaling a lower render scale
+            let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | (surface_capabilities.supported_usage_flags
+                    & (vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST));
 
             let swapchain_loader = AshSwapchain::new(&instance as &Instance, &device as &Device);
 
@@ -165,19 +216,22 @@ impl SwapchainBuilder {
                 .image_color_space(surface_format.color_space)
                 .image_format(surface_format.format)
                 .image_extent(surface_resolution)
-                .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+                .image_usage(image_usage)
                 .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .pre_transform(pre_transform)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-                .present_mode(present_mode)
+                .present_mode(present_mode.to_vk())
                 .clipped(true)
+                .old_swapchain(self.old_swapchain.unwrap_or_default())
                 .image_array_layers(1);
 
             let swapchain = swapchain_loader
                 .create_swapchain(&swapchain_create_info, None)
-                .unwrap();
+                .map_err(&error)?;
 
-            let present_images = swapchain_loader.get_swapchain_images(swapchain).unwrap();
+            let present_images = swapchain_loader
+                .get_swapchain_images(swapchain)
+                .map_err(&error)?;
             let present_image_views: Vec<vk::ImageView> = present_images
                 .iter()
                 .map(|&image| {
@@ -198,19 +252,26 @@ impl SwapchainBuilder {
                             layer_count: 1,
                         })
                         .image(image);
-                    device.create_image_view(&create_view_info, None).unwrap()
+                    device
+                        .create_image_view(&create_view_info, None)
+                        .map_err(&error)
                 })
-                .collect();
+                .collect::<Result<_>>()?;
 
-            Swapchain {
+            Ok(Swapchain {
                 device: device.clone(),
 
                 swapchain,
                 swapchain_loader: Arc::new(swapchain_loader),
 
+                format: surface_format,
+                extent: surface_resolution,
+                image_usage,
+                present_mode,
+
                 present_images,
                 present_image_views,
-            }
+            })
         }
     }
 }
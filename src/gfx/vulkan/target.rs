@@ -0,0 +1,265 @@
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+
+use super::image::Image;
+use super::swapchain::Swapchain;
+use crate::gfx::{Error, Result};
+
+/// Render scales below this are raised to it.
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+
+/// Images the main render pass draws to.
+#[derive(Clone)]
+pub enum Output {
+    Swapchain(Arc<Swapchain>),
+    Offscreen(Arc<Image>),
+}
+
+impl Output {
+    pub fn format(&self) -> vk::Format {
+        match self {
+            Output::Swapchain(swapchain) => swapchain.format().format,
+            Output::Offscreen(image) => image.format(),
+        }
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        match self {
+            Output::Swapchain(swapchain) => swapchain.extent(),
+            Output::Offscreen(image) => image.extent(),
+        }
+    }
+
+    /// Whether a scaled rendering can be blitted to the output images, swapchains may not allow
+    /// it. Offscreen images always do.
+    pub fn is_upscalable(&self) -> bool {
+        match self {
+            Output::Swapchain(swapchain) => swapchain
+                .image_usage()
+                .contains(vk::ImageUsageFlags::TRANSFER_DST),
+            Output::Offscreen(_) => true,
+        }
+    }
+}
+
+/// Output images with a shared depth buffer and a framebuffer for each output image. With
+/// multisampling the render pass draws to a multisampled color image resolved at its end, and
+/// with a render scale below one into an image that size, upscaled to the output afterwards.
+pub struct Target {
+    device: Arc<Device>,
+
+    output: Output,
+    /// Multisampled color, `None` with one sample.
+    _color: Option<Image>,
+    /// Rendering at the render scale, `None` when rendering at the output's size.
+    scaled: Option<Image>,
+    depth: Image,
+    samples: vk::SampleCountFlags,
+    framebuffers: Vec<vk::Framebuffer>,
+}
+
+impl Target {
+    pub const DEPTH_FORMAT: vk::Format = vk::Format::D16_UNORM;
+
+    /// A target rendering `samples` per pixel at `scale` times the size of `output`, matching a
+    /// render pass created for them.
+    pub fn new(
+        device: Arc<Device>,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        render_pass: vk::RenderPass,
+        output: Output,
+        samples: vk::SampleCountFlags,
+        scale: f32,
+    ) -> Result<Self> {
+        let format = output.format();
+        let extent = scaled_extent(output.extent(), scale);
+
+        let scaled = if extent != output.extent() {
+            Some(Image::new(
+                device.clone(),
+                memory_properties,
+                extent,
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::ImageAspectFlags::COLOR,
+            )?)
+        } else {
+            None
+        };
+        // Samples only live during the render pass, resolved at its end
+        let color = if samples != vk::SampleCountFlags::TYPE_1 {
+            Some(Image::multisampled(
+                device.clone(),
+                memory_properties,
+                extent,
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                vk::ImageAspectFlags::COLOR,
+                samples,
+            )?)
+        } else {
+            None
+        };
+        let depth = Image::multisampled(
+            device.clone(),
+            memory_properties,
+            extent,
+            Self::DEPTH_FORMAT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageAspectFlags::DEPTH,
+            samples,
+        )?;
+
+        // Views the render pass leaves its result in, one framebuffer will do when scaling
+        let views = match (&scaled, &output) {
+            (Some(scaled), _) => vec![scaled.view()],
+            (None, Output::Swapchain(swapchain)) => swapchain.present_image_views().clone(),
+            (None, Output::Offscreen(image)) => vec![image.view()],
+        };
+        let framebuffers = views
+            .iter()
+            .map(|&view| {
+                let attachments = match &color {
+                    Some(color) => vec![color.view(), depth.view(), view],
+                    None => vec![view, depth.view()],
+                };
+                let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .attachments(&attachments)
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1);
+
+                unsafe {
+                    device
+                        .create_framebuffer(&framebuffer_info, None)
+                        .map_err(Error::vulkan("creating a framebuffer"))
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            device,
+
+            output,
+            _color: color,
+            scaled,
+            depth,
+            samples,
+            framebuffers,
+        })
+    }
+
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+
+    /// Size rendered at, smaller than the output with a render scale below one.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.depth.extent()
+    }
+
+    pub fn output_extent(&self) -> vk::Extent2D {
+        self.output.extent()
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.output.format()
+    }
+
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        self.samples
+    }
+
+    /// The image rendered into at the render scale, left in `TRANSFER_SRC_OPTIMAL` by the render
+    /// pass for upscaling. `None` when rendering to the output directly.
+    pub fn scaled(&self) -> Option<&Image> {
+        self.scaled.as_ref()
+    }
+
+    /// Layout output images end up in, by the render pass or after upscaling.
+    pub fn layout(&self) -> vk::ImageLayout {
+        match &self.output {
+            Output::Swapchain(_) => vk::ImageLayout::PRESENT_SRC_KHR,
+            Output::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        }
+    }
+
+    pub fn image(&self, index: u32) -> vk::Image {
+        match &self.output {
+            Output::Swapchain(swapchain) => swapchain.present_images()[index as usize],
+            Output::Offscreen(image) => image.image(),
+        }
+    }
+
+    /// Whether output images can be copied from, swapchains may not allow it.
+    pub fn is_readable(&self) -> bool {
+        match &self.output {
+            Output::Swapchain(swapchain) => swapchain
+                .image_usage()
+                .contains(vk::ImageUsageFlags::TRANSFER_SRC),
+            Output::Offscreen(_) => true,
+        }
+    }
+
+    /// Framebuffer for output image `index`. When scaling, all frames in flight share one, the
+    /// render pass' external dependency orders its reuse after the previous frame's upscale.
+    pub fn framebuffer(&self, index: u32) -> vk::Framebuffer {
+        match self.scaled {
+            Some(_) => self.framebuffers[0],
+            None => self.framebuffers[index as usize],
+        }
+    }
+}
+
+impl Drop for Target {
+    fn drop(&mut self) {
+        unsafe {
+            for &framebuffer in &self.framebuffers {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+        }
+    }
+}
+
+/// `extent` times `scale`, clamped to `MIN_RENDER_SCALE..=1.0` and at least a pixel.
+pub fn scaled_extent(extent: vk::Extent2D, scale: f32) -> vk::Extent2D {
+    let scale = scale.clamp(MIN_RENDER_SCALE, 1.0);
+    let scaled = |size: u32| ((size as f32 * scale).round() as u32).clamp(1, size.max(1));
+    vk::Extent2D {
+        width: scaled(extent.width),
+        height: scaled(extent.height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_extents_within_bounds() {
+        let extent = vk::Extent2D {
+            width: 1920,
+            height: 1080,
+        };
+        let scaled = |scale| {
+            let extent = scaled_extent(extent, scale);
+            (extent.width, extent.height)
+        };
+        assert_eq!(scaled(1.0), (1920, 1080));
+        assert_eq!(scaled(0.5), (960, 540));
+        assert_eq!(scaled(0.1), (480, 270));
+        assert_eq!(scaled(2.0), (1920, 1080));
+
+        let tiny = scaled_extent(
+            vk::Extent2D {
+                width: 1,
+                height: 2,
+            },
+            0.25,
+        );
+        assert_eq!((tiny.width, tiny.height), (1, 1));
+    }
+}
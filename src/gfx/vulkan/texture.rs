@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use ash::version::DeviceV1_0;
+use ash::{vk, Device};
+
+use super::buffer::Buffer;
+use super::Vulkan;
+use crate::gfx::textures::TextureArray;
+
+/// A sampled 2D texture array living in device local memory.
+pub struct Texture {
+    device: Arc<Device>,
+
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    sampler: vk::Sampler,
+}
+
+impl Texture {
+    pub const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+    pub fn new(vulkan: &Vulkan, array: &TextureArray) -> Self {
+        let device = vulkan.clone_device();
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: array.mip_levels(),
+            base_array_layer: 0,
+            layer_count: array.layers(),
+        };
+
+        unsafe {
+            // === IMAGE ===
+
+            let image_info = vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(Self::FORMAT)
+                .extent(vk::Extent3D {
+                    width: array.size(),
+                    height: array.size(),
+                    depth: 1,
+                })
+                .mip_levels(array.mip_levels())
+                .array_layers(array.layers())
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED);
+
+            let image = device.create_image(&image_info, None).unwrap();
+
+            let requirements = device.get_image_memory_requirements(image);
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(
+                    vulkan.find_memory_type(&requirements, vk::MemoryPropertyFlags::DEVICE_LOCAL),
+                );
+
+            let memory = device.allocate_memory(&allocate_info, None).unwrap();
+            device.bind_image_memory(image, memory, 0).unwrap();
+
+            // === UPLOAD ===
+
+            let staging = Buffer::staging(vulkan, array.data());
+
+            let regions: Vec<_> = (0..array.mip_levels())
+                .map(|level| vk::BufferImageCopy {
+                    buffer_offset: array.mip_offset(level) as vk::DeviceSize,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: level,
+                        base_array_layer: 0,
+                        layer_count: array.layers(),
+                    },
+                    image_extent: vk::Extent3D {
+                        width: array.mip_size(level),
+                        height: array.mip_size(level),
+                        depth: 1,
+                    },
+                    ..Default::default()
+                })
+                .collect();
+
+            vulkan.one_time_commands(|device, command_buffer| {
+                let to_transfer = [vk::ImageMemoryBarrier::builder()
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .image(image)
+                    .subresource_range(subresource_range)
+                    .build()];
+
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &to_transfer,
+                );
+
+                device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging.buffer(),
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                );
+
+                let to_shader = [vk::ImageMemoryBarrier::builder()
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .image(image)
+                    .subresource_range(subresource_range)
+                    .build()];
+
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &to_shader,
+                );
+            });
+
+            // === VIEW & SAMPLER ===
+
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                .format(Self::FORMAT)
+                .subresource_range(subresource_range)
+                .image(image);
+
+            let view = device.create_image_view(&view_info, None).unwrap();
+
+            // Pixelated up close, mipmapped in the distance
+            let sampler_info = vk::SamplerCreateInfo::builder()
+                .mag_filter(vk::Filter::NEAREST)
+                .min_filter(vk::Filter::NEAREST)
+                .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+                .address_mode_u(vk::SamplerAddressMode::REPEAT)
+                .address_mode_v(vk::SamplerAddressMode::REPEAT)
+                .address_mode_w(vk::SamplerAddressMode::REPEAT)
+                .max_lod(array.mip_levels() as f32);
+
+            let sampler = device.create_sampler(&sampler_info, None).unwrap();
+
+            Self {
+                device,
+
+                image,
+                memory,
+                view,
+                sampler,
+            }
+        }
+    }
+
+    /// Descriptor info for a `texture2DArray` binding.
+    pub fn image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: self.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    /// Descriptor info for a `sampler` binding.
+    pub fn sampler_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            ..Default::default()
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
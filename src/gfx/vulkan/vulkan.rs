@@ -1,71 +1,174 @@
-use std::ffi::CString;
+use std::ffi::{c_void, CStr, CString};
 use std::io::Cursor;
 use std::mem::ManuallyDrop;
+use std::path::Path;
 use std::sync::Arc;
+use std::thread::JoinHandle;
 
 use ash::extensions::ext::DebugUtils;
 use ash::extensions::khr::{Surface, Swapchain as AshSwapchain};
 use ash::util::read_spv;
-use ash::version::{DeviceV1_0, EntryV1_0, InstanceV1_0};
-use ash::{vk, Device, Entry, Instance};
+use ash::version::{DeviceV1_0, EntryV1_0, InstanceV1_0, InstanceV1_1};
+use ash::{vk, Device, Entry, Instance, InstanceError};
 use winit::event_loop::EventLoop;
 use winit::window::Window;
 use winit::{dpi::LogicalSize, window::WindowBuilder};
 
+use super::buffer::Buffer;
 use super::debug;
+use super::frame::Frame;
+use super::gpu;
+use super::image::{self, Image};
+use super::msaa::Msaa;
+use super::present::PresentMode;
+use super::shader::Shader;
 use super::swapchain::Swapchain;
+use super::target::{self, Output, Target};
+use crate::gfx::{capture, Error, Result, TITLE};
+use crate::settings::Settings;
+
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+/// Parts of a frame's command buffer, see `Vulkan::draw_stages`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Before the main render pass, for render passes of its own like shadow maps.
+    Prepare,
+    /// Inside the main render pass, with viewport and scissor covering the target.
+    Main,
+}
+
+const CLEAR_COLOR: [f32; 4] = [0.5, 0.7, 1.0, 1.0];
+const VALIDATION_LAYER: &[u8] = b"VK_LAYER_KHRONOS_validation\0";
 
 pub struct Vulkan {
-    window: Window,
+    _entry: Entry,
+    window: Option<Window>,
 
     instance: Arc<Instance>,
-    debug_utils_loader: DebugUtils,
-    debug_callback: vk::DebugUtilsMessengerEXT,
+    messenger: Option<debug::Messenger>,
 
     surface: vk::SurfaceKHR,
-    surface_format: vk::SurfaceFormatKHR,
-    surface_loader: Arc<Surface>,
+    surface_loader: Option<Arc<Surface>>,
 
     physical_device: vk::PhysicalDevice,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    descriptor_indexing: bool,
+    multi_draw_indirect: bool,
+    max_bindless_descriptors: u32,
     device: Arc<Device>,
     present_queue: vk::Queue,
+    command_pool: vk::CommandPool,
 
-    swapchain: ManuallyDrop<Arc<Swapchain>>,
+    render_pass: vk::RenderPass,
+    present_mode: PresentMode,
+    hdr: bool,
+    msaa: Msaa,
+    /// Sample counts supported by both color and depth attachments.
+    supported_samples: vk::SampleCountFlags,
+    render_scale: f32,
+    target: ManuallyDrop<Target>,
+    frames: Vec<Frame>,
+    frame: usize,
+    stale: bool,
+
+    screenshot_requested: bool,
+    screenshots: Vec<JoinHandle<()>>,
 }
 
 impl Vulkan {
-    pub fn new<T>(event_loop: &EventLoop<T>) -> Self {
+    pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+    pub fn new<T>(event_loop: &EventLoop<T>, settings: &Settings) -> Result<Self> {
         const INIT_WIDTH: u32 = 800;
         const INIT_HEIGHT: u32 = 600;
-        const TITLE: &str = "Minecraft";
 
-        // TODO make a "build" function for things that need rebuilding on resize
-        // that takes width and height
+        let window = WindowBuilder::new()
+            .with_title(TITLE)
+            .with_inner_size(LogicalSize::new(
+                f64::from(INIT_WIDTH),
+                f64::from(INIT_HEIGHT),
+            ))
+            .build(event_loop)
+            .map_err(|e| Error::Window(e.to_string()))?;
+
+        let extent = vk::Extent2D {
+            width: window.inner_size().width,
+            height: window.inner_size().height,
+        };
+
+        Self::create(Some(window), extent, settings)
+    }
+
+    /// Renders into an offscreen image instead of a window, see `read_pixels`. Runs without a
+    /// display, e.g. on CI machines with a CPU implementation like lavapipe.
+    pub fn headless(width: u32, height: u32, settings: &Settings) -> Result<Self> {
+        Self::create(None, vk::Extent2D { width, height }, settings)
+    }
+
+    /// Describes all GPUs, their queue families, limits and format support.
+    pub fn report_gpus() -> String {
+        unsafe {
+            let entry = match Entry::new() {
+                Ok(entry) => entry,
+                Err(e) => return format!("failed to load Vulkan: {}\n", e),
+            };
+
+            let app_info = vk::ApplicationInfo::builder().api_version(vk::make_version(1, 2, 0));
+            let create_info = vk::InstanceCreateInfo::builder().application_info(&app_info);
+            let instance = match entry.create_instance(&create_info, None) {
+                Ok(instance) => instance,
+                Err(e) => return format!("failed to create Vulkan instance: {}\n", e),
+            };
+
+            let report = gpu::report(&instance);
+            instance.destroy_instance(None);
+            report
+        }
+    }
+
+    fn create(window: Option<Window>, extent: vk::Extent2D, settings: &Settings) -> Result<Self> {
         unsafe {
             // === INSTANCE ===
 
-            let entry = Entry::new().unwrap();
-            let window = WindowBuilder::new()
-                .with_title(TITLE)
-                .with_inner_size(LogicalSize::new(
-                    f64::from(INIT_WIDTH),
-                    f64::from(INIT_HEIGHT),
-                ))
-                .build(event_loop)
-                .unwrap();
+            let entry = Entry::new().map_err(|e| Error::Loading(e.to_string()))?;
 
-            let layer_names = [CString::new("VK_LAYER_KHRONOS_validation").unwrap()];
-            let layer_names: Vec<*const i8> = layer_names
-                .iter()
-                .map(|raw_name| raw_name.as_ptr())
-                .collect();
+            let mut layers = vec![];
+            let mut extensions = match &window {
+                Some(window) => ash_window::enumerate_required_extensions(window)
+                    .map_err(Error::vulkan("querying window extensions"))?,
+                None => vec![],
+            };
+            check_instance_support(&entry, &layers, &extensions)?;
 
-            let extension_names = ash_window::enumerate_required_extensions(&window).unwrap();
-            let mut extension_names = extension_names
-                .iter()
-                .map(|ext| ext.as_ptr())
-                .collect::<Vec<_>>();
-            extension_names.push(DebugUtils::name().as_ptr());
+            // Players usually don't have the Vulkan SDK installed
+            let validation_layer = CStr::from_bytes_with_nul(VALIDATION_LAYER).unwrap();
+            let validation = settings.validation.is_active()
+                && has_instance_support(
+                    &entry,
+                    &[validation_layer],
+                    &[DebugUtils::name()],
+                    "validation",
+                )?;
+            if validation {
+                layers.push(validation_layer);
+                extensions.push(DebugUtils::name());
+            }
+
+            let hdr = settings.hdr
+                && window.is_some()
+                && has_instance_support(
+                    &entry,
+                    &[],
+                    &[vk::ExtSwapchainColorspaceFn::name()],
+                    "HDR",
+                )?;
+            if hdr {
+                extensions.push(vk::ExtSwapchainColorspaceFn::name());
+            }
+
+            let layer_names: Vec<_> = layers.iter().map(|name| name.as_ptr()).collect();
+            let extension_names: Vec<_> = extensions.iter().map(|name| name.as_ptr()).collect();
 
             let app_name = CString::new(TITLE).unwrap();
             let app_info = vk::ApplicationInfo::builder()
@@ -80,60 +183,39 @@ impl Vulkan {
 
             let instance = entry
                 .create_instance(&create_info, None)
-                .expect("failed to create Vulkan instance");
-
-            let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-                .message_severity(
-                    vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
-                        | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                        | vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
-                )
-                .message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
-                .pfn_user_callback(Some(debug::vulkan_debug_callback));
-
-            let debug_utils_loader = DebugUtils::new(&entry, &instance);
-            let debug_callback = debug_utils_loader
-                .create_debug_utils_messenger(&debug_info, None)
-                .unwrap();
+                .map_err(|e| match e {
+                    InstanceError::LoadError(names) => Error::Loading(names.join(", ")),
+                    InstanceError::VkError(result) => {
+                        Error::vulkan("creating the instance")(result)
+                    }
+                })?;
+
+            let messenger = if validation {
+                Some(debug::Messenger::new(
+                    &entry,
+                    &instance,
+                    &settings.validation,
+                )?)
+            } else {
+                None
+            };
 
-            let surface = ash_window::create_surface(&entry, &instance, &window, None).unwrap();
-            let surface_loader = Surface::new(&entry, &instance);
+            let (surface, surface_loader) = match &window {
+                Some(window) => (
+                    ash_window::create_surface(&entry, &instance, window, None)
+                        .map_err(Error::vulkan("creating the window surface"))?,
+                    Some(Surface::new(&entry, &instance)),
+                ),
+                None => (vk::SurfaceKHR::null(), None),
+            };
 
             // === DEVICE ===
 
-            let physical_devices = instance
-                .enumerate_physical_devices()
-                .expect("failed to enumerate Vulkan physical devices");
-
-            let (physical_device, queue_family_index) = physical_devices
-                .iter()
-                .map(|pdevice| {
-                    instance
-                        .get_physical_device_queue_family_properties(*pdevice)
-                        .iter()
-                        .enumerate()
-                        .filter_map(|(index, ref info)| {
-                            let supports_graphics =
-                                info.queue_flags.contains(vk::QueueFlags::GRAPHICS);
-                            let supports_surface = surface_loader
-                                .get_physical_device_surface_support(
-                                    *pdevice,
-                                    index as u32,
-                                    surface,
-                                )
-                                .unwrap();
-
-                            if supports_graphics && supports_surface {
-                                Some((*pdevice, index as u32))
-                            } else {
-                                None
-                            }
-                        })
-                        .next()
-                })
-                .flatten()
-                .next()
-                .expect("no suitable Vulkan physical device");
+            let (physical_device, queue_family_index) = gpu::select(
+                &instance,
+                surface_loader.as_ref().map(|loader| (loader, surface)),
+                settings.gpu.as_ref(),
+            )?;
 
             let priorities = [1.0];
             let queue_info = [vk::DeviceQueueCreateInfo::builder()
@@ -141,60 +223,173 @@ impl Vulkan {
                 .queue_priorities(&priorities)
                 .build()];
 
-            let device_extension_names = [AshSwapchain::name().as_ptr()];
+            // Descriptor indexing is core since 1.2, used for bindless texture arrays
+            let mut supported_indexing = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+            // ash has no `push_next` for features2 chains
+            let mut supported_features = vk::PhysicalDeviceFeatures2 {
+                p_next: &mut supported_indexing as *mut _ as *mut c_void,
+                ..Default::default()
+            };
+            instance.get_physical_device_features2(physical_device, &mut supported_features);
+
+            let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+            let mut properties =
+                vk::PhysicalDeviceProperties2::builder().push_next(&mut indexing_properties);
+            instance.get_physical_device_properties2(physical_device, &mut properties);
+
+            let limits = properties.properties.limits;
+            let supported_samples =
+                limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+            let descriptor_indexing = properties.properties.api_version
+                >= vk::make_version(1, 2, 0)
+                && supported_indexing.runtime_descriptor_array == vk::TRUE
+                && supported_indexing.descriptor_binding_partially_bound == vk::TRUE
+                && supported_indexing.descriptor_binding_variable_descriptor_count == vk::TRUE
+                && supported_indexing.descriptor_binding_sampled_image_update_after_bind
+                    == vk::TRUE
+                && supported_indexing.shader_sampled_image_array_non_uniform_indexing == vk::TRUE;
+            let max_bindless_descriptors = if descriptor_indexing {
+                indexing_properties.max_descriptor_set_update_after_bind_sampled_images
+            } else {
+                0
+            };
+
+            let device_extension_names = if window.is_some() {
+                vec![AshSwapchain::name().as_ptr()]
+            } else {
+                vec![]
+            };
+            // Draws all chunks with one call, otherwise one indirect draw per chunk
+            let multi_draw_indirect = supported_features.features.multi_draw_indirect == vk::TRUE;
             let features = vk::PhysicalDeviceFeatures {
                 shader_clip_distance: 1,
+                multi_draw_indirect: multi_draw_indirect.into(),
                 ..Default::default()
             };
+            let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+                .runtime_descriptor_array(true)
+                .descriptor_binding_partially_bound(true)
+                .descriptor_binding_variable_descriptor_count(true)
+                .descriptor_binding_sampled_image_update_after_bind(true)
+                .shader_sampled_image_array_non_uniform_indexing(true);
 
-            let device_create_info = vk::DeviceCreateInfo::builder()
+            let mut device_create_info = vk::DeviceCreateInfo::builder()
                 .queue_create_infos(&queue_info)
                 .enabled_extension_names(&device_extension_names)
                 .enabled_features(&features);
+            if descriptor_indexing {
+                device_create_info = device_create_info.push_next(&mut indexing_features);
+            }
 
             let device = instance
                 .create_device(physical_device, &device_create_info, None)
-                .unwrap();
+                .map_err(Error::vulkan("creating the device"))?;
+
+            let present_queue = device.get_device_queue(queue_family_index, 0);
 
-            let present_queue = device.get_device_queue(queue_family_index as u32, 0);
+            let memory_properties = instance.get_physical_device_memory_properties(physical_device);
 
-            let surface_format = surface_loader
-                .get_physical_device_surface_formats(physical_device, surface)
-                .unwrap()[0];
+            let pool_create_info = vk::CommandPoolCreateInfo::builder()
+                .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+                .queue_family_index(queue_family_index);
 
-            // === SWAPCHAIN ===
+            let command_pool = device
+                .create_command_pool(&pool_create_info, None)
+                .map_err(Error::vulkan("creating the command pool"))?;
+
+            // === TARGET ===
 
             let instance = Arc::new(instance);
-            let surface_loader = Arc::new(surface_loader);
+            let surface_loader = surface_loader.map(Arc::new);
             let device = Arc::new(device);
 
-            let swapchain = ManuallyDrop::new(Arc::new(
-                Swapchain::builder()
-                    .instance(instance.clone())
-                    .surface(surface)
-                    .surface_loader(surface_loader.clone())
-                    .physical_device(physical_device)
-                    .device(device.clone())
-                    .build(&window.inner_size()),
-            ));
+            // Swapchain images get presented, offscreen images read back
+            let final_layout = if window.is_some() {
+                vk::ImageLayout::PRESENT_SRC_KHR
+            } else {
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+            };
+            let output = match &surface_loader {
+                Some(surface_loader) => Output::Swapchain(Arc::new(
+                    Swapchain::builder()
+                        .instance(instance.clone())
+                        .surface(surface)
+                        .surface_loader(surface_loader.clone())
+                        .physical_device(physical_device)
+                        .device(device.clone())
+                        .present_mode(settings.vsync)
+                        .hdr(hdr)
+                        .build(&window.as_ref().unwrap().inner_size())?,
+                )),
+                None => Output::Offscreen(Arc::new(Image::new(
+                    device.clone(),
+                    &memory_properties,
+                    extent,
+                    Self::OFFSCREEN_FORMAT,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::TRANSFER_SRC
+                        | vk::ImageUsageFlags::TRANSFER_DST,
+                    vk::ImageAspectFlags::COLOR,
+                )?)),
+            };
+
+            let samples = settings.msaa.choose(supported_samples);
+            let scale = upscalable_scale(&output, settings.render_scale);
+            let render_pass = create_render_pass(
+                &device,
+                output.format(),
+                final_layout,
+                samples,
+                is_scaled(&output, scale),
+            )?;
+
+            let target = ManuallyDrop::new(Target::new(
+                device.clone(),
+                &memory_properties,
+                render_pass,
+                output,
+                samples,
+                scale,
+            )?);
+
+            let frames = (0..FRAMES_IN_FLIGHT)
+                .map(|_| Frame::new(device.clone(), command_pool))
+                .collect::<Result<_>>()?;
 
-            Self {
+            Ok(Self {
+                _entry: entry,
                 window,
 
                 instance,
-                debug_utils_loader,
-                debug_callback,
+                messenger,
 
                 surface,
-                surface_format,
                 surface_loader,
 
                 physical_device,
+                memory_properties,
+                descriptor_indexing,
+                multi_draw_indirect,
+                max_bindless_descriptors,
                 device,
                 present_queue,
+                command_pool,
 
-                swapchain,
-            }
+                render_pass,
+                present_mode: settings.vsync,
+                hdr,
+                msaa: settings.msaa,
+                supported_samples,
+                render_scale: settings.render_scale,
+                target,
+                frames,
+                frame: 0,
+                stale: false,
+
+                screenshot_requested: false,
+                screenshots: Vec::new(),
+            })
         }
     }
 
@@ -202,37 +397,542 @@ impl Vulkan {
         self.device.clone()
     }
 
+    /// Format and color space of the images drawn to, see `OutputEncoding` for what shaders
+    /// must write. May change when the swapchain gets recreated.
     pub fn surface_format(&self) -> vk::SurfaceFormatKHR {
-        self.surface_format
+        match self.target.output() {
+            Output::Swapchain(swapchain) => swapchain.format(),
+            Output::Offscreen(image) => vk::SurfaceFormatKHR {
+                format: image.format(),
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            },
+        }
     }
 
-    pub fn create_shader_module(&self, file: &mut Cursor<&[u8]>) -> vk::ShaderModule {
-        let code = read_spv(file).unwrap();
+    /// The render pass all renderers draw in, with a color and a depth attachment, multisampled
+    /// ones resolved at its end.
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.render_pass
+    }
 
-        let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    /// Samples per pixel of the render pass, pipelines have to rasterize with as many. May
+    /// change when the target gets recreated.
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        self.target.samples()
+    }
+
+    /// Updates a renderer's copy of the surface format and sample count, returning whether its
+    /// pipelines need rebuilding for the new render pass.
+    pub fn follow_surface(
+        &self,
+        surface_format: &mut vk::SurfaceFormatKHR,
+        samples: &mut vk::SampleCountFlags,
+    ) -> bool {
+        let new_format = self.surface_format();
+        let changed = new_format.format != surface_format.format || self.samples() != *samples;
+        *surface_format = new_format;
+        *samples = self.samples();
+        changed
+    }
 
+    /// Size rendered at, the render scale times the size of the window.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.target.extent()
+    }
+
+    pub fn find_memory_type(
+        &self,
+        requirements: &vk::MemoryRequirements,
+        flags: vk::MemoryPropertyFlags,
+    ) -> u32 {
+        image::find_memory_type(&self.memory_properties, requirements, flags)
+    }
+
+    /// Records commands using `record` and waits for the queue to execute them.
+    pub fn one_time_commands(&self, record: impl FnOnce(&Device, vk::CommandBuffer)) {
         unsafe {
+            let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .command_pool(self.command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+
+            let command_buffers = self
+                .device
+                .allocate_command_buffers(&allocate_info)
+                .unwrap();
+            let command_buffer = command_buffers[0];
+
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+            self.device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .unwrap();
+            record(&self.device, command_buffer);
+            self.device.end_command_buffer(command_buffer).unwrap();
+
+            let submits = [vk::SubmitInfo::builder()
+                .command_buffers(&command_buffers)
+                .build()];
+
+            self.device
+                .queue_submit(self.present_queue, &submits, vk::Fence::null())
+                .unwrap();
+            self.device.queue_wait_idle(self.present_queue).unwrap();
+
+            self.device
+                .free_command_buffers(self.command_pool, &command_buffers);
+        }
+
+        self.check_validation();
+    }
+
+    /// Panics on validation errors if `Validation::panic_on_error` is set.
+    fn check_validation(&self) {
+        if let Some(messenger) = &self.messenger {
+            messenger.check();
+        }
+    }
+
+    /// Whether bindless descriptor arrays are supported and enabled.
+    pub fn descriptor_indexing(&self) -> bool {
+        self.descriptor_indexing
+    }
+
+    /// Whether indirect draws can issue more than one draw.
+    pub fn multi_draw_indirect(&self) -> bool {
+        self.multi_draw_indirect
+    }
+
+    pub fn max_bindless_descriptors(&self) -> u32 {
+        self.max_bindless_descriptors
+    }
+
+    pub fn create_shader_module(&self, file: &mut Cursor<&[u8]>) -> Result<Shader> {
+        let code = read_spv(file).map_err(|e| Error::InvalidShader(e.to_string()))?;
+
+        Shader::new(self.device.clone(), &code)
+    }
+
+    /// Flags the swapchain for recreation before the next frame, e.g. after a resize.
+    pub fn mark_stale(&mut self) {
+        self.stale = true;
+    }
+
+    /// Present mode in use, `None` when rendering offscreen.
+    pub fn present_mode(&self) -> Option<PresentMode> {
+        match self.target.output() {
+            Output::Swapchain(swapchain) => Some(swapchain.present_mode()),
+            Output::Offscreen(_) => None,
+        }
+    }
+
+    /// Requests `present_mode`, rebuilding the swapchain before the next frame if it changed.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        if present_mode != self.present_mode {
+            self.present_mode = present_mode;
+            self.stale = true;
+        }
+    }
+
+    /// Requests `msaa`, clamped to what the device supports, rebuilding the target before the
+    /// next frame if it changed.
+    pub fn set_msaa(&mut self, msaa: Msaa) {
+        if msaa != self.msaa {
+            self.msaa = msaa;
+            self.stale = true;
+        }
+    }
+
+    /// Requests rendering at `scale` times the size of the window, rebuilding the target before
+    /// the next frame if it changed.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        if scale != self.render_scale {
+            self.render_scale = scale;
+            self.stale = true;
+        }
+    }
+
+    pub fn window(&self) -> Option<&Window> {
+        self.window.as_ref()
+    }
+
+    /// Saves the next drawn frame as a timestamped PNG in `screenshots/`, encoded on a background
+    /// thread.
+    pub fn screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    /// Draws a frame, calling `record` once for each `Stage` in order with the frame's command
+    /// buffer and the index of the frame in flight.
+    pub fn draw_stages(
+        &mut self,
+        mut record: impl FnMut(Stage, vk::CommandBuffer, usize),
+    ) -> Result<()> {
+        if self.stale {
+            let (format, samples) = (self.target.format(), self.target.samples());
+            self.recreate_target()?;
+            // Minimized, or renderers have to rebuild pipelines for the new format or samples first
+            if self.stale || self.target.format() != format || self.target.samples() != samples {
+                return Ok(());
+            }
+        }
+
+        let error = Error::vulkan("drawing a frame");
+
+        let extent = self.target.extent();
+
+        let frame = &self.frames[self.frame];
+
+        unsafe {
+            let fences = [frame.in_flight];
+            self.device
+                .wait_for_fences(&fences, true, u64::MAX)
+                .map_err(&error)?;
+
+            let swapchain = match self.target.output() {
+                Output::Swapchain(swapchain) => Some(swapchain.clone()),
+                Output::Offscreen(_) => None,
+            };
+
+            let image_index = match &swapchain {
+                Some(swapchain) => match swapchain.swapchain_loader().acquire_next_image(
+                    swapchain.swapchain(),
+                    u64::MAX,
+                    frame.image_available,
+                    vk::Fence::null(),
+                ) {
+                    Ok((index, suboptimal)) => {
+                        self.stale |= suboptimal;
+                        index
+                    }
+                    Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                        self.stale = true;
+                        return Ok(());
+                    }
+                    Err(e) => return Err(error(e)),
+                },
+                None => 0,
+            };
+
+            self.device.reset_fences(&fences).map_err(&error)?;
+
+            // === RECORD ===
+
+            let command_buffer = frame.command_buffer;
+            self.device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .map_err(&error)?;
+
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            self.device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .map_err(&error)?;
+
+            record(Stage::Prepare, command_buffer, self.frame);
+
+            let clear_values = [
+                vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: CLEAR_COLOR,
+                    },
+                },
+                vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: 1.0,
+                        stencil: 0,
+                    },
+                },
+            ];
+            let render_area = vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            };
+            let render_pass_info = vk::RenderPassBeginInfo::builder()
+                .render_pass(self.render_pass)
+                .framebuffer(self.target.framebuffer(image_index))
+                .render_area(render_area)
+                .clear_values(&clear_values);
+
+            self.device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_info,
+                vk::SubpassContents::INLINE,
+            );
+
+            let viewports = [vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: extent.width as f32,
+                height: extent.height as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            }];
+            self.device.cmd_set_viewport(command_buffer, 0, &viewports);
+            self.device
+                .cmd_set_scissor(command_buffer, 0, &[render_area]);
+
+            record(Stage::Main, command_buffer, self.frame);
+
+            self.device.cmd_end_render_pass(command_buffer);
+
+            if let Some(scaled) = self.target.scaled() {
+                record_upscale(
+                    &self.device,
+                    command_buffer,
+                    scaled,
+                    self.target.image(image_index),
+                    self.target.output_extent(),
+                    self.target.layout(),
+                );
+            }
+
+            // === CAPTURE ===
+
+            let screenshot = if self.screenshot_requested {
+                self.screenshot_requested = false;
+                self.screenshot_buffer()
+            } else {
+                None
+            };
+            if let Some(buffer) = &screenshot {
+                record_readback(
+                    &self.device,
+                    command_buffer,
+                    self.target.image(image_index),
+                    self.target.layout(),
+                    self.target.output_extent(),
+                    buffer.buffer(),
+                );
+            }
+
+            self.device
+                .end_command_buffer(command_buffer)
+                .map_err(&error)?;
+
+            // === SUBMIT ===
+
+            let command_buffers = [command_buffer];
+            let wait_semaphores = [frame.image_available];
+            let wait_stages =
+                [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::TRANSFER];
+            let signal_semaphores = [frame.render_finished];
+
+            let mut submit = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+            if swapchain.is_some() {
+                submit = submit
+                    .wait_semaphores(&wait_semaphores)
+                    .wait_dst_stage_mask(&wait_stages)
+                    .signal_semaphores(&signal_semaphores);
+            }
+
             self.device
-                .create_shader_module(&create_info, None)
-                .unwrap()
+                .queue_submit(self.present_queue, &[submit.build()], frame.in_flight)
+                .map_err(&error)?;
+
+            if let Some(buffer) = screenshot {
+                self.device
+                    .wait_for_fences(&fences, true, u64::MAX)
+                    .map_err(&error)?;
+
+                self.screenshots.retain(|handle| !handle.is_finished());
+                let extent = self.target.output_extent();
+                self.screenshots.push(capture::save_screenshot(
+                    self.target.format(),
+                    extent.width,
+                    extent.height,
+                    buffer.read(),
+                ));
+            }
+
+            // === PRESENT ===
+
+            if let Some(swapchain) = swapchain {
+                let swapchains = [swapchain.swapchain()];
+                let image_indices = [image_index];
+                let present_info = vk::PresentInfoKHR::builder()
+                    .wait_semaphores(&signal_semaphores)
+                    .swapchains(&swapchains)
+                    .image_indices(&image_indices);
+
+                match swapchain
+                    .swapchain_loader()
+                    .queue_present(self.present_queue, &present_info)
+                {
+                    Ok(false) => (),
+                    Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.stale = true,
+                    Err(e) => return Err(error(e)),
+                }
+            }
         }
+
+        self.frame = (self.frame + 1) % FRAMES_IN_FLIGHT;
+
+        self.check_validation();
+        Ok(())
     }
 
-    pub fn recreate_swapchain(&mut self) {
+    /// Reads back the last rendered offscreen image as tightly packed RGBA8 texels.
+    pub fn read_pixels(&self) -> Vec<u8> {
+        let image = match self.target.output() {
+            Output::Offscreen(image) => image,
+            Output::Swapchain(_) => panic!("only headless rendering can be read back"),
+        };
+
+        let extent = image.extent();
+        let buffer = Buffer::new(
+            self,
+            u64::from(extent.width * extent.height * 4),
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+
         unsafe {
-            self.device.device_wait_idle();
+            self.device.device_wait_idle().unwrap();
+        }
+
+        self.one_time_commands(|device, command_buffer| {
+            record_readback(
+                device,
+                command_buffer,
+                image.image(),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                extent,
+                buffer.buffer(),
+            );
+        });
 
-            ManuallyDrop::drop(&mut self.swapchain);
+        buffer.read()
+    }
+
+    /// A host visible buffer fitting the output image, `None` when it can't be captured.
+    fn screenshot_buffer(&self) -> Option<Buffer> {
+        let format = self.target.format();
+        if !capture::is_supported(format) {
+            log::warn!("screenshots of {:?} surfaces are not supported", format);
+            return None;
+        }
+        if !self.target.is_readable() {
+            log::warn!("surface doesn't support copying from swapchain images");
+            return None;
+        }
+
+        let extent = self.target.output_extent();
+        Some(Buffer::new(
+            self,
+            u64::from(extent.width * extent.height * 4),
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        ))
+    }
+
+    /// Writes the last rendered offscreen image to a PNG file.
+    pub fn save_png(&self, path: impl AsRef<Path>) {
+        let extent = self.target.output_extent();
+        capture::write_png(path, extent.width, extent.height, &self.read_pixels());
+    }
 
-            self.swapchain = ManuallyDrop::new(Arc::new(
-                Swapchain::builder()
+    /// Rebuilds the target for the current window size, present mode and multisampling, and the
+    /// render pass if its attachments changed.
+    pub fn recreate_target(&mut self) -> Result<()> {
+        let output = match (&self.window, &self.surface_loader) {
+            (Some(window), Some(surface_loader)) => {
+                // Minimized windows can't have a swapchain, stay stale until restored
+                let size = window.inner_size();
+                if size.width == 0 || size.height == 0 {
+                    return Ok(());
+                }
+
+                let (old_swapchain, old_format) = match self.target.output() {
+                    Output::Swapchain(swapchain) => (swapchain.swapchain(), swapchain.format()),
+                    Output::Offscreen(_) => unreachable!("windows draw to a swapchain"),
+                };
+
+                self.wait_idle()?;
+
+                let swapchain = Swapchain::builder()
                     .instance(self.instance.clone())
                     .surface(self.surface)
-                    .surface_loader(self.surface_loader.clone())
+                    .surface_loader(surface_loader.clone())
                     .physical_device(self.physical_device)
                     .device(self.device.clone())
-                    .build(&self.window.inner_size()),
-            ));
+                    .present_mode(self.present_mode)
+                    .hdr(self.hdr)
+                    .previous_format(old_format)
+                    .old_swapchain(old_swapchain)
+                    .build(&size)?;
+
+                // Only when moving to a display that doesn't support the old format anymore
+                if swapchain.format().format != old_format.format {
+                    log::info!(
+                        "surface format changed from {:?} to {:?}",
+                        old_format.format,
+                        swapchain.format().format
+                    );
+                }
+                Output::Swapchain(Arc::new(swapchain))
+            }
+            // Offscreen images keep their size, only multisampling and scale change
+            _ => {
+                self.wait_idle()?;
+                self.target.output().clone()
+            }
+        };
+
+        let samples = self.msaa.choose(self.supported_samples);
+        let scale = upscalable_scale(&output, self.render_scale);
+        let scaled = is_scaled(&output, scale);
+        let render_pass = if output.format() != self.target.format()
+            || samples != self.target.samples()
+            || scaled != self.target.scaled().is_some()
+        {
+            create_render_pass(
+                &self.device,
+                output.format(),
+                self.target.layout(),
+                samples,
+                scaled,
+            )?
+        } else {
+            self.render_pass
+        };
+
+        let target = Target::new(
+            self.device.clone(),
+            &self.memory_properties,
+            render_pass,
+            output,
+            samples,
+            scale,
+        );
+        let target = match target {
+            Ok(target) => target,
+            Err(e) => {
+                if render_pass != self.render_pass {
+                    unsafe { self.device.destroy_render_pass(render_pass, None) };
+                }
+                return Err(e);
+            }
+        };
+
+        // Replaces and drops the old target only once everything succeeded
+        *self.target = target;
+        if render_pass != self.render_pass {
+            unsafe { self.device.destroy_render_pass(self.render_pass, None) };
+            self.render_pass = render_pass;
+        }
+
+        self.stale = false;
+        Ok(())
+    }
+
+    fn wait_idle(&self) -> Result<()> {
+        unsafe {
+            self.device
+                .device_wait_idle()
+                .map_err(Error::vulkan("waiting for the device"))
         }
     }
 }
@@ -240,14 +940,378 @@ impl Vulkan {
 impl Drop for Vulkan {
     fn drop(&mut self) {
         unsafe {
-            ManuallyDrop::drop(&mut self.swapchain);
+            self.device.device_wait_idle().unwrap();
+
+            self.frames.clear();
+            ManuallyDrop::drop(&mut self.target);
 
+            // Let pending screenshots finish, a panicked one already reported itself
+            for handle in self.screenshots.drain(..) {
+                let _ = handle.join();
+            }
+
+            self.device.destroy_render_pass(self.render_pass, None);
+            self.device.destroy_command_pool(self.command_pool, None);
             self.device.destroy_device(None);
 
-            self.surface_loader.destroy_surface(self.surface, None);
-            self.debug_utils_loader
-                .destroy_debug_utils_messenger(self.debug_callback, None);
+            if let Some(surface_loader) = &self.surface_loader {
+                surface_loader.destroy_surface(self.surface, None);
+            }
+            self.messenger = None;
             self.instance.destroy_instance(None);
         }
     }
 }
+
+/// Like `check_instance_support`, but warns that `feature` is disabled instead of failing when
+/// the layers or extensions are missing.
+unsafe fn has_instance_support(
+    entry: &Entry,
+    layers: &[&CStr],
+    extensions: &[&CStr],
+    feature: &str,
+) -> Result<bool> {
+    match check_instance_support(entry, layers, extensions) {
+        Ok(()) => Ok(true),
+        Err(e @ (Error::MissingLayer(_) | Error::MissingExtension(_))) => {
+            log::warn!("{} disabled: {}", feature, e);
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Fails with the first of `layers` or `extensions` the Vulkan loader doesn't provide.
+unsafe fn check_instance_support(
+    entry: &Entry,
+    layers: &[&CStr],
+    extensions: &[&CStr],
+) -> Result<()> {
+    let error = Error::vulkan("querying instance support");
+
+    if !layers.is_empty() {
+        let available = entry
+            .enumerate_instance_layer_properties()
+            .map_err(&error)?;
+        for &layer in layers {
+            let found = available
+                .iter()
+                .any(|properties| CStr::from_ptr(properties.layer_name.as_ptr()) == layer);
+            if !found {
+                return Err(Error::MissingLayer(layer.to_string_lossy().into_owned()));
+            }
+        }
+    }
+
+    let available = entry
+        .enumerate_instance_extension_properties()
+        .map_err(&error)?;
+    for &extension in extensions {
+        let found = available
+            .iter()
+            .any(|properties| CStr::from_ptr(properties.extension_name.as_ptr()) == extension);
+        if !found {
+            return Err(Error::MissingExtension(
+                extension.to_string_lossy().into_owned(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Records a copy of a 4 byte per texel color image in `layout` to `buffer`, leaving the image
+/// in `layout` again.
+fn record_readback(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layout: vk::ImageLayout,
+    extent: vk::Extent2D,
+    buffer: vk::Buffer,
+) {
+    let subresource_range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+
+    // Make the writes visible to the transfer, by the render pass or by the upscale blit with a
+    // render scale below one
+    let to_transfer = [vk::ImageMemoryBarrier::builder()
+        .old_layout(layout)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .image(image)
+        .subresource_range(subresource_range)
+        .build()];
+
+    let regions = [vk::BufferImageCopy {
+        image_subresource: vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        image_extent: vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+        ..Default::default()
+    }];
+
+    let to_layout = [vk::ImageMemoryBarrier::builder()
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(layout)
+        .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .image(image)
+        .subresource_range(subresource_range)
+        .build()];
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &to_transfer,
+        );
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer,
+            &regions,
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &to_layout,
+        );
+    }
+}
+
+/// Records blitting the image rendered at a lower render scale in `TRANSFER_SRC_OPTIMAL` to
+/// `output`, leaving the output image in `layout`.
+fn record_upscale(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    scaled: &Image,
+    output: vk::Image,
+    extent: vk::Extent2D,
+    layout: vk::ImageLayout,
+) {
+    let subresource_range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let subresource = vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let corner = |extent: vk::Extent2D| vk::Offset3D {
+        x: extent.width as i32,
+        y: extent.height as i32,
+        z: 1,
+    };
+
+    // The render pass already moved the scaled image to the transfer layout, the output's old
+    // contents get overwritten entirely
+    let to_transfer = [
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .image(scaled.image())
+            .subresource_range(subresource_range)
+            .build(),
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .image(output)
+            .subresource_range(subresource_range)
+            .build(),
+    ];
+
+    let regions = [vk::ImageBlit {
+        src_subresource: subresource,
+        src_offsets: [vk::Offset3D::default(), corner(scaled.extent())],
+        dst_subresource: subresource,
+        dst_offsets: [vk::Offset3D::default(), corner(extent)],
+    }];
+
+    let to_layout = [vk::ImageMemoryBarrier::builder()
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(layout)
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .image(output)
+        .subresource_range(subresource_range)
+        .build()];
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &to_transfer,
+        );
+        device.cmd_blit_image(
+            command_buffer,
+            scaled.image(),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            output,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &regions,
+            vk::Filter::LINEAR,
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &to_layout,
+        );
+    }
+}
+
+/// `scale` if it can be upscaled to `output`, otherwise rendering at full size.
+fn upscalable_scale(output: &Output, scale: f32) -> f32 {
+    if is_scaled(output, scale) && !output.is_upscalable() {
+        log::warn!("surface doesn't support blitting to swapchain images, ignoring render scale");
+        return 1.0;
+    }
+    scale
+}
+
+fn is_scaled(output: &Output, scale: f32) -> bool {
+    target::scaled_extent(output.extent(), scale) != output.extent()
+}
+
+/// The main render pass drawing `samples` per pixel. With multisampling they get resolved to a
+/// third attachment, and when `scaled` the result is left for `record_upscale` instead of in
+/// `final_layout`.
+fn create_render_pass(
+    device: &Device,
+    color_format: vk::Format,
+    final_layout: vk::ImageLayout,
+    samples: vk::SampleCountFlags,
+    scaled: bool,
+) -> Result<vk::RenderPass> {
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let final_layout = if scaled {
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+    } else {
+        final_layout
+    };
+
+    let mut renderpass_attachments = vec![
+        vk::AttachmentDescription {
+            format: color_format,
+            samples,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            // Samples are only needed until they're resolved
+            store_op: if multisampled {
+                vk::AttachmentStoreOp::DONT_CARE
+            } else {
+                vk::AttachmentStoreOp::STORE
+            },
+            final_layout: if multisampled {
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            } else {
+                final_layout
+            },
+            ..Default::default()
+        },
+        vk::AttachmentDescription {
+            format: Target::DEPTH_FORMAT,
+            samples,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ..Default::default()
+        },
+    ];
+    if multisampled {
+        renderpass_attachments.push(vk::AttachmentDescription {
+            format: color_format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            final_layout,
+            ..Default::default()
+        });
+    }
+    let color_attachment_refs = [vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    }];
+    let depth_attachment_ref = vk::AttachmentReference {
+        attachment: 1,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+    let resolve_attachment_refs = [vk::AttachmentReference {
+        attachment: 2,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    }];
+    // The scaled, multisampled and depth images are shared by the frames in flight, so the
+    // previous frame's attachment writes and upscale blit must finish before they get reused
+    let dependencies = [vk::SubpassDependency {
+        src_subpass: vk::SUBPASS_EXTERNAL,
+        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::TRANSFER,
+        src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+        ..Default::default()
+    }];
+
+    let mut subpass = vk::SubpassDescription::builder()
+        .color_attachments(&color_attachment_refs)
+        .depth_stencil_attachment(&depth_attachment_ref)
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+    if multisampled {
+        subpass = subpass.resolve_attachments(&resolve_attachment_refs);
+    }
+    let subpasses = [subpass.build()];
+
+    let renderpass_create_info = vk::RenderPassCreateInfo::builder()
+        .attachments(&renderpass_attachments)
+        .subpasses(&subpasses)
+        .dependencies(&dependencies);
+
+    unsafe {
+        device
+            .create_render_pass(&renderpass_create_info, None)
+            .map_err(Error::vulkan("creating the render pass"))
+    }
+}
@@ -23,7 +23,7 @@ impl Window {
         self.input_buffer.push(input);
     }
 
-    pub fn cycle(&mut self, update: impl Fn(Duration, &[Input])) {
+    pub fn cycle(&mut self, mut update: impl FnMut(Duration, &[Input])) {
         self.exec_time += SystemTime::now().duration_since(self.curr_time).unwrap();
         self.curr_time = SystemTime::now();
 
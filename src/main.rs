@@ -1,12 +1,77 @@
 mod app;
 mod game;
 mod gfx;
+mod settings;
 mod shaders;
 mod world;
 
+use std::env;
+use std::process;
+
 use app::App;
+use gfx::Vulkan;
+use settings::{Settings, SETTINGS_FILE};
+
+const USAGE: &str =
+    "usage: minecraft [--list-gpus] [--headless <out.png>] [--<setting> <value>]...";
+
+const HEADLESS_WIDTH: u32 = 800;
+const HEADLESS_HEIGHT: u32 = 600;
 
 fn main() {
-    let mut app = App::new();
-    app.run();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut settings = Settings::load(SETTINGS_FILE);
+    let mut headless = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let key = match arg.strip_prefix("--") {
+            Some(key) => key,
+            None => usage_error(&format!("unexpected argument '{}'", arg)),
+        };
+
+        match key {
+            "list-gpus" => {
+                print!("{}", Vulkan::report_gpus());
+                return;
+            }
+            "headless" => headless = Some(value(&mut args, key)),
+            _ => {
+                let value = value(&mut args, key);
+                if let Err(e) = settings.set_override(key, &value) {
+                    usage_error(&e);
+                }
+            }
+        }
+    }
+
+    match headless {
+        Some(path) => {
+            if let Err(e) =
+                App::screenshot_headless(&settings, path, HEADLESS_WIDTH, HEADLESS_HEIGHT)
+            {
+                fail(e);
+            }
+        }
+        None => {
+            let app = App::new(&settings).unwrap_or_else(|e| fail(e));
+            app.run();
+        }
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, key: &str) -> String {
+    args.next()
+        .unwrap_or_else(|| usage_error(&format!("missing value for --{}", key)))
+}
+
+fn fail(error: gfx::Error) -> ! {
+    log::error!("{}", error);
+    process::exit(1);
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
 }
@@ -0,0 +1,291 @@
+//! # Settings
+//!
+//! User settings read from `options.txt`, one `key:value` per line. Command line arguments
+//! override them for a single run, as do environment variables like `MINECRAFT_VALIDATION`.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::gfx::renderers::{CloudMode, Leaves, ShadowQuality};
+use crate::gfx::{GpuSelector, Msaa, PresentMode, Validation, MIN_RENDER_SCALE, VALIDATION_ENV};
+
+pub const SETTINGS_FILE: &str = "options.txt";
+
+/// Default `lod_distance`, in chunks.
+pub const LOD_DISTANCE: u32 = 8;
+/// Default `render_distance`, in chunks.
+pub const RENDER_DISTANCE: u32 = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// GPU to render with instead of the best suitable one.
+    pub gpu: Option<GpuSelector>,
+    pub vsync: PresentMode,
+    /// Frame rate cap, unlimited if `None`.
+    pub max_fps: Option<u32>,
+    /// Chunks further than this aren't drawn, with distance fog ending there.
+    pub render_distance: u32,
+    /// Chunks within this distance are meshed at full detail, further ones coarser. `None`
+    /// meshes everything at full detail.
+    pub lod_distance: Option<u32>,
+    pub leaves: Leaves,
+    pub clouds: CloudMode,
+    pub shadows: ShadowQuality,
+    /// Rain falling around the camera.
+    pub rain: bool,
+    /// Samples per pixel, clamped to what the GPU supports.
+    pub msaa: Msaa,
+    /// Fraction of the window size to render at before upscaling, from 0.25 to 1.
+    pub render_scale: f32,
+    /// Outputs scRGB or HDR10 when the display supports it.
+    pub hdr: bool,
+    /// Vulkan validation layers, on in debug builds.
+    pub validation: Validation,
+    /// Keys and values set with `set_override`, applied again by `reload`.
+    overrides: Vec<(String, String)>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            gpu: None,
+            vsync: PresentMode::default(),
+            max_fps: None,
+            render_distance: RENDER_DISTANCE,
+            lod_distance: Some(LOD_DISTANCE),
+            leaves: Leaves::default(),
+            clouds: CloudMode::default(),
+            shadows: ShadowQuality::default(),
+            rain: false,
+            msaa: Msaa::default(),
+            render_scale: 1.0,
+            hdr: false,
+            validation: Validation::default(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Reads settings from `path`, falling back to defaults if the file doesn't exist.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let mut settings = match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text, &path.display().to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                log::warn!("failed to read '{}': {}", path.display(), e);
+                Self::default()
+            }
+        };
+
+        if let Ok(value) = env::var(VALIDATION_ENV) {
+            if let Err(e) = settings.set("validation", &value) {
+                log::warn!("{}: {}", VALIDATION_ENV, e);
+            }
+        }
+        settings
+    }
+
+    /// Reads settings from `path` again, keeping the overrides.
+    pub fn reload(&self, path: impl AsRef<Path>) -> Self {
+        self.overridden(Self::load(path))
+    }
+
+    /// `settings` with our overrides applied on top.
+    fn overridden(&self, mut settings: Self) -> Self {
+        for (key, value) in &self.overrides {
+            if let Err(e) = settings.set_override(key, value) {
+                log::warn!("--{}: {}", key, e);
+            }
+        }
+        settings
+    }
+
+    /// Parses `key:value` lines, warning about and skipping invalid ones. `source` names the
+    /// settings in warnings.
+    pub fn parse(text: &str, source: &str) -> Self {
+        let mut settings = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let result = match line.split_once(':') {
+                Some((key, value)) => settings.set(key.trim(), value.trim()),
+                None => Err("expected 'key:value'".to_string()),
+            };
+            if let Err(e) = result {
+                log::warn!("{}:{}: {}", source, number + 1, e);
+            }
+        }
+        settings
+    }
+
+    /// Sets `key` like `set`, but keeps it when reloading, for `--key value` arguments.
+    pub fn set_override(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.set(key, value)?;
+        self.overrides.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    /// Sets a setting by its key in the settings file.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "gpu" => self.gpu = Some(value.parse()?),
+            "lod_distance" => {
+                self.lod_distance = match value {
+                    "off" => None,
+                    _ => Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid distance '{}'", value))?,
+                    ),
+                }
+            }
+            "render_distance" => {
+                self.render_distance = value
+                    .parse()
+                    .map_err(|_| format!("invalid distance '{}'", value))?
+            }
+            "leaves" => self.leaves = value.parse()?,
+            "clouds" => self.clouds = value.parse()?,
+            "shadows" => self.shadows = value.parse()?,
+            "rain" => self.rain = parse_bool(value)?,
+            "msaa" => self.msaa = value.parse()?,
+            "render_scale" => {
+                self.render_scale = value
+                    .parse()
+                    .ok()
+                    .filter(|scale| (MIN_RENDER_SCALE..=1.0).contains(scale))
+                    .ok_or_else(|| format!("invalid render scale '{}'", value))?
+            }
+            "hdr" => self.hdr = parse_bool(value)?,
+            "vsync" => self.vsync = value.parse()?,
+            "max_fps" => {
+                self.max_fps = match value {
+                    "off" | "0" => None,
+                    _ => Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid frame rate '{}'", value))?,
+                    ),
+                }
+            }
+            "validation" | "validation_level" | "validation_panic" => {
+                self.validation.set(key, value)?
+            }
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Parses a boolean setting, `true`/`false` or `1`/`0`.
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(format!("expected 'true' or 'false', got '{}'", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_overrides_when_reloading() {
+        let mut settings = Settings::parse("vsync:on\nmax_fps:60", "test");
+        settings.set_override("vsync", "off").unwrap();
+        assert!(settings.set_override("max_fps", "lots").is_err());
+
+        let reloaded = settings.overridden(Settings::parse("vsync:on\nmax_fps:30", "test"));
+        assert_eq!(reloaded.vsync, PresentMode::Immediate);
+        assert_eq!(reloaded.max_fps, Some(30));
+    }
+
+    #[test]
+    fn parses_and_skips_invalid_lines() {
+        let settings = Settings::parse("# comment\n\nbogus\nfov:90\ngpu: GeForce RTX\n", "test");
+        assert_eq!(
+            settings.gpu,
+            Some(GpuSelector::Name("GeForce RTX".to_string()))
+        );
+
+        let settings = Settings::parse("gpu:1", "test");
+        assert_eq!(settings.gpu, Some(GpuSelector::Index(1)));
+    }
+
+    #[test]
+    fn parses_validation() {
+        let settings = Settings::parse(
+            "validation:true\nvalidation_level:info\nvalidation_panic:yes",
+            "test",
+        );
+        assert!(settings.validation.enabled);
+        assert_eq!(settings.validation.level, log::LevelFilter::Info);
+        assert!(!settings.validation.panic_on_error);
+    }
+
+    #[test]
+    fn parses_presentation() {
+        let settings = Settings::parse("vsync:off\nmax_fps:144\nhdr:1", "test");
+        assert!(settings.hdr);
+        assert_eq!(settings.vsync, PresentMode::Immediate);
+        assert_eq!(settings.max_fps, Some(144));
+        assert_eq!(Settings::parse("max_fps:off", "test").max_fps, None);
+    }
+
+    #[test]
+    fn parses_distances() {
+        assert_eq!(Settings::parse("", "test").lod_distance, Some(LOD_DISTANCE));
+        assert_eq!(
+            Settings::parse("lod_distance:4", "test").lod_distance,
+            Some(4)
+        );
+        assert_eq!(
+            Settings::parse("lod_distance:off", "test").lod_distance,
+            None
+        );
+        assert_eq!(
+            Settings::parse("render_distance:6", "test").render_distance,
+            6
+        );
+    }
+
+    #[test]
+    fn parses_effects() {
+        assert_eq!(Settings::parse("leaves:fast", "test").leaves, Leaves::Fast);
+        assert_eq!(Settings::parse("leaves:ugly", "test").leaves, Leaves::Fancy);
+        assert_eq!(
+            Settings::parse("clouds:fast", "test").clouds,
+            CloudMode::Fast
+        );
+        assert_eq!(Settings::parse("", "test").shadows, ShadowQuality::Off);
+        assert_eq!(
+            Settings::parse("shadows:high", "test").shadows,
+            ShadowQuality::High
+        );
+        assert!(!Settings::parse("", "test").rain);
+        assert!(Settings::parse("rain:true", "test").rain);
+    }
+
+    #[test]
+    fn parses_antialiasing_and_render_scale() {
+        assert_eq!(Settings::parse("msaa:4", "test").msaa, Msaa::X4);
+        assert_eq!(Settings::parse("", "test").render_scale, 1.0);
+        assert_eq!(
+            Settings::parse("render_scale:0.5", "test").render_scale,
+            0.5
+        );
+        assert_eq!(
+            Settings::parse("render_scale:0.1", "test").render_scale,
+            1.0
+        );
+    }
+}
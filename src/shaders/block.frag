@@ -1,10 +1,122 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
-layout(location = 0) in vec3 fragColor;
+// See `OutputEncoding`
+const uint ENCODING_LINEAR = 0;
+const uint ENCODING_SRGB = 1;
+const uint ENCODING_PQ = 2;
+
+// Brightness of white on HDR10 displays, in nits
+const float HDR_WHITE = 203.0;
+
+layout(push_constant) uniform Constants {
+    mat4 viewProj;
+    uint outputEncoding;
+    float alphaCutoff;
+    // Brightness of daylight, see `Time::sky_light`
+    float skyLight;
+    // See `Fog`, alpha is unused
+    vec4 fogColor;
+    vec3 eye;
+    float fogStart;
+    float fogEnd;
+} constants;
+
+layout(location = 0) in vec3 fragUv;
+layout(location = 1) in float fragShade;
+layout(location = 2) in vec3 fragPosition;
+
+layout(set = 0, binding = 0) uniform texture2DArray blockTextures;
+layout(set = 0, binding = 1) uniform sampler blockSampler;
+
+// See `shadows::Cascade`
+layout(set = 0, binding = 2) uniform Shadows {
+    mat4 cascades[3];
+    // Far distance of each cascade along the view direction, shadow strength in w
+    vec4 splits;
+    // View direction, size of a shadow map texel in w
+    vec4 forward;
+} shadows;
+layout(set = 0, binding = 3) uniform texture2DArray shadowMap;
+layout(set = 0, binding = 4) uniform samplerShadow shadowSampler;
 
 layout(location = 0) out vec4 outColor;
 
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+vec3 encodePq(vec3 linear) {
+    // Rec.709 to Rec.2020 primaries
+    mat3 toRec2020 = mat3(
+        0.6274, 0.0691, 0.0164,
+        0.3293, 0.9195, 0.0880,
+        0.0433, 0.0114, 0.8956
+    );
+    vec3 y = clamp(toRec2020 * linear * (HDR_WHITE / 10000.0), 0.0, 1.0);
+
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 ym = pow(y, vec3(m1));
+    return pow((c1 + c2 * ym) / (1.0 + c3 * ym), vec3(m2));
+}
+
+vec3 encode(vec3 linear) {
+    if (constants.outputEncoding == ENCODING_SRGB) {
+        return encodeSrgb(linear);
+    }
+    if (constants.outputEncoding == ENCODING_PQ) {
+        return encodePq(linear);
+    }
+    return linear;
+}
+
+// How much sunlight reaches the fragment, from 0 in shadow to 1, filtered over the texels
+// around it to soften edges
+float sunlight() {
+    float depth = dot(fragPosition - constants.eye, shadows.forward.xyz);
+    int cascade = 0;
+    while (cascade < 3 && depth > shadows.splits[cascade]) {
+        cascade += 1;
+    }
+    if (cascade == 3) {
+        return 1.0;
+    }
+
+    vec4 p = shadows.cascades[cascade] * vec4(fragPosition, 1.0);
+    vec2 uv = p.xy * 0.5 + 0.5;
+    float texel = shadows.forward.w;
+    float lit = 0.0;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            vec2 offset = vec2(float(x), float(y)) * texel;
+            vec4 coord = vec4(uv + offset, float(cascade), p.z);
+            lit += texture(sampler2DArrayShadow(shadowMap, shadowSampler), coord);
+        }
+    }
+    return lit / 9.0;
+}
+
 void main() {
-    outColor = vec4(fragColor, 1.0);
+    vec4 color = texture(sampler2DArray(blockTextures, blockSampler), fragUv);
+    if (color.a < constants.alphaCutoff) {
+        discard;
+    }
+    vec3 lit = color.rgb * fragShade * constants.skyLight;
+    float strength = shadows.splits.w;
+    if (strength > 0.0) {
+        lit *= mix(1.0 - strength, 1.0, sunlight());
+    }
+
+    float distance = length(fragPosition - constants.eye);
+    float fog = 0.0;
+    if (constants.fogEnd > constants.fogStart) {
+        fog = smoothstep(constants.fogStart, constants.fogEnd, distance);
+    }
+    outColor = vec4(encode(mix(lit, constants.fogColor.rgb, fog)), color.a);
 }
@@ -1,21 +1,28 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
-layout(location = 0) out vec3 fragColor;
+layout(push_constant) uniform Constants {
+    mat4 viewProj;
+    uint outputEncoding;
+    float alphaCutoff;
+    float skyLight;
+    vec4 fogColor;
+    vec3 eye;
+    float fogStart;
+    float fogEnd;
+} constants;
 
-vec2 positions[3] = vec2[](
-vec2(0.0, -0.5),
-vec2(0.5, 0.5),
-vec2(-0.5, 0.5)
-);
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inUv;
+layout(location = 2) in float inShade;
 
-vec3 colors[3] = vec3[](
-vec3(1.0, 0.0, 0.0),
-vec3(0.0, 1.0, 0.0),
-vec3(0.0, 0.0, 1.0)
-);
+layout(location = 0) out vec3 fragUv;
+layout(location = 1) out float fragShade;
+layout(location = 2) out vec3 fragPosition;
 
 void main() {
-    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
-    fragColor = colors[gl_VertexIndex];
-}
\ No newline at end of file
+    gl_Position = constants.viewProj * vec4(inPosition, 1.0);
+    fragUv = inUv;
+    fragShade = inShade;
+    fragPosition = inPosition;
+}
@@ -0,0 +1,30 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform Clouds {
+    mat4 viewProj;
+    vec4 origin;
+    vec4 eye;
+    vec4 sun;
+    vec4 color;
+    vec4 fogColor;
+    vec4 fog;
+    uvec4 params;
+} clouds;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out vec3 fragNormal;
+
+void main() {
+    // Instances repeat the tile on a 3×3 grid around the camera
+    uint instance = uint(gl_InstanceIndex);
+    vec2 tile = vec2(float(instance % 3u), float(instance / 3u)) * clouds.origin.w;
+    vec3 position = inPosition + clouds.origin.xyz + vec3(tile.x, 0.0, tile.y);
+
+    gl_Position = clouds.viewProj * vec4(position, 1.0);
+    fragPosition = position;
+    fragNormal = inNormal;
+}
@@ -0,0 +1,55 @@
+//! # Compile
+//!
+//! GLSL to SPIR-V compilation, shared by the build script and shader hot reloading.
+
+use std::fs;
+use std::path::Path;
+
+use naga::back::spv;
+use naga::front::glsl;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::ShaderStage;
+
+pub fn is_shader(path: &Path) -> bool {
+    stage(path).is_some()
+}
+
+/// Compiles the shader at `path`, its stage given by the `.vert`, `.frag` or `.comp` extension.
+pub fn compile(path: &Path) -> Result<Vec<u32>, String> {
+    let display = path.display().to_string();
+
+    let stage = stage(path).ok_or_else(|| format!("{}: unknown shader stage", display))?;
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", display, e))?;
+
+    let module = glsl::Frontend::default()
+        .parse(&glsl::Options::from(stage), &source)
+        .map_err(|errors| {
+            errors
+                .iter()
+                .map(|e| {
+                    let location = e.meta.location(&source);
+                    format!(
+                        "{}:{}:{}: {}",
+                        display, location.line_number, location.line_position, e.kind
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })?;
+
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| e.emit_to_string_with_path(&source, &display))?;
+
+    spv::write_vec(&module, &info, &spv::Options::default(), None)
+        .map_err(|e| format!("{}: {}", display, e))
+}
+
+fn stage(path: &Path) -> Option<ShaderStage> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(ShaderStage::Vertex),
+        "frag" => Some(ShaderStage::Fragment),
+        "comp" => Some(ShaderStage::Compute),
+        _ => None,
+    }
+}
@@ -0,0 +1,16 @@
+#[cfg(debug_assertions)]
+mod compile;
+#[cfg(debug_assertions)]
+mod watcher;
+
+#[cfg(debug_assertions)]
+pub use watcher::Watcher;
+
+/// Includes SPIR-V compiled by the build script from `src/shaders/<name>`.
+macro_rules! spirv {
+    ($name:literal) => {
+        &include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".spv"))[..]
+    };
+}
+
+pub(crate) use spirv;
@@ -0,0 +1,42 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 2) uniform Particles {
+    mat4 viewProj;
+    // Camera axes billboards are spanned by
+    vec4 right;
+    vec4 up;
+    vec4 eye;
+    vec4 fogColor;
+    vec4 fog;
+    uvec4 params;
+} particles;
+
+// Center and texture layer
+layout(location = 0) in vec4 inPosition;
+// Corner and size of the part of the texture shown
+layout(location = 1) in vec4 inUv;
+// Width and height, brightness and opacity
+layout(location = 2) in vec4 inSize;
+
+layout(location = 0) out vec3 fragUv;
+layout(location = 1) out vec2 fragColor;
+layout(location = 2) out vec3 fragPosition;
+
+void main() {
+    // Two triangles facing the camera
+    uint i = uint(gl_VertexIndex);
+    vec2 corner = vec2(
+        i == 1u || i == 2u || i == 4u ? 1.0 : 0.0,
+        i == 2u || i == 4u || i == 5u ? 1.0 : 0.0
+    );
+    vec2 offset = (corner - 0.5) * inSize.xy;
+    vec3 pos = inPosition.xyz + particles.right.xyz * offset.x + particles.up.xyz * offset.y;
+
+    gl_Position = particles.viewProj * vec4(pos, 1.0);
+    // Texture rows go down
+    vec2 uv = inUv.xy + vec2(corner.x, 1.0 - corner.y) * inUv.zw;
+    fragUv = vec3(uv, inPosition.w);
+    fragColor = inSize.zw;
+    fragPosition = pos;
+}
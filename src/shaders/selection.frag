@@ -0,0 +1,67 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// See `OutputEncoding`
+const uint ENCODING_LINEAR = 0;
+const uint ENCODING_SRGB = 1;
+const uint ENCODING_PQ = 2;
+
+// Brightness of white on HDR10 displays, in nits
+const float HDR_WHITE = 203.0;
+
+layout(push_constant) uniform Constants {
+    mat4 viewProj;
+    // Linear color and opacity, multiplied with the texture if textured
+    vec4 color;
+    uint outputEncoding;
+    uint textured;
+} constants;
+
+layout(location = 0) in vec3 fragUv;
+
+layout(set = 0, binding = 0) uniform texture2DArray blockTextures;
+layout(set = 0, binding = 1) uniform sampler blockSampler;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+vec3 encodePq(vec3 linear) {
+    // Rec.709 to Rec.2020 primaries
+    mat3 toRec2020 = mat3(
+        0.6274, 0.0691, 0.0164,
+        0.3293, 0.9195, 0.0880,
+        0.0433, 0.0114, 0.8956
+    );
+    vec3 y = clamp(toRec2020 * linear * (HDR_WHITE / 10000.0), 0.0, 1.0);
+
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 ym = pow(y, vec3(m1));
+    return pow((c1 + c2 * ym) / (1.0 + c3 * ym), vec3(m2));
+}
+
+vec3 encode(vec3 linear) {
+    if (constants.outputEncoding == ENCODING_SRGB) {
+        return encodeSrgb(linear);
+    }
+    if (constants.outputEncoding == ENCODING_PQ) {
+        return encodePq(linear);
+    }
+    return linear;
+}
+
+void main() {
+    vec4 color = constants.color;
+    if (constants.textured != 0u) {
+        color *= texture(sampler2DArray(blockTextures, blockSampler), fragUv);
+    }
+    outColor = vec4(encode(color.rgb), color.a);
+}
@@ -0,0 +1,19 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Constants {
+    mat4 viewProj;
+    vec4 color;
+    uint outputEncoding;
+    uint textured;
+} constants;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inUv;
+
+layout(location = 0) out vec3 fragUv;
+
+void main() {
+    gl_Position = constants.viewProj * vec4(inPosition, 1.0);
+    fragUv = inUv;
+}
@@ -0,0 +1,22 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Constants {
+    mat4 lightViewProj;
+    float alphaCutoff;
+} constants;
+
+layout(location = 0) in vec3 fragUv;
+
+layout(set = 0, binding = 0) uniform texture2DArray blockTextures;
+layout(set = 0, binding = 1) uniform sampler blockSampler;
+
+// Only depth gets written, cutout blocks cast shadows where they're solid
+void main() {
+    if (constants.alphaCutoff > 0.0) {
+        float alpha = texture(sampler2DArray(blockTextures, blockSampler), fragUv).a;
+        if (alpha < constants.alphaCutoff) {
+            discard;
+        }
+    }
+}
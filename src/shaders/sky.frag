@@ -0,0 +1,131 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// See `OutputEncoding`
+const uint ENCODING_LINEAR = 0;
+const uint ENCODING_SRGB = 1;
+const uint ENCODING_PQ = 2;
+
+// Brightness of white on HDR10 displays, in nits
+const float HDR_WHITE = 203.0;
+
+// See `Body`
+const uint BODY_DOME = 0;
+
+// Stars are spread over cells of a grid around the sky, about one in a few hundred has one
+const float STAR_CELLS = 160.0;
+const float STAR_CHANCE = 0.004;
+
+layout(push_constant) uniform Constants {
+    uint body;
+} constants;
+
+layout(set = 0, binding = 2) uniform Sky {
+    mat4 viewProj;
+    mat4 invViewProj;
+    // Alpha is how visible stars are
+    vec4 zenith;
+    vec4 horizon;
+    // Sunrise and sunset light around the sun
+    vec4 glow;
+    // Directions, with the billboard's half size in alpha
+    vec4 sun;
+    vec4 moon;
+    // Axis the sky turns around and its angle
+    vec4 stars;
+    // Fog color, with how much it hides the sky in alpha
+    vec4 fog;
+    // Output encoding, sun and moon layers
+    uvec4 params;
+} sky;
+
+layout(location = 0) in vec3 fragDir;
+layout(location = 1) in vec3 fragUv;
+
+layout(set = 0, binding = 0) uniform texture2DArray skyTextures;
+layout(set = 0, binding = 1) uniform sampler skySampler;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+vec3 encodePq(vec3 linear) {
+    // Rec.709 to Rec.2020 primaries
+    mat3 toRec2020 = mat3(
+        0.6274, 0.0691, 0.0164,
+        0.3293, 0.9195, 0.0880,
+        0.0433, 0.0114, 0.8956
+    );
+    vec3 y = clamp(toRec2020 * linear * (HDR_WHITE / 10000.0), 0.0, 1.0);
+
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 ym = pow(y, vec3(m1));
+    return pow((c1 + c2 * ym) / (1.0 + c3 * ym), vec3(m2));
+}
+
+vec3 encode(vec3 linear) {
+    if (sky.params.x == ENCODING_SRGB) {
+        return encodeSrgb(linear);
+    }
+    if (sky.params.x == ENCODING_PQ) {
+        return encodePq(linear);
+    }
+    return linear;
+}
+
+// Rotates `v` around the unit vector `axis`
+vec3 rotate(vec3 v, vec3 axis, float angle) {
+    float s = sin(angle);
+    float c = cos(angle);
+    return v * c + cross(axis, v) * s + axis * dot(axis, v) * (1.0 - c);
+}
+
+float hash(vec3 p) {
+    return fract(sin(dot(p, vec3(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// Brightness of the star field in direction `dir`, turning along with the sun
+float stars(vec3 dir) {
+    vec3 p = rotate(dir, sky.stars.xyz, -sky.stars.w) * STAR_CELLS;
+    vec3 cell = floor(p);
+    float h = hash(cell);
+    if (h > STAR_CHANCE) {
+        return 0.0;
+    }
+
+    float brightness = 0.3 + 0.7 * h / STAR_CHANCE;
+    float distance = length(p - cell - 0.5);
+    return brightness * (1.0 - smoothstep(0.1, 0.3, distance));
+}
+
+void main() {
+    vec3 dir = normalize(fragDir);
+
+    // Sun and moon set below the horizon
+    if (constants.body != BODY_DOME) {
+        vec4 color = texture(sampler2DArray(skyTextures, skySampler), fragUv);
+        float above = smoothstep(-0.05, 0.05, dir.y);
+        outColor = vec4(encode(color.rgb), color.a * above * (1.0 - sky.fog.a));
+        return;
+    }
+
+    float up = max(dir.y, 0.0);
+    vec3 color = mix(sky.horizon.rgb, sky.zenith.rgb, sqrt(up));
+    // Darker below the horizon, where the world usually hides it
+    color *= 1.0 - 0.5 * clamp(-dir.y * 4.0, 0.0, 1.0);
+
+    float towardsSun = max(dot(dir, sky.sun.xyz), 0.0);
+    color += sky.glow.rgb * pow(towardsSun, 6.0) * (1.0 - up);
+    color += vec3(stars(dir)) * sky.zenith.a * smoothstep(0.0, 0.2, dir.y);
+    color = mix(color, sky.fog.rgb, sky.fog.a);
+
+    outColor = vec4(encode(color), 1.0);
+}
@@ -0,0 +1,58 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// See `Body`
+const uint BODY_DOME = 0;
+const uint BODY_SUN = 1;
+
+layout(push_constant) uniform Constants {
+    uint body;
+} constants;
+
+layout(set = 0, binding = 2) uniform Sky {
+    mat4 viewProj;
+    mat4 invViewProj;
+    vec4 zenith;
+    vec4 horizon;
+    vec4 glow;
+    vec4 sun;
+    vec4 moon;
+    vec4 stars;
+    vec4 fog;
+    uvec4 params;
+} sky;
+
+layout(location = 0) out vec3 fragDir;
+layout(location = 1) out vec3 fragUv;
+
+void main() {
+    if (constants.body == BODY_DOME) {
+        // Fullscreen triangle, with view directions read back from the far plane
+        uint i = uint(gl_VertexIndex);
+        vec2 p = vec2(float((i << 1u) & 2u), float(i & 2u)) * 2.0 - 1.0;
+        vec4 far = sky.invViewProj * vec4(p, 1.0, 1.0);
+        gl_Position = vec4(p, 0.0, 1.0);
+        fragDir = far.xyz / far.w;
+        fragUv = vec3(0.0);
+        return;
+    }
+
+    // Billboard of two triangles, facing the center of the sky. Its sides follow the path of
+    // the body and the axis it turns around, which are never parallel to its direction.
+    bool sun = constants.body == BODY_SUN;
+    vec4 body = sun ? sky.sun : sky.moon;
+    uint i = uint(gl_VertexIndex);
+    vec2 corner = vec2(
+        i == 1u || i == 2u || i == 4u ? 1.0 : -1.0,
+        i == 2u || i == 4u || i == 5u ? 1.0 : -1.0
+    );
+    vec3 axis = sky.stars.xyz;
+    vec3 path = cross(axis, body.xyz);
+    vec3 pos = body.xyz + (path * corner.x + axis * corner.y) * body.w;
+
+    // A direction at infinity, kept in front of the far plane
+    vec4 clip = sky.viewProj * vec4(pos, 0.0);
+    gl_Position = vec4(clip.xy, 0.0, clip.w);
+    fragDir = pos;
+    fragUv = vec3(corner * 0.5 + 0.5, float(sun ? sky.params.y : sky.params.z));
+}
@@ -0,0 +1,68 @@
+//! # Watcher
+//!
+//! Recompiles shaders in `src/shaders` as they change so pipelines can be rebuilt without
+//! restarting the game. Only available in debug builds, which run from the source tree.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use super::compile;
+
+const SHADERS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        let (tx, events) = mpsc::channel();
+
+        let mut watcher = notify::watcher(tx, DEBOUNCE).unwrap();
+        watcher
+            .watch(SHADERS_DIR, RecursiveMode::NonRecursive)
+            .expect("failed to watch shaders");
+
+        Self {
+            _watcher: watcher,
+            events,
+        }
+    }
+
+    /// Recompiles shaders changed since the last poll. Returns file names, e.g. `block.frag`,
+    /// with SPIR-V of the shaders that compiled. Errors are printed, keeping the old shader.
+    pub fn poll(&self) -> Vec<(String, Vec<u32>)> {
+        let changed: BTreeSet<_> = self
+            .events
+            .try_iter()
+            .filter_map(|event| match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => Some(path),
+                _ => None,
+            })
+            .filter(|path| compile::is_shader(path))
+            .collect();
+
+        changed
+            .into_iter()
+            .filter_map(|path| match compile::compile(&path) {
+                Ok(code) => Some((file_name(&path), code)),
+                Err(e) => {
+                    log::error!("{}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
@@ -1,3 +1,249 @@
-pub struct Block {
-    // ...
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Block {
+    Air,
+    Stone,
+    Dirt,
+    Grass,
+    Sand,
+    Water,
+    Lava,
+    StainedGlass,
+    Ice,
+    Log,
+    Leaves,
+    TallGrass,
+    Poppy,
+    StoneSlab,
+    /// Stairs rising towards the given side.
+    StoneStairs(Face),
+    OakFence,
+    Torch,
+}
+
+/// Shape of a block's mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// A full cube with a texture per face.
+    Cube,
+    /// Two diagonal quads crossing in the middle of the block, like plants.
+    Cross,
+    /// Cuboid elements of the model `name` in `assets/models/`, turned clockwise around the Y
+    /// axis by `rotation` degrees.
+    Elements { name: &'static str, rotation: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+    Top,
+    Bottom,
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::Top,
+        Face::Bottom,
+        Face::North,
+        Face::South,
+        Face::East,
+        Face::West,
+    ];
+
+    pub fn opposite(self) -> Face {
+        match self {
+            Face::Top => Face::Bottom,
+            Face::Bottom => Face::Top,
+            Face::North => Face::South,
+            Face::South => Face::North,
+            Face::East => Face::West,
+            Face::West => Face::East,
+        }
+    }
+
+    /// Unit vector pointing out of the face, +Y is up, north is -Z and east is +X.
+    pub fn normal(self) -> [i32; 3] {
+        match self {
+            Face::Top => [0, 1, 0],
+            Face::Bottom => [0, -1, 0],
+            Face::North => [0, 0, -1],
+            Face::South => [0, 0, 1],
+            Face::East => [1, 0, 0],
+            Face::West => [-1, 0, 0],
+        }
+    }
+}
+
+impl Block {
+    /// Every block, stairs once for each side they can rise towards.
+    pub fn all() -> impl Iterator<Item = Block> {
+        const BLOCKS: [Block; 16] = [
+            Block::Air,
+            Block::Stone,
+            Block::Dirt,
+            Block::Grass,
+            Block::Sand,
+            Block::Water,
+            Block::Lava,
+            Block::StainedGlass,
+            Block::Ice,
+            Block::Log,
+            Block::Leaves,
+            Block::TallGrass,
+            Block::Poppy,
+            Block::StoneSlab,
+            Block::OakFence,
+            Block::Torch,
+        ];
+        let stairs = Face::ALL.iter().map(|&face| Block::StoneStairs(face));
+        IntoIterator::into_iter(BLOCKS).chain(stairs)
+    }
+
+    /// Whether the block fully hides faces behind it and darkens corners next to it.
+    pub fn is_opaque(self) -> bool {
+        self != Block::Air
+            && self.model() == Model::Cube
+            && !self.is_translucent()
+            && !self.is_cutout()
+    }
+
+    /// Whether the block's texture has fully transparent holes, drawn alpha tested.
+    pub fn is_cutout(self) -> bool {
+        matches!(
+            self,
+            Block::Leaves | Block::TallGrass | Block::Poppy | Block::Torch
+        )
+    }
+
+    pub fn model(self) -> Model {
+        match self {
+            Block::TallGrass | Block::Poppy => Model::Cross,
+            Block::StoneSlab => Model::Elements {
+                name: "stone_slab",
+                rotation: 0,
+            },
+            // The model rises towards the east
+            Block::StoneStairs(facing) => Model::Elements {
+                name: "stone_stairs",
+                rotation: match facing {
+                    Face::South => 90,
+                    Face::West => 180,
+                    Face::North => 270,
+                    _ => 0,
+                },
+            },
+            Block::OakFence => Model::Elements {
+                name: "oak_fence_post",
+                rotation: 0,
+            },
+            Block::Torch => Model::Elements {
+                name: "torch",
+                rotation: 0,
+            },
+            _ => Model::Cube,
+        }
+    }
+
+    /// Whether the block is a liquid, which things sink into instead of resting on.
+    pub fn is_fluid(self) -> bool {
+        matches!(self, Block::Water | Block::Lava)
+    }
+
+    /// Whether things can't pass through the block, treating its whole cell as taken. Plants and
+    /// torches can be walked through.
+    pub fn is_solid(self) -> bool {
+        self != Block::Air
+            && !self.is_fluid()
+            && self.model() != Model::Cross
+            && self != Block::Torch
+    }
+
+    /// Boxes making up the block's shape as `(min, max)` corners across `0.0..1.0`, outlined when
+    /// targeted and what rays hit. Empty for air and fluids, which can't be targeted.
+    pub fn outline(self) -> Vec<([f32; 3], [f32; 3])> {
+        // In sixteenths of a block like model elements
+        let boxes: &[([u8; 3], [u8; 3])] = match self {
+            Block::Air | Block::Water | Block::Lava => &[],
+            Block::TallGrass | Block::Poppy => &[([2, 0, 2], [14, 13, 14])],
+            Block::StoneSlab => &[([0, 0, 0], [16, 8, 16])],
+            // The step is on the east side, like the model
+            Block::StoneStairs(_) => &[([0, 0, 0], [16, 8, 16]), ([8, 8, 0], [16, 16, 16])],
+            Block::OakFence => &[([6, 0, 6], [10, 16, 10])],
+            Block::Torch => &[([6, 0, 6], [10, 10, 10])],
+            _ => &[([0, 0, 0], [16, 16, 16])],
+        };
+        let rotation = match self.model() {
+            Model::Elements { rotation, .. } => rotation,
+            _ => 0,
+        };
+
+        boxes
+            .iter()
+            .map(|&(min, max)| {
+                let (min, max) = (min.map(|c| c as f32 / 16.0), max.map(|c| c as f32 / 16.0));
+                // Clockwise seen from above, east turning to south
+                let (mut a, mut b) = (min, max);
+                for _ in 0..rotation / 90 {
+                    a = [1.0 - a[2], a[1], a[0]];
+                    b = [1.0 - b[2], b[1], b[0]];
+                }
+                let min = [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])];
+                let max = [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])];
+                (min, max)
+            })
+            .collect()
+    }
+
+    /// Seconds it takes to break the block, zero for ones breaking at once and infinite for ones
+    /// that can't be broken.
+    pub fn break_time(self) -> f32 {
+        match self {
+            Block::TallGrass | Block::Poppy | Block::Torch => 0.0,
+            Block::Leaves => 0.3,
+            Block::StainedGlass => 0.45,
+            Block::Dirt | Block::Sand | Block::Ice => 0.75,
+            Block::Grass => 0.9,
+            Block::Stone | Block::StoneSlab | Block::StoneStairs(_) => 1.5,
+            Block::Log | Block::OakFence => 2.0,
+            Block::Air | Block::Water | Block::Lava => f32::INFINITY,
+        }
+    }
+
+    /// Whether the block is partially see-through and drawn blended, after opaque blocks.
+    pub fn is_translucent(self) -> bool {
+        matches!(self, Block::Water | Block::StainedGlass | Block::Ice)
+    }
+
+    /// Name of the texture in `assets/textures/` used by the given face, `None` for air. Cross
+    /// models use their `Face::North` texture, element models name theirs in the model file and
+    /// this is just the texture that represents them.
+    pub fn texture(self, face: Face) -> Option<&'static str> {
+        match self {
+            Block::Air => None,
+            Block::Stone => Some("stone"),
+            Block::Dirt => Some("dirt"),
+            Block::Grass => match face {
+                Face::Top => Some("grass_top"),
+                Face::Bottom => Some("dirt"),
+                _ => Some("grass_side"),
+            },
+            Block::Sand => Some("sand"),
+            Block::Water => Some("water_still"),
+            Block::Lava => Some("lava_still"),
+            Block::StainedGlass => Some("stained_glass"),
+            Block::Ice => Some("ice"),
+            Block::Log => match face {
+                Face::Top | Face::Bottom => Some("log_top"),
+                _ => Some("log_side"),
+            },
+            Block::Leaves => Some("leaves"),
+            Block::TallGrass => Some("tall_grass"),
+            Block::Poppy => Some("poppy"),
+            Block::StoneSlab | Block::StoneStairs(_) => Some("stone"),
+            Block::OakFence => Some("planks"),
+            Block::Torch => Some("torch"),
+        }
+    }
 }
@@ -0,0 +1,106 @@
+//! # Breaking
+//!
+//! Breaking the targeted block by holding the button on it for its `Block::break_time`.
+//! Looking away, targeting another block or letting go starts over.
+
+use std::time::Duration;
+
+use cgmath::Point3;
+
+use super::{Block, Hit};
+
+#[derive(Debug, Clone, Default)]
+pub struct Breaking {
+    /// The block being broken.
+    target: Option<(Point3<i32>, Block)>,
+    /// Seconds held on the target.
+    elapsed: f32,
+}
+
+impl Breaking {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances breaking by `dt` with `target` under the crosshair and the button `held` down.
+    /// Returns the hit that broke, to be removed from the world.
+    pub fn update(&mut self, target: Option<Hit>, held: bool, dt: Duration) -> Option<Hit> {
+        let target = target.filter(|_| held);
+        let key = target.map(|hit| (hit.pos, hit.block));
+        if key != self.target {
+            self.target = key;
+            self.elapsed = 0.0;
+        }
+
+        let hit = target?;
+        self.elapsed += dt.as_secs_f32();
+        if self.elapsed >= hit.block.break_time() {
+            self.target = None;
+            self.elapsed = 0.0;
+            return Some(hit);
+        }
+        None
+    }
+
+    /// Position of the block being broken and how far along, from `0.0` up to `1.0`.
+    pub fn progress(&self) -> Option<(Point3<i32>, f32)> {
+        self.target
+            .map(|(pos, block)| (pos, (self.elapsed / block.break_time()).min(1.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::Face;
+
+    fn hit(x: i32, block: Block) -> Option<Hit> {
+        Some(Hit {
+            pos: Point3::new(x, 0, 0),
+            block,
+            face: Face::Top,
+            distance: 1.0,
+        })
+    }
+
+    #[test]
+    fn breaks_after_the_break_time() {
+        let mut breaking = Breaking::new();
+        let tick = Duration::from_millis(500);
+
+        assert_eq!(breaking.update(hit(0, Block::Stone), true, tick), None);
+        let (pos, progress) = breaking.progress().unwrap();
+        assert_eq!(pos, Point3::new(0, 0, 0));
+        assert!((progress - 0.5 / Block::Stone.break_time()).abs() < 1e-5);
+
+        assert_eq!(breaking.update(hit(0, Block::Stone), true, tick), None);
+        assert_eq!(
+            breaking.update(hit(0, Block::Stone), true, tick),
+            hit(0, Block::Stone)
+        );
+        assert_eq!(breaking.progress(), None);
+
+        // Plants go at once
+        assert_eq!(
+            breaking.update(hit(0, Block::Poppy), true, Duration::ZERO),
+            hit(0, Block::Poppy)
+        );
+    }
+
+    #[test]
+    fn starts_over_when_interrupted() {
+        let mut breaking = Breaking::new();
+        let tick = Duration::from_millis(1000);
+
+        breaking.update(hit(0, Block::Stone), true, tick);
+        breaking.update(hit(1, Block::Stone), true, tick);
+        assert_eq!(breaking.progress().map(|(pos, _)| pos.x), Some(1));
+        assert!(breaking.progress().unwrap().1 < 1.0);
+
+        breaking.update(hit(1, Block::Stone), false, tick);
+        assert_eq!(breaking.progress(), None);
+        assert_eq!(breaking.update(hit(1, Block::Stone), true, tick), None);
+        assert_eq!(breaking.update(None, true, tick), None);
+        assert_eq!(breaking.progress(), None);
+    }
+}
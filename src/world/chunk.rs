@@ -0,0 +1,45 @@
+use cgmath::Point3;
+
+use super::Block;
+
+/// Edge length of a cubic chunk in blocks.
+pub const CHUNK_SIZE: i32 = 16;
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Chunk coordinates, a block at `p` lives in chunk `p / CHUNK_SIZE` rounded down.
+pub type ChunkPos = Point3<i32>;
+
+/// A 16³ section of blocks, indexed by local coordinates in `0..CHUNK_SIZE`.
+#[derive(Clone)]
+pub struct Chunk {
+    blocks: Box<[Block]>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![Block::Air; CHUNK_VOLUME].into_boxed_slice(),
+        }
+    }
+
+    pub fn block(&self, x: i32, y: i32, z: i32) -> Block {
+        self.blocks[index(x, y, z)]
+    }
+
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) {
+        self.blocks[index(x, y, z)] = block;
+    }
+}
+
+fn index(x: i32, y: i32, z: i32) -> usize {
+    debug_assert!(
+        (0..CHUNK_SIZE).contains(&x)
+            && (0..CHUNK_SIZE).contains(&y)
+            && (0..CHUNK_SIZE).contains(&z),
+        "local block position ({}, {}, {}) out of chunk",
+        x,
+        y,
+        z
+    );
+    ((y * CHUNK_SIZE + z) * CHUNK_SIZE + x) as usize
+}
@@ -1 +1,18 @@
 mod block;
+mod breaking;
+mod chunk;
+mod particles;
+mod raycast;
+mod time;
+mod visibility;
+#[allow(clippy::module_inception)]
+mod world;
+
+pub use block::{Block, Face, Model};
+pub use breaking::Breaking;
+pub use chunk::{ChunkPos, CHUNK_SIZE};
+pub use particles::{Particle, ParticleSystem, Sprite, MAX_PARTICLES};
+pub use raycast::{raycast, Hit};
+pub use time::Time;
+pub use visibility::{visible_chunks, Connectivity};
+pub use world::World;
@@ -0,0 +1,482 @@
+//! # Particles
+//!
+//! Small sprites simulated on the CPU: debris of broken blocks, smoke rising from torches and
+//! rain with the splashes it makes where it lands. Particles fall with gravity and collide with
+//! solid blocks as points. They live in a pool allocated up front and recycled as they die, and
+//! spawning is capped both in total and per update, so bursts can't stall a frame.
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use cgmath::{Point3, Vector3};
+
+use super::{Block, Face, World};
+
+/// Particles alive at most, further ones aren't spawned.
+pub const MAX_PARTICLES: usize = 4096;
+/// Particles spawned at most between two updates, the rest of a burst is dropped.
+pub const MAX_SPAWNS: usize = 256;
+
+/// Downwards acceleration, in blocks per second squared.
+const GRAVITY: f32 = 16.0;
+/// Updates longer than this are simulated as this long, e.g. after a hitch.
+const MAX_STEP: f32 = 0.1;
+
+/// Debris spawned per axis of a broken block.
+const DEBRIS_GRID: usize = 4;
+/// Part of a block's texture each bit of debris shows, along each side.
+const DEBRIS_UV: f32 = 0.25;
+
+/// Smoke puffs per second of a torch, and how high above the torch's cell its flame is.
+const SMOKE_RATE: f32 = 2.0;
+const FLAME_HEIGHT: f32 = 0.7;
+
+/// Drops per second, spawned this far around and this high above the camera.
+const RAIN_RATE: f32 = 300.0;
+const RAIN_RADIUS: f32 = 12.0;
+const RAIN_HEIGHT: f32 = 12.0;
+const RAIN_SPEED: f32 = 14.0;
+/// Droplets a landing drop splashes into.
+const SPLASH_DROPLETS: usize = 2;
+
+/// What a particle looks like, which decides how it moves too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sprite {
+    /// A bit of a broken block, showing part of its texture.
+    Debris(Block),
+    /// A puff of smoke, shrinking as it ages.
+    Smoke,
+    /// A falling rain drop, splashing where it lands.
+    Rain,
+    /// A droplet of a splash.
+    Splash,
+}
+
+impl Sprite {
+    /// Multiple of gravity pulling the particle down, smoke is lighter than air.
+    fn gravity(self) -> f32 {
+        match self {
+            Sprite::Smoke => -0.05,
+            _ => 1.0,
+        }
+    }
+
+    /// Fraction of velocity lost per second in the air.
+    fn drag(self) -> f32 {
+        match self {
+            Sprite::Smoke => 1.5,
+            Sprite::Rain => 0.0,
+            _ => 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub position: Point3<f32>,
+    pub velocity: Vector3<f32>,
+    pub sprite: Sprite,
+    /// Corner of the part of the sprite's texture shown and its size, in UV.
+    pub uv: [f32; 4],
+    /// Width and height, in blocks.
+    pub size: [f32; 2],
+    /// Seconds since spawning, and how long until it dies.
+    pub age: f32,
+    pub lifetime: f32,
+    on_ground: bool,
+}
+
+impl Particle {
+    fn new(sprite: Sprite, position: Point3<f32>, velocity: Vector3<f32>, size: f32) -> Self {
+        Self {
+            position,
+            velocity,
+            sprite,
+            uv: [0.0, 0.0, 1.0, 1.0],
+            size: [size, size],
+            age: 0.0,
+            lifetime: 1.0,
+            on_ground: false,
+        }
+    }
+
+    /// Fraction of its life the particle lived, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        (self.age / self.lifetime).min(1.0)
+    }
+
+    /// Opacity, smoke fades out as it rises.
+    pub fn alpha(&self) -> f32 {
+        match self.sprite {
+            Sprite::Smoke => 1.0 - self.progress(),
+            _ => 1.0,
+        }
+    }
+
+    /// Moves the particle by its velocity over `dt`, one axis at a time so it slides along
+    /// blocks it hits. Returns whether it hit any.
+    fn step(&mut self, world: &World, dt: f32) -> bool {
+        let rain = self.sprite == Sprite::Rain;
+        let lands = |p: Point3<f32>| {
+            let block = world.block(p.map(|c| c.floor() as i32));
+            // Rain stops at the surface of fluids too
+            block.is_solid() || (rain && block.is_fluid())
+        };
+
+        let motion = self.velocity * dt;
+        let mut hit = false;
+        self.on_ground = false;
+        for axis in 0..3 {
+            let mut next = self.position;
+            next[axis] += motion[axis];
+            if lands(next) {
+                hit = true;
+                self.on_ground |= axis == 1 && motion.y < 0.0;
+                self.velocity[axis] = 0.0;
+            } else {
+                self.position = next;
+            }
+        }
+        hit
+    }
+}
+
+/// A source spawning particles over time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emitter {
+    /// Smoke rising from the flame of a torch at the given block.
+    Smoke(Point3<i32>),
+    /// Rain falling around the camera.
+    Rain,
+}
+
+impl Emitter {
+    /// Particles spawned per second.
+    fn rate(self) -> f32 {
+        match self {
+            Emitter::Smoke(_) => SMOKE_RATE,
+            Emitter::Rain => RAIN_RATE,
+        }
+    }
+}
+
+pub struct ParticleSystem {
+    /// Live particles, in no particular order. Dead ones are swapped out, so the pool never
+    /// reallocates.
+    particles: Vec<Particle>,
+    /// Emitters, with the fraction of a particle each owes from previous updates.
+    emitters: Vec<(Emitter, f32)>,
+    /// Particles spawned since the last update.
+    spawned: usize,
+    rng: Rng,
+}
+
+impl Default for ParticleSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        Self {
+            particles: Vec::with_capacity(MAX_PARTICLES),
+            emitters: Vec::new(),
+            spawned: 0,
+            rng: Rng(0x9e37_79b9),
+        }
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn add_emitter(&mut self, emitter: Emitter) {
+        self.emitters.push((emitter, 0.0));
+    }
+
+    pub fn remove_emitter(&mut self, emitter: Emitter) {
+        self.emitters.retain(|&(e, _)| e != emitter);
+    }
+
+    /// Adds smoke to every torch of `world`, replacing previous smoke emitters.
+    pub fn add_torches(&mut self, world: &World) {
+        self.emitters
+            .retain(|(emitter, _)| !matches!(emitter, Emitter::Smoke(_)));
+        for pos in world.find(Block::Torch) {
+            self.add_emitter(Emitter::Smoke(pos));
+        }
+    }
+
+    /// Starts or stops the rain.
+    pub fn set_rain(&mut self, rain: bool) {
+        self.remove_emitter(Emitter::Rain);
+        if rain {
+            self.add_emitter(Emitter::Rain);
+        }
+    }
+
+    /// Bursts `block` at `pos` into debris, call after removing it from the world. A torch's
+    /// smoke stops too.
+    pub fn break_block(&mut self, pos: Point3<i32>, block: Block) {
+        self.remove_emitter(Emitter::Smoke(pos));
+        if block.texture(Face::North).is_none() {
+            return;
+        }
+
+        let corner = pos.map(|c| c as f32);
+        let cell = 1.0 / DEBRIS_GRID as f32;
+        for i in 0..DEBRIS_GRID.pow(3) {
+            let [x, y, z] = [
+                i % DEBRIS_GRID,
+                i / DEBRIS_GRID % DEBRIS_GRID,
+                i / DEBRIS_GRID.pow(2),
+            ]
+            .map(|c| (c as f32 + 0.5) * cell);
+            // Flying out from the center, a little upwards
+            let velocity =
+                Vector3::new(x - 0.5, y - 0.5 + 0.6, z - 0.5) * 4.0 + self.rng.vector() * 0.5;
+
+            let mut particle = Particle::new(
+                Sprite::Debris(block),
+                corner + Vector3::new(x, y, z),
+                velocity,
+                self.rng.range(0.08, 0.16),
+            );
+            let max = 1.0 - DEBRIS_UV;
+            particle.uv = [
+                (self.rng.range(0.0, max) * 16.0).round() / 16.0,
+                (self.rng.range(0.0, max) * 16.0).round() / 16.0,
+                DEBRIS_UV,
+                DEBRIS_UV,
+            ];
+            particle.lifetime = self.rng.range(0.6, 1.5);
+            self.spawn(particle);
+        }
+    }
+
+    /// Adds `particle` unless the pool is full or too many spawned since the last update.
+    /// Returns whether it was added.
+    pub fn spawn(&mut self, particle: Particle) -> bool {
+        if self.particles.len() >= MAX_PARTICLES || self.spawned >= MAX_SPAWNS {
+            return false;
+        }
+        self.spawned += 1;
+        self.particles.push(particle);
+        true
+    }
+
+    /// Emits new particles and moves the others over `dt`, with rain following `eye`.
+    pub fn update(&mut self, world: &World, eye: Point3<f32>, dt: Duration) {
+        let dt = dt.as_secs_f32().min(MAX_STEP);
+        self.spawned = 0;
+
+        // === EMIT ===
+
+        for i in 0..self.emitters.len() {
+            let (emitter, owed) = self.emitters[i];
+            let owed = owed + emitter.rate() * dt;
+            let count = owed.floor();
+            self.emitters[i].1 = owed - count;
+
+            for _ in 0..count as usize {
+                let particle = self.emit(emitter, eye);
+                self.spawn(particle);
+            }
+        }
+
+        // === SIMULATE ===
+
+        let mut splashes = Vec::new();
+        let mut i = 0;
+        while i < self.particles.len() {
+            let particle = &mut self.particles[i];
+            particle.age += dt;
+
+            let sprite = particle.sprite;
+            particle.velocity.y -= GRAVITY * sprite.gravity() * dt;
+            particle.velocity *= (-sprite.drag() * dt).exp();
+            let hit = particle.step(world, dt);
+            if particle.on_ground {
+                // Sliding to a stop
+                particle.velocity.x *= (-8.0 * dt).exp();
+                particle.velocity.z *= (-8.0 * dt).exp();
+            }
+
+            let landed = hit && sprite == Sprite::Rain;
+            if landed {
+                splashes.push(particle.position);
+            }
+            if landed || particle.age >= particle.lifetime {
+                self.particles.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        for position in splashes {
+            for _ in 0..SPLASH_DROPLETS {
+                let angle = self.rng.range(0.0, 2.0 * PI);
+                let velocity = Vector3::new(angle.cos(), 0.0, angle.sin())
+                    * self.rng.range(0.5, 1.5)
+                    + Vector3::new(0.0, self.rng.range(2.0, 3.5), 0.0);
+                let mut droplet = Particle::new(Sprite::Splash, position, velocity, 0.06);
+                droplet.lifetime = self.rng.range(0.2, 0.4);
+                self.spawn(droplet);
+            }
+        }
+    }
+
+    fn emit(&mut self, emitter: Emitter, eye: Point3<f32>) -> Particle {
+        match emitter {
+            Emitter::Smoke(pos) => {
+                let flame = pos.map(|c| c as f32) + Vector3::new(0.5, FLAME_HEIGHT, 0.5);
+                let drift = self.rng.vector() * 0.05;
+                let velocity = Vector3::new(drift.x, self.rng.range(0.4, 0.7), drift.z);
+                let mut smoke =
+                    Particle::new(Sprite::Smoke, flame, velocity, self.rng.range(0.15, 0.25));
+                smoke.lifetime = self.rng.range(1.5, 2.5);
+                smoke
+            }
+            Emitter::Rain => {
+                // Spread evenly over a disc around the camera
+                let angle = self.rng.range(0.0, 2.0 * PI);
+                let radius = RAIN_RADIUS * self.rng.range(0.0, 1.0).sqrt();
+                let position =
+                    eye + Vector3::new(angle.cos() * radius, RAIN_HEIGHT, angle.sin() * radius);
+                let velocity = Vector3::new(0.0, -RAIN_SPEED, 0.0);
+                let mut drop = Particle::new(Sprite::Rain, position, velocity, 0.1);
+                drop.size = [0.05, 0.6];
+                // Falls well past the camera before giving up
+                drop.lifetime = 3.0 * RAIN_HEIGHT / RAIN_SPEED;
+                drop
+            }
+        }
+    }
+}
+
+/// Xorshift random numbers, good enough for scattering particles and deterministic in tests.
+#[derive(Debug, Clone, Copy)]
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// A value in `min..max`.
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        let t = (self.next() >> 8) as f32 / (1 << 24) as f32;
+        min + (max - min) * t
+    }
+
+    /// A vector with each component in `-1..1`.
+    fn vector(&mut self) -> Vector3<f32> {
+        Vector3::new(
+            self.range(-1.0, 1.0),
+            self.range(-1.0, 1.0),
+            self.range(-1.0, 1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stone floor with its top at y = 1.
+    fn floor() -> World {
+        let mut world = World::new();
+        for x in -8..8 {
+            for z in -8..8 {
+                world.set_block(Point3::new(x, 0, z), Block::Stone);
+            }
+        }
+        world
+    }
+
+    fn run(system: &mut ParticleSystem, world: &World, seconds: f32) {
+        let eye = Point3::new(0.0, 2.0, 0.0);
+        for _ in 0..(seconds * 60.0) as usize {
+            system.update(world, eye, Duration::from_secs_f32(1.0 / 60.0));
+        }
+    }
+
+    #[test]
+    fn debris_lands_on_blocks() {
+        let world = floor();
+        let mut system = ParticleSystem::new();
+        system.break_block(Point3::new(0, 1, 0), Block::Dirt);
+        assert_eq!(system.particles().len(), DEBRIS_GRID.pow(3));
+        assert!(system.particles().iter().all(|p| p.uv[2] == DEBRIS_UV));
+
+        run(&mut system, &world, 0.5);
+        let particles = system.particles();
+        assert!(!particles.is_empty());
+        assert!(particles.iter().all(|p| p.position.y >= 1.0));
+        assert!(particles.iter().any(|p| p.on_ground));
+
+        run(&mut system, &world, 2.0);
+        assert!(system.particles().is_empty(), "debris dies");
+    }
+
+    #[test]
+    fn rain_splashes_and_smoke_rises() {
+        let mut world = floor();
+        world.set_block(Point3::new(4, 1, 4), Block::Torch);
+        let mut system = ParticleSystem::new();
+        system.add_torches(&world);
+        system.set_rain(true);
+
+        run(&mut system, &world, 1.5);
+        let count = |system: &ParticleSystem, sprite| {
+            system
+                .particles()
+                .iter()
+                .filter(|p| p.sprite == sprite)
+                .count()
+        };
+        assert!(count(&system, Sprite::Rain) > 0);
+        assert!(
+            count(&system, Sprite::Splash) > 0,
+            "drops reached the floor"
+        );
+        assert!(system
+            .particles()
+            .iter()
+            .filter(|p| p.sprite == Sprite::Smoke)
+            .all(|p| p.position.y > 1.0 + FLAME_HEIGHT));
+
+        system.set_rain(false);
+        run(&mut system, &world, 3.0);
+        assert_eq!(
+            count(&system, Sprite::Rain) + count(&system, Sprite::Splash),
+            0
+        );
+    }
+
+    #[test]
+    fn spawns_are_capped() {
+        let mut system = ParticleSystem::new();
+        for _ in 0..10 {
+            system.break_block(Point3::new(0, 10, 0), Block::Stone);
+        }
+        assert_eq!(system.particles().len(), MAX_SPAWNS);
+
+        // The pool fills up over a few updates and never grows
+        let world = World::new();
+        for _ in 0..MAX_PARTICLES / MAX_SPAWNS + 1 {
+            system.update(&world, Point3::new(0.0, 0.0, 0.0), Duration::ZERO);
+            for _ in 0..10 {
+                system.break_block(Point3::new(0, 10, 0), Block::Stone);
+            }
+        }
+        assert_eq!(system.particles().len(), MAX_PARTICLES);
+        assert_eq!(system.particles.capacity(), MAX_PARTICLES);
+    }
+}
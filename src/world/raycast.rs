@@ -0,0 +1,188 @@
+//! # Raycast
+//!
+//! Finds the block a ray hits first, like the one under the crosshair. The ray walks the grid
+//! cell by cell in the order it crosses them, after Amanatides and Woo, and in each cell tests
+//! the boxes of the block's outline, so it passes over the empty half of a slab or beside a
+//! torch to whatever is behind.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use super::{Block, Face, World};
+
+/// A block hit by a ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub pos: Point3<i32>,
+    pub block: Block,
+    /// Face of the outline box the ray entered through.
+    pub face: Face,
+    /// Along the ray from its origin, in blocks.
+    pub distance: f32,
+}
+
+/// The first block along the ray from `origin` towards `direction` within `reach` blocks. Blocks
+/// the origin is inside of are passed through.
+pub fn raycast(
+    world: &World,
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    reach: f32,
+) -> Option<Hit> {
+    let direction = direction.normalize();
+    let mut cell = origin.map(|c| c.floor() as i32);
+
+    // Per axis the direction stepped in, the distance to the next cell boundary and the
+    // distance between boundaries
+    let mut step = [0; 3];
+    let mut next = [f32::INFINITY; 3];
+    let mut delta = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        let d = direction[axis];
+        if d == 0.0 {
+            continue;
+        }
+        step[axis] = if d > 0.0 { 1 } else { -1 };
+        let boundary = cell[axis] as f32 + if d > 0.0 { 1.0 } else { 0.0 };
+        next[axis] = (boundary - origin[axis]) / d;
+        delta[axis] = 1.0 / d.abs();
+    }
+
+    let mut distance = 0.0;
+    while distance <= reach {
+        let block = world.block(cell);
+        let corner = cell.map(|c| c as f32);
+        let hit = block
+            .outline()
+            .into_iter()
+            .filter_map(|(min, max)| {
+                let min = corner + Vector3::from(min);
+                let max = corner + Vector3::from(max);
+                intersect(origin, direction, min, max)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((distance, face)) = hit.filter(|&(distance, _)| distance <= reach) {
+            return Some(Hit {
+                pos: cell,
+                block,
+                face,
+                distance,
+            });
+        }
+
+        let axis = (0..3).min_by(|&a, &b| next[a].total_cmp(&next[b])).unwrap();
+        distance = next[axis];
+        cell[axis] += step[axis];
+        next[axis] += delta[axis];
+    }
+    None
+}
+
+/// Distance along the ray to where it enters the box from `min` to `max` and the face it enters
+/// through, `None` if it misses or starts inside.
+fn intersect(
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    min: Point3<f32>,
+    max: Point3<f32>,
+) -> Option<(f32, Face)> {
+    const FACES: [(Face, Face); 3] = [
+        (Face::West, Face::East),
+        (Face::Bottom, Face::Top),
+        (Face::North, Face::South),
+    ];
+
+    let mut enter = (f32::NEG_INFINITY, Face::Top);
+    let mut exit = f32::INFINITY;
+    for axis in 0..3 {
+        let d = direction[axis];
+        if d == 0.0 {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let (near, far, face) = if d > 0.0 {
+            (min[axis], max[axis], FACES[axis].0)
+        } else {
+            (max[axis], min[axis], FACES[axis].1)
+        };
+        let (t_near, t_far) = ((near - origin[axis]) / d, (far - origin[axis]) / d);
+        if t_near > enter.0 {
+            enter = (t_near, face);
+        }
+        exit = exit.min(t_far);
+    }
+
+    (enter.0 >= 0.0 && enter.0 <= exit).then_some(enter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(blocks: &[(Point3<i32>, Block)]) -> World {
+        let mut world = World::new();
+        for &(pos, block) in blocks {
+            world.set_block(pos, block);
+        }
+        world
+    }
+
+    #[test]
+    fn hits_the_first_block_and_its_face() {
+        let world = world(&[
+            (Point3::new(3, 0, 0), Block::Stone),
+            (Point3::new(5, 0, 0), Block::Dirt),
+        ]);
+        let origin = Point3::new(0.5, 0.5, 0.5);
+
+        let hit = raycast(&world, origin, Vector3::unit_x(), 8.0).unwrap();
+        assert_eq!(hit.pos, Point3::new(3, 0, 0));
+        assert_eq!(hit.block, Block::Stone);
+        assert_eq!(hit.face, Face::West);
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+
+        let down = raycast(&world, Point3::new(3.5, 4.0, 0.5), -Vector3::unit_y(), 8.0);
+        assert_eq!(down.map(|hit| hit.face), Some(Face::Top));
+
+        assert_eq!(raycast(&world, origin, Vector3::unit_x(), 2.0), None);
+        assert_eq!(raycast(&world, origin, -Vector3::unit_x(), 8.0), None);
+    }
+
+    #[test]
+    fn follows_outline_shapes() {
+        let world = world(&[
+            (Point3::new(2, 0, 0), Block::StoneSlab),
+            (Point3::new(4, 0, 0), Block::Stone),
+            (Point3::new(0, 0, 2), Block::Water),
+            (Point3::new(0, 0, 4), Block::Torch),
+        ]);
+
+        // Over the slab's empty top half to the stone behind
+        let high = raycast(&world, Point3::new(0.5, 0.75, 0.5), Vector3::unit_x(), 8.0);
+        assert_eq!(high.map(|hit| hit.block), Some(Block::Stone));
+        let low = raycast(&world, Point3::new(0.5, 0.25, 0.5), Vector3::unit_x(), 8.0);
+        assert_eq!(low.map(|hit| hit.block), Some(Block::StoneSlab));
+
+        // Through water, and past the side of a torch but not into its stick
+        let torch = raycast(&world, Point3::new(0.5, 0.25, 0.5), Vector3::unit_z(), 8.0);
+        assert_eq!(torch.map(|hit| hit.block), Some(Block::Torch));
+        let beside = raycast(&world, Point3::new(0.2, 0.25, 0.5), Vector3::unit_z(), 8.0);
+        assert_eq!(beside, None);
+    }
+
+    #[test]
+    fn passes_through_the_block_it_starts_in() {
+        let world = world(&[
+            (Point3::new(0, 0, 0), Block::Stone),
+            (Point3::new(-2, 0, 0), Block::Sand),
+        ]);
+
+        let hit = raycast(&world, Point3::new(0.5, 0.5, 0.5), -Vector3::unit_x(), 8.0);
+        assert_eq!(
+            hit.map(|hit| (hit.pos, hit.face)),
+            Some((Point3::new(-2, 0, 0), Face::East))
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! # Time
+//!
+//! Time of day, advanced by the game tick. Days start at sunrise, the sun rises in the east and
+//! crosses the sky tilted towards the south, with the moon opposite of it. The moon goes through
+//! its phases once every `MOON_PHASES` days, starting full.
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Vector3};
+
+pub const DAY_LENGTH: Duration = Duration::from_secs(20 * 60);
+pub const MOON_PHASES: u32 = 8;
+
+/// Sky light at night, so nights are dark but not black.
+const NIGHT_LIGHT: f32 = 0.2;
+/// How far the sun's path leans south, in radians.
+const TILT: f32 = 0.35;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    /// Since sunrise of the first day.
+    elapsed: Duration,
+}
+
+impl Default for Time {
+    /// Noon of the first day.
+    fn default() -> Self {
+        Self::new(DAY_LENGTH / 4)
+    }
+}
+
+impl Time {
+    pub fn new(elapsed: Duration) -> Self {
+        Self { elapsed }
+    }
+
+    /// The first day at `fraction` of it since sunrise, see `time_of_day`.
+    #[cfg(test)]
+    pub fn of_day(fraction: f32) -> Self {
+        Self::new(DAY_LENGTH.mul_f32(fraction))
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Number of days passed.
+    pub fn day(&self) -> u64 {
+        (self.elapsed.as_millis() / DAY_LENGTH.as_millis()) as u64
+    }
+
+    /// Fraction of the day since sunrise, 0.25 is noon, 0.5 sunset and 0.75 midnight.
+    pub fn time_of_day(&self) -> f32 {
+        let day = DAY_LENGTH.as_millis();
+        (self.elapsed.as_millis() % day) as f32 / day as f32
+    }
+
+    /// Axis the sun and moon rotate around, the sun's path being counter-clockwise around it.
+    pub fn sky_axis(&self) -> Vector3<f32> {
+        Vector3::new(0.0, -TILT.sin(), TILT.cos())
+    }
+
+    /// Angle the sky turned since sunrise, in radians.
+    pub fn sky_angle(&self) -> f32 {
+        self.time_of_day() * 2.0 * PI
+    }
+
+    /// Unit vector towards the sun.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let (sin, cos) = self.sky_angle().sin_cos();
+        Vector3::new(cos, sin * TILT.cos(), sin * TILT.sin()).normalize()
+    }
+
+    pub fn moon_direction(&self) -> Vector3<f32> {
+        -self.sun_direction()
+    }
+
+    /// Phase of the moon, 0 is full and `MOON_PHASES / 2` new.
+    pub fn moon_phase(&self) -> u32 {
+        (self.day() % MOON_PHASES as u64) as u32
+    }
+
+    /// How much the sun lights the world, 0 at night and 1 during the day, blending smoothly
+    /// while it's near the horizon.
+    pub fn daylight(&self) -> f32 {
+        let t = ((self.sun_direction().y + 0.15) / 0.3).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Brightness of sky light on blocks.
+    pub fn sky_light(&self) -> f32 {
+        NIGHT_LIGHT + (1.0 - NIGHT_LIGHT) * self.daylight()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_crosses_the_sky() {
+        let sunrise = Time::of_day(0.0).sun_direction();
+        assert!(sunrise.x > 0.99 && sunrise.y.abs() < 1e-4);
+
+        let noon = Time::default();
+        assert!(noon.sun_direction().y > 0.9);
+        assert!(noon.sun_direction().z > 0.0, "leans south");
+        assert_eq!(noon.sky_light(), 1.0);
+
+        let midnight = Time::of_day(0.75);
+        assert!(midnight.moon_direction().y > 0.9);
+        assert_eq!(midnight.sky_light(), NIGHT_LIGHT);
+
+        // Dusk is in between
+        let dusk = Time::of_day(0.5).sky_light();
+        assert!(dusk > NIGHT_LIGHT && dusk < 1.0);
+    }
+
+    #[test]
+    fn moon_phases_follow_days() {
+        let mut time = Time::default();
+        assert_eq!((time.day(), time.moon_phase()), (0, 0));
+
+        time.advance(DAY_LENGTH * 3);
+        assert_eq!((time.day(), time.moon_phase()), (3, 3));
+        time.advance(DAY_LENGTH * 5);
+        assert_eq!(time.moon_phase(), 0);
+        assert!((time.time_of_day() - 0.25).abs() < 1e-6);
+    }
+}
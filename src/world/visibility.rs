@@ -0,0 +1,231 @@
+//! # Visibility
+//!
+//! Cave culling, after the approach Minecraft uses for sections. Each chunk records which pairs
+//! of its faces are connected through non-opaque blocks. A breadth-first walk from the camera's
+//! chunk then only enters a neighbour through a face reachable from the face the walk came in
+//! by, and never turns back towards the camera. Chunks the walk can't reach are hidden behind
+//! solid ground, e.g. caves seen from the surface or the surface seen from a cave.
+
+use std::collections::{HashSet, VecDeque};
+
+use cgmath::Point3;
+
+use super::chunk::{Chunk, ChunkPos, CHUNK_SIZE};
+use super::Face;
+
+/// Which faces of a chunk can see each other, a symmetric 6×6 bit matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connectivity(u64);
+
+impl Connectivity {
+    pub const NONE: Connectivity = Connectivity(0);
+    pub const ALL: Connectivity = Connectivity((1 << 36) - 1);
+
+    pub fn compute(chunk: &Chunk) -> Self {
+        let mut visited = vec![false; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize];
+        let mut connectivity = Self::NONE;
+        let mut stack = Vec::new();
+
+        for start in positions() {
+            if visited[index(start)] || chunk.block(start.x, start.y, start.z).is_opaque() {
+                continue;
+            }
+
+            // Flood fill the open region containing `start`, collecting the faces it touches
+            let mut faces = Vec::new();
+            visited[index(start)] = true;
+            stack.push(start);
+            while let Some(pos) = stack.pop() {
+                for &face in &Face::ALL {
+                    let [dx, dy, dz] = face.normal();
+                    let next = Point3::new(pos.x + dx, pos.y + dy, pos.z + dz);
+
+                    let outside = [next.x, next.y, next.z]
+                        .iter()
+                        .any(|c| !(0..CHUNK_SIZE).contains(c));
+                    if outside {
+                        if !faces.contains(&face) {
+                            faces.push(face);
+                        }
+                    } else if !visited[index(next)]
+                        && !chunk.block(next.x, next.y, next.z).is_opaque()
+                    {
+                        visited[index(next)] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+
+            for &a in &faces {
+                for &b in &faces {
+                    connectivity.connect(a, b);
+                }
+            }
+            if connectivity == Self::ALL {
+                break;
+            }
+        }
+        connectivity
+    }
+
+    pub fn connects(self, a: Face, b: Face) -> bool {
+        self.0 & bit(a, b) != 0
+    }
+
+    fn connect(&mut self, a: Face, b: Face) {
+        self.0 |= bit(a, b) | bit(b, a);
+    }
+}
+
+fn bit(a: Face, b: Face) -> u64 {
+    1 << (face_index(a) * 6 + face_index(b))
+}
+
+fn face_index(face: Face) -> usize {
+    Face::ALL.iter().position(|&f| f == face).unwrap()
+}
+
+fn positions() -> impl Iterator<Item = Point3<i32>> {
+    (0..CHUNK_SIZE).flat_map(|y| {
+        (0..CHUNK_SIZE).flat_map(move |z| (0..CHUNK_SIZE).map(move |x| Point3::new(x, y, z)))
+    })
+}
+
+fn index(pos: Point3<i32>) -> usize {
+    ((pos.y * CHUNK_SIZE + pos.z) * CHUNK_SIZE + pos.x) as usize
+}
+
+/// Chunks potentially visible from a camera in chunk `origin`. `connectivity` returns `None` for
+/// chunks the walk shouldn't enter, e.g. outside the world, and `in_view` limits it to chunks
+/// in the view frustum.
+pub fn visible_chunks(
+    origin: ChunkPos,
+    connectivity: impl Fn(ChunkPos) -> Option<Connectivity>,
+    in_view: impl Fn(ChunkPos) -> bool,
+) -> HashSet<ChunkPos> {
+    let mut visible = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    if connectivity(origin).is_none() {
+        return visible;
+    }
+    visible.insert(origin);
+    // Chunk, the face the walk entered it through and the directions it went so far
+    queue.push_back((origin, None, Vec::new()));
+
+    while let Some((pos, entered, directions)) = queue.pop_front() {
+        let chunk_connectivity = connectivity(pos).unwrap_or(Connectivity::ALL);
+
+        for &face in &Face::ALL {
+            if directions.contains(&face.opposite()) {
+                continue;
+            }
+            if let Some(entered) = entered {
+                if !chunk_connectivity.connects(entered, face) {
+                    continue;
+                }
+            }
+
+            let [dx, dy, dz] = face.normal();
+            let next = Point3::new(pos.x + dx, pos.y + dy, pos.z + dz);
+            if visible.contains(&next) || !in_view(next) || connectivity(next).is_none() {
+                continue;
+            }
+
+            visible.insert(next);
+            let mut next_directions = directions.clone();
+            if !next_directions.contains(&face) {
+                next_directions.push(face);
+            }
+            queue.push_back((next, Some(face.opposite()), next_directions));
+        }
+    }
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::world::{Block, World};
+
+    fn connectivity_map(world: &World) -> HashMap<ChunkPos, Connectivity> {
+        world
+            .chunks()
+            .map(|(pos, chunk)| (pos, Connectivity::compute(chunk)))
+            .collect()
+    }
+
+    #[test]
+    fn floor_separates_top_from_bottom() {
+        let mut chunk = Chunk::new();
+        assert_eq!(Connectivity::compute(&chunk), Connectivity::ALL);
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set_block(x, 8, z, Block::Stone);
+            }
+        }
+        let connectivity = Connectivity::compute(&chunk);
+        assert!(!connectivity.connects(Face::Top, Face::Bottom));
+        assert!(connectivity.connects(Face::Top, Face::North));
+        assert!(connectivity.connects(Face::Bottom, Face::East));
+        assert!(connectivity.connects(Face::North, Face::South));
+
+        // Holes let it through
+        chunk.set_block(3, 8, 3, Block::Water);
+        assert_eq!(Connectivity::compute(&chunk), Connectivity::ALL);
+    }
+
+    #[test]
+    fn sealed_room_hides_the_outside() {
+        // 5×5×5 chunks of air, with a stone shell around the center chunk
+        let mut world = World::new();
+        for x in -2 * CHUNK_SIZE..3 * CHUNK_SIZE {
+            for y in -2 * CHUNK_SIZE..3 * CHUNK_SIZE {
+                for z in -2 * CHUNK_SIZE..3 * CHUNK_SIZE {
+                    let shell = [x, y, z].iter().all(|c| (-1..=CHUNK_SIZE).contains(c))
+                        && [x, y, z].iter().any(|&c| c == -1 || c == CHUNK_SIZE);
+                    let block = if shell { Block::Stone } else { Block::Air };
+                    world.set_block(Point3::new(x, y, z), block);
+                }
+            }
+        }
+        let map = connectivity_map(&world);
+        let everywhere = |_| true;
+
+        // The shell lies in the chunks around the room, whose inner faces connect to nothing
+        let inside = visible_chunks(Point3::new(0, 0, 0), |p| map.get(&p).copied(), everywhere);
+        assert!(inside.len() <= 7, "saw {} chunks", inside.len());
+        assert!(!inside.contains(&Point3::new(2, 0, 0)));
+        assert!(!inside.contains(&Point3::new(0, -2, 0)));
+
+        // From outside the room is hidden, but the open air around it is not
+        let outside = visible_chunks(Point3::new(-2, 0, 0), |p| map.get(&p).copied(), everywhere);
+        assert!(!outside.contains(&Point3::new(0, 0, 0)));
+        assert!(outside.contains(&Point3::new(2, 2, 2)));
+        assert!(outside.len() < map.len());
+    }
+
+    #[test]
+    fn stops_outside_world_and_view() {
+        let connectivity = |p: ChunkPos| {
+            if p.x.abs() <= 3 && p.y.abs() <= 3 && p.z.abs() <= 3 {
+                Some(Connectivity::ALL)
+            } else {
+                None
+            }
+        };
+
+        let all = visible_chunks(Point3::new(0, 0, 0), connectivity, |_| true);
+        assert_eq!(all.len(), 7 * 7 * 7);
+
+        // Only looking east
+        let east = visible_chunks(Point3::new(0, 0, 0), connectivity, |p| p.x >= 0);
+        assert!(east.iter().all(|p| p.x >= 0));
+        assert!(east.contains(&Point3::new(3, -3, 3)));
+
+        assert!(visible_chunks(Point3::new(9, 0, 0), connectivity, |_| true).is_empty());
+    }
+}
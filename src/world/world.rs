@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use cgmath::{Point3, Vector3};
+
+use super::chunk::{Chunk, ChunkPos, CHUNK_SIZE};
+use super::{Block, Face, Time};
+
+/// Sparse grid of chunks addressed in world block coordinates.
+pub struct World {
+    chunks: HashMap<ChunkPos, Chunk>,
+    time: Time,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            time: Time::default(),
+        }
+    }
+
+    /// Small rolling terrain with a lake, plants, trees and a few props, spanning 4×4 chunks
+    /// around the origin.
+    pub fn demo() -> Self {
+        const WATER_LEVEL: i32 = 6;
+        const TREES: [(i32, i32); 3] = [(-12, 20), (18, 22), (24, -26)];
+        // Blocks stacked on the ground
+        const PROPS: [(i32, i32, &[Block]); 4] = [
+            (-16, 14, &[Block::OakFence, Block::Torch]),
+            (-18, 12, &[Block::StoneStairs(Face::South)]),
+            (-17, 12, &[Block::StoneStairs(Face::South)]),
+            (-16, 12, &[Block::StoneSlab]),
+        ];
+
+        let mut world = Self::new();
+        for x in -32..32 {
+            for z in -32..32 {
+                let (fx, fz) = (x as f32, z as f32);
+                let height = 7.0 + 3.0 * (fx / 7.0).sin() * (fz / 9.0).cos() + (fz / 13.0).sin();
+                let height = height.round() as i32;
+                let above = Point3::new(x, height + 1, z);
+                // A small pool on a hilltop
+                let lava = (-11..=-10).contains(&x) && (28..=29).contains(&z);
+
+                if height > WATER_LEVEL && !lava && world.block(above) == Block::Air {
+                    if TREES.contains(&(x, z)) {
+                        world.tree(above);
+                    } else if let Some(&(_, _, blocks)) =
+                        PROPS.iter().find(|&&(px, pz, _)| (px, pz) == (x, z))
+                    {
+                        for (dy, &block) in blocks.iter().enumerate() {
+                            world.set_block(above + Vector3::new(0, dy as i32, 0), block);
+                        }
+                    } else if (x * 31 + z * 17).rem_euclid(13) == 0 {
+                        world.set_block(above, Block::TallGrass);
+                    } else if (x * 7 + z * 29).rem_euclid(47) == 0 {
+                        world.set_block(above, Block::Poppy);
+                    }
+                }
+
+                for y in 0..=height.max(WATER_LEVEL) {
+                    let block = if y > height {
+                        Block::Water
+                    } else if y == height && lava {
+                        Block::Lava
+                    } else if y == height && height <= WATER_LEVEL {
+                        Block::Sand
+                    } else if y == height {
+                        Block::Grass
+                    } else if y + 3 > height {
+                        Block::Dirt
+                    } else {
+                        Block::Stone
+                    };
+                    world.set_block(Point3::new(x, y, z), block);
+                }
+            }
+        }
+        world
+    }
+
+    /// A trunk with a crown of leaves, growing up from `base`.
+    fn tree(&mut self, base: Point3<i32>) {
+        const TRUNK: i32 = 5;
+
+        for dy in -2..=1 {
+            let radius: i32 = if dy < 0 { 2 } else { 1 };
+            for dx in -radius..=radius {
+                for dz in -radius..=radius {
+                    let corner = dx.abs() == radius && dz.abs() == radius;
+                    if !corner || dy == -2 {
+                        let pos = Point3::new(base.x + dx, base.y + TRUNK + dy, base.z + dz);
+                        self.set_block(pos, Block::Leaves);
+                    }
+                }
+            }
+        }
+        for dy in 0..TRUNK {
+            self.set_block(Point3::new(base.x, base.y + dy, base.z), Block::Log);
+        }
+    }
+
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Advances the world by one game tick lasting `dt`.
+    pub fn tick(&mut self, dt: Duration) {
+        self.time.advance(dt);
+    }
+
+    pub fn block(&self, pos: Point3<i32>) -> Block {
+        let (chunk, local) = split(pos);
+        self.chunks
+            .get(&chunk)
+            .map_or(Block::Air, |c| c.block(local.x, local.y, local.z))
+    }
+
+    pub fn set_block(&mut self, pos: Point3<i32>, block: Block) {
+        let (chunk, local) = split(pos);
+        self.chunks
+            .entry(chunk)
+            .or_insert_with(Chunk::new)
+            .set_block(local.x, local.y, local.z, block);
+    }
+
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (ChunkPos, &Chunk)> {
+        self.chunks.iter().map(|(&pos, chunk)| (pos, chunk))
+    }
+
+    /// World positions of every `block`, in no particular order.
+    pub fn find(&self, block: Block) -> Vec<Point3<i32>> {
+        let mut found = Vec::new();
+        for (pos, chunk) in self.chunks() {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        if chunk.block(x, y, z) == block {
+                            found.push(pos * CHUNK_SIZE + Vector3::new(x, y, z));
+                        }
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Splits a world block position into its chunk and the position within the chunk.
+fn split(pos: Point3<i32>) -> (ChunkPos, Point3<i32>) {
+    (
+        Point3::new(
+            pos.x.div_euclid(CHUNK_SIZE),
+            pos.y.div_euclid(CHUNK_SIZE),
+            pos.z.div_euclid(CHUNK_SIZE),
+        ),
+        Point3::new(
+            pos.x.rem_euclid(CHUNK_SIZE),
+            pos.y.rem_euclid(CHUNK_SIZE),
+            pos.z.rem_euclid(CHUNK_SIZE),
+        ),
+    )
+}